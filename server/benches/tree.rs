@@ -0,0 +1,180 @@
+//! Building the tree that `/api/members` returns, run with `cargo bench -p server`
+
+use std::collections::VecDeque;
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use server::{
+    api::members::models::{
+        build_tree, MarriageRowWithSpouses, MemberResponse, MemberRowWithParents,
+    },
+    Gender,
+};
+
+/// every member has this many children until there are enough members
+const CHILDREN_PER_FAMILY: usize = 4;
+
+fn member(id: i64, father_id: Option<i64>, mother_id: Option<i64>) -> MemberRowWithParents {
+    let gender = if id % 2 == 0 {
+        Gender::Male
+    } else {
+        Gender::Female
+    };
+
+    MemberRowWithParents {
+        id,
+        name: format!("member {id}"),
+        gender,
+        birthday: None,
+        death_date: None,
+        deceased: false,
+        burial_place: None,
+        last_name: String::from("family"),
+        image_hash: None,
+        mother_id,
+        father_id,
+        personal_info: None,
+        mother_name: None,
+        mother_gender: None,
+        mother_birthday: None,
+        mother_last_name: None,
+        father_name: None,
+        father_gender: None,
+        father_birthday: None,
+        father_last_name: None,
+        nasab: format!("member {id}"),
+    }
+}
+
+/// a family of `size` members where every husband has a wife from outside the tree,
+/// and their children are spread over the generations breadth first
+fn family(size: usize) -> (Vec<MemberRowWithParents>, Vec<MarriageRowWithSpouses>) {
+    let mut members = vec![member(0, None, None)];
+    let mut marriages = Vec::new();
+    let mut fathers = VecDeque::from([0]);
+    let mut next_id = 1;
+
+    while members.len() < size {
+        let Some(father_id) = fathers.pop_front() else {
+            break;
+        };
+        // wives have negative ids so they don't take the ids of children
+        let wife_id = -(father_id + 1);
+
+        members.push(member(wife_id, None, None));
+        marriages.push(marriage(father_id, father_id, wife_id));
+
+        for _ in 0..CHILDREN_PER_FAMILY {
+            let child = member(next_id, Some(father_id), Some(wife_id));
+            if child.gender == Gender::Male {
+                fathers.push_back(next_id);
+            }
+
+            members.push(child);
+            next_id += 1;
+        }
+    }
+
+    (members, marriages)
+}
+
+fn marriage(id: i64, husband_id: i64, wife_id: i64) -> MarriageRowWithSpouses {
+    MarriageRowWithSpouses {
+        id,
+        husband_id,
+        wife_id,
+        married_at: None,
+        ended_at: None,
+        end_reason: None,
+        marriage_order: 1,
+        husband_name: String::new(),
+        husband_last_name: String::new(),
+        wife_name: String::new(),
+        wife_last_name: String::new(),
+    }
+}
+
+/// two brothers whose descendants marry their cousins in every generation,
+/// each couple has a son and a daughter that marry the daughter and son of the other couple,
+/// so every child has both parents in the tree
+fn cousin_marriages(
+    generations: usize,
+) -> (Vec<MemberRowWithParents>, Vec<MarriageRowWithSpouses>) {
+    let mut members = vec![member(0, None, None), member(-1, None, None)];
+    let mut marriages = vec![marriage(0, 0, -1)];
+
+    // the brothers marry from outside the tree
+    let mut couples = vec![(2, -3), (4, -5)];
+    members.extend([
+        member(2, Some(0), Some(-1)),
+        member(4, Some(0), Some(-1)),
+        member(-3, None, None),
+        member(-5, None, None),
+    ]);
+    marriages.extend([marriage(1, 2, -3), marriage(2, 4, -5)]);
+    let mut next_id = 6;
+
+    for _ in 0..generations {
+        let mut sons = Vec::new();
+        let mut daughters = Vec::new();
+
+        for &(father_id, mother_id) in &couples {
+            // even ids are male
+            sons.push(next_id);
+            daughters.push(next_id + 1);
+            members.push(member(next_id, Some(father_id), Some(mother_id)));
+            members.push(member(next_id + 1, Some(father_id), Some(mother_id)));
+            next_id += 2;
+        }
+
+        couples = vec![(sons[0], daughters[1]), (sons[1], daughters[0])];
+        for &(husband_id, wife_id) in &couples {
+            marriages.push(marriage(marriages.len() as i64, husband_id, wife_id));
+        }
+    }
+
+    (members, marriages)
+}
+
+fn bench_build_tree(c: &mut Criterion) {
+    let mut group = c.benchmark_group("build_tree");
+    group.sample_size(10);
+
+    for size in [10_000, 100_000] {
+        let (members, marriages) = family(size);
+
+        group.bench_with_input(BenchmarkId::from_parameter(size), &size, |b, _| {
+            b.iter(|| build_tree(0, &members, &marriages))
+        });
+    }
+
+    group.finish();
+}
+
+/// how many members were built in the tree under `member`
+fn tree_size(member: &MemberResponse) -> usize {
+    1 + member.children.iter().map(tree_size).sum::<usize>()
+}
+
+fn bench_cousin_marriages(c: &mut Criterion) {
+    let mut group = c.benchmark_group("build_tree_cousin_marriages");
+    group.sample_size(10);
+
+    for generations in [8, 12, 100] {
+        let (members, marriages) = cousin_marriages(generations);
+
+        // every child is built once, so the tree grows by 4 members with every generation
+        let tree = build_tree(0, &members, &marriages).expect("the root is in the members");
+        assert_eq!(tree_size(&tree), 3 + 4 * generations);
+
+        group.bench_with_input(
+            BenchmarkId::from_parameter(generations),
+            &generations,
+            |b, _| b.iter(|| build_tree(0, &members, &marriages)),
+        );
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, bench_build_tree, bench_cousin_marriages);
+criterion_main!(benches);
@@ -0,0 +1,34 @@
+-- Add migration script here
+DO $$ BEGIN
+    CREATE TYPE marriage_end_reason AS ENUM (
+        'divorce', 'death'
+    );
+EXCEPTION
+    WHEN duplicate_object THEN null;
+END $$;
+
+CREATE TABLE IF NOT EXISTS marriages
+(
+    id BIGSERIAL PRIMARY KEY,
+    husband_id INT8 NOT NULL,
+    wife_id INT8 NOT NULL,
+    married_at TIMESTAMPTZ,
+    ended_at TIMESTAMPTZ,
+    end_reason marriage_end_reason,
+    -- the order of this marriage among the husband's marriages
+    marriage_order INT4 NOT NULL DEFAULT 1,
+
+   CONSTRAINT fk_husband
+      FOREIGN KEY(husband_id)
+        REFERENCES members(id)
+        ON DELETE CASCADE,
+   CONSTRAINT fk_wife
+      FOREIGN KEY(wife_id)
+        REFERENCES members(id)
+        ON DELETE CASCADE,
+   CONSTRAINT marriage_spouses_differ
+      CHECK (husband_id <> wife_id)
+);
+
+CREATE INDEX IF NOT EXISTS marriages_husband_id_idx ON marriages (husband_id);
+CREATE INDEX IF NOT EXISTS marriages_wife_id_idx ON marriages (wife_id);
@@ -0,0 +1,8 @@
+-- Add migration script here
+ALTER TABLE members ADD COLUMN IF NOT EXISTS death_date TIMESTAMPTZ;
+ALTER TABLE members ADD COLUMN IF NOT EXISTS deceased BOOLEAN NOT NULL DEFAULT FALSE;
+ALTER TABLE members ADD COLUMN IF NOT EXISTS burial_place TEXT;
+
+ALTER TABLE member_add_requests ADD COLUMN IF NOT EXISTS death_date TIMESTAMPTZ;
+ALTER TABLE member_add_requests ADD COLUMN IF NOT EXISTS deceased BOOLEAN NOT NULL DEFAULT FALSE;
+ALTER TABLE member_add_requests ADD COLUMN IF NOT EXISTS burial_place TEXT;
@@ -0,0 +1,19 @@
+-- Add migration script here
+-- dates are stored as text so they can be partial or approximate, e.g. "1932", "1932-05" or "circa 1932"
+DO $$
+DECLARE
+    target RECORD;
+BEGIN
+    FOR target IN
+        SELECT table_name, column_name
+        FROM information_schema.columns
+        WHERE table_name IN ('members', 'member_add_requests')
+            AND column_name IN ('birthday', 'death_date')
+            AND data_type = 'timestamp with time zone'
+    LOOP
+        EXECUTE format(
+            'ALTER TABLE %I ALTER COLUMN %I TYPE TEXT USING to_char(%I AT TIME ZONE ''UTC'', ''YYYY-MM-DD'')',
+            target.table_name, target.column_name, target.column_name
+        );
+    END LOOP;
+END $$;
@@ -0,0 +1,5 @@
+-- Add migration script here
+-- members are soft deleted, they stay in the trash with their links until they're restored or purged
+ALTER TABLE members ADD COLUMN IF NOT EXISTS deleted_at TIMESTAMPTZ;
+
+CREATE INDEX IF NOT EXISTS members_deleted_at_idx ON members (deleted_at);
@@ -0,0 +1,29 @@
+-- Add migration script here
+DO $$ BEGIN
+    CREATE TYPE member_change_action AS ENUM (
+        'create', 'edit', 'delete', 'restore', 'purge', 'import', 'approve', 'revert'
+    );
+EXCEPTION
+    WHEN duplicate_object THEN null;
+END $$;
+
+-- every change to a member with the member's fields before and after it,
+-- kept after the member is purged so there's no foreign key to members
+CREATE TABLE IF NOT EXISTS member_changes
+(
+    id BIGSERIAL PRIMARY KEY,
+    member_id INT8 NOT NULL,
+    user_id UUID,
+    action member_change_action NOT NULL,
+    changed_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    before JSONB,
+    after JSONB,
+
+   CONSTRAINT fk_user
+      FOREIGN KEY(user_id)
+        REFERENCES users(id)
+        ON DELETE SET NULL
+);
+
+CREATE INDEX IF NOT EXISTS member_changes_member_id_idx ON member_changes (member_id, changed_at);
+CREATE INDEX IF NOT EXISTS member_changes_changed_at_idx ON member_changes (changed_at);
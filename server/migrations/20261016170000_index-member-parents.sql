@@ -0,0 +1,4 @@
+-- Add migration script here
+-- the tree is walked from parents to children
+CREATE INDEX IF NOT EXISTS members_father_id_idx ON members (father_id);
+CREATE INDEX IF NOT EXISTS members_mother_id_idx ON members (mother_id);
@@ -0,0 +1,4 @@
+-- Add migration script here
+-- used as the ETag of the member image and to bust caches when it changes
+ALTER TABLE members
+ADD COLUMN IF NOT EXISTS image_hash TEXT GENERATED ALWAYS AS (encode(sha256(image), 'hex')) STORED;
@@ -0,0 +1,17 @@
+-- Add migration script here
+DO $$ BEGIN
+    CREATE TYPE image_size AS ENUM ('thumbnail', 'medium', 'original');
+EXCEPTION
+    WHEN duplicate_object THEN null;
+END $$;
+
+-- smaller copies of member images, made the first time they are requested
+CREATE TABLE IF NOT EXISTS member_image_sizes (
+    member_id INT8 NOT NULL REFERENCES members (id) ON DELETE CASCADE,
+    size image_size NOT NULL,
+    image BYTEA NOT NULL,
+    image_type TEXT NOT NULL,
+    -- the image_hash of the member image it was made from, copies of older images are made again
+    source_hash TEXT NOT NULL,
+    PRIMARY KEY (member_id, size)
+);
@@ -0,0 +1,28 @@
+-- Add migration script here
+DO $$ BEGIN
+    CREATE TYPE media_kind AS ENUM ('photo', 'document');
+EXCEPTION
+    WHEN duplicate_object THEN null;
+END $$;
+
+-- photos and scanned documents of a member, shown in their gallery
+CREATE TABLE IF NOT EXISTS member_media (
+    id BIGSERIAL PRIMARY KEY,
+    member_id INT8 NOT NULL REFERENCES members (id) ON DELETE CASCADE,
+    kind media_kind NOT NULL,
+    caption TEXT,
+    -- a partial date, like the dates of members
+    date TEXT,
+    position INT4 NOT NULL,
+    file_name TEXT,
+    content BYTEA NOT NULL,
+    content_type TEXT NOT NULL,
+    content_hash TEXT GENERATED ALWAYS AS (encode(sha256(content), 'hex')) STORED,
+    -- made when the media is uploaded, documents that aren't images have none
+    thumbnail BYTEA,
+    thumbnail_type TEXT,
+    uploaded_by UUID REFERENCES users (id) ON DELETE SET NULL,
+    uploaded_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+CREATE INDEX IF NOT EXISTS member_media_member_id_idx ON member_media (member_id, position);
@@ -0,0 +1,18 @@
+-- Add migration script here
+-- images are kept in the configured storage under the sha256 of their content,
+-- the hash columns are the storage keys, the BYTEA columns are emptied by `server move-images`
+ALTER TABLE members ALTER COLUMN image_hash DROP EXPRESSION;
+
+ALTER TABLE member_add_requests ADD COLUMN IF NOT EXISTS image_hash TEXT;
+UPDATE member_add_requests SET image_hash = encode(sha256(image), 'hex') WHERE image IS NOT NULL;
+
+-- the smaller sizes are made again from the stored images when they're requested
+DELETE FROM member_image_sizes;
+ALTER TABLE member_image_sizes DROP COLUMN IF EXISTS image;
+ALTER TABLE member_image_sizes ADD COLUMN IF NOT EXISTS image_hash TEXT NOT NULL;
+
+ALTER TABLE member_media ALTER COLUMN content_hash DROP EXPRESSION;
+ALTER TABLE member_media ALTER COLUMN content_hash SET NOT NULL;
+ALTER TABLE member_media ALTER COLUMN content DROP NOT NULL;
+ALTER TABLE member_media ADD COLUMN IF NOT EXISTS thumbnail_hash TEXT;
+UPDATE member_media SET thumbnail_hash = encode(sha256(thumbnail), 'hex') WHERE thumbnail IS NOT NULL;
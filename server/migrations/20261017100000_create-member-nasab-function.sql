@@ -0,0 +1,25 @@
+-- The name of a member followed by the names of their fathers, up to `depth` of them,
+-- e.g. محمد بن أحمد بن علي, the last name isn't part of it
+CREATE OR REPLACE FUNCTION member_nasab(member_id BIGINT, depth INT) RETURNS TEXT
+LANGUAGE SQL STABLE AS $$
+    WITH RECURSIVE line AS (
+        SELECT id, name, gender, father_id, 0 AS generation
+        FROM members WHERE id = member_id
+        UNION ALL
+        SELECT m.id, m.name, m.gender, m.father_id, l.generation + 1
+        FROM members m
+        INNER JOIN line l ON m.id = l.father_id
+        WHERE l.generation < depth AND m.deleted_at IS NULL
+    )
+    SELECT string_agg(
+        CASE
+            WHEN l.generation = 0 THEN l.name
+            -- the connector follows the member it comes after
+            WHEN l.generation = 1 AND member.gender = 'female' THEN 'بنت ' || l.name
+            ELSE 'بن ' || l.name
+        END,
+        ' ' ORDER BY l.generation
+    )
+    FROM line l, members member
+    WHERE member.id = member_id
+$$;
@@ -0,0 +1,21 @@
+-- Add migration script here
+-- names are also matched by their trigrams so a misspelled name is still found
+CREATE EXTENSION IF NOT EXISTS pg_trgm;
+
+-- Text the way it's searched: the hamza forms as the letter they're written on,
+-- ة as ه, ى as ي, without tashkeel or tatweel and in lower case
+CREATE OR REPLACE FUNCTION normalize_arabic(value TEXT) RETURNS TEXT
+LANGUAGE SQL IMMUTABLE PARALLEL SAFE AS $$
+    SELECT lower(translate(
+        regexp_replace(value, '[\u064B-\u0652\u0670\u0640]', '', 'g'),
+        'أإآٱىةؤئ',
+        'اااايهوي'
+    ))
+$$;
+
+ALTER TABLE members
+ADD COLUMN IF NOT EXISTS search_name TEXT
+GENERATED ALWAYS AS (normalize_arabic(name || ' ' || last_name)) STORED;
+
+CREATE INDEX IF NOT EXISTS members_search_name_trgm_idx
+ON members USING GIN (search_name gin_trgm_ops);
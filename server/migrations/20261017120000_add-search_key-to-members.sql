@@ -0,0 +1,10 @@
+-- Add migration script here
+-- the name in Latin letters reduced to how it sounds, so it's found however it's spelled
+-- and in either script, it's made by the server from the `search_name` kept with it
+-- and made again when the name changes
+ALTER TABLE members
+ADD COLUMN IF NOT EXISTS search_key TEXT,
+ADD COLUMN IF NOT EXISTS search_key_source TEXT;
+
+CREATE INDEX IF NOT EXISTS members_search_key_trgm_idx
+ON members USING GIN (search_key gin_trgm_ops);
@@ -0,0 +1,31 @@
+-- Add migration script here
+DROP FUNCTION member_nasab(BIGINT, INT);
+
+-- The nasab of every member in `ids`, or of all of them when it's null, in one walk up
+-- the fathers, see `member_nasab` in 20261017100000_create-member-nasab-function.sql
+CREATE FUNCTION member_nasabs(depth INT, ids BIGINT[] DEFAULT NULL)
+RETURNS TABLE (id BIGINT, nasab TEXT)
+LANGUAGE SQL STABLE AS $$
+    WITH RECURSIVE line AS (
+        SELECT m.id AS member_id, m.gender AS member_gender, m.name, m.father_id, 0 AS generation
+        FROM members m WHERE ids IS NULL OR m.id = ANY(ids)
+        UNION ALL
+        SELECT l.member_id, l.member_gender, f.name, f.father_id, l.generation + 1
+        FROM line l
+        INNER JOIN members f ON f.id = l.father_id
+        WHERE l.generation < depth AND f.deleted_at IS NULL
+    )
+    SELECT
+        l.member_id,
+        string_agg(
+            CASE
+                WHEN l.generation = 0 THEN l.name
+                -- the connector follows the member it comes after
+                WHEN l.generation = 1 AND l.member_gender = 'female' THEN 'بنت ' || l.name
+                ELSE 'بن ' || l.name
+            END,
+            ' ' ORDER BY l.generation
+        )
+    FROM line l
+    GROUP BY l.member_id
+$$;
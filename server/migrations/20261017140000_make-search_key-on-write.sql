@@ -0,0 +1,5 @@
+-- search keys are now made whenever a name is written instead of before each search,
+-- the keys made the old way are made again by the server when it starts
+ALTER TABLE members DROP COLUMN IF EXISTS search_key_source;
+
+UPDATE members SET search_key = NULL;
@@ -0,0 +1,8 @@
+-- Add migration script here
+-- a couple is married once, the marriages added twice keep their first row
+DELETE FROM marriages m
+USING marriages first
+WHERE m.husband_id = first.husband_id AND m.wife_id = first.wife_id AND m.id > first.id;
+
+ALTER TABLE marriages
+ADD CONSTRAINT marriage_spouses_unique UNIQUE (husband_id, wife_id);
@@ -0,0 +1,2 @@
+-- Add migration script here
+ALTER TYPE member_change_action ADD VALUE IF NOT EXISTS 'restore_backup';
@@ -0,0 +1,520 @@
+use std::collections::{BTreeMap, HashSet};
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use chrono::Utc;
+use sqlx::{PgConnection, PgPool};
+use tokio::io::{AsyncWrite, AsyncWriteExt};
+use uuid::Uuid;
+
+use crate::{
+    api::{
+        members::{
+            dates::PartialDate,
+            history::{record_changes, snapshots},
+            images,
+            models::{ChangeAction, MarriageEndReason, MediaKind, RequestStatus},
+            search,
+        },
+        users::models::UserRole,
+    },
+    storage::{content_key, Storage},
+    Gender,
+};
+
+use super::{
+    models::{
+        Backup, BackupAddRequest, BackupChange, BackupHeader, BackupMarriage, BackupMedia,
+        BackupMember, BackupSummary, BackupUser, BACKUP_VERSION,
+    },
+    BackupError,
+};
+
+/// postgres takes at most 65535 parameters in a query, the widest table has 19 columns
+const ROWS_PER_INSERT: usize = 1_000;
+
+/// Make a backup of the whole database, the files it refers to are added when it's written,
+/// see [`write`]
+///
+/// the password hashes of the users are only kept if `passwords` is set
+pub async fn create(db_pool: &PgPool, passwords: bool) -> Result<Backup, BackupError> {
+    let in_database = images::count_in_database(db_pool).await?;
+    if in_database > 0 {
+        return Err(BackupError::ImagesInDatabase(in_database));
+    }
+
+    // one snapshot, so rows added while the backup is made can't point at missing ones
+    let mut tx = db_pool.begin().await?;
+    sqlx::query!("SET TRANSACTION ISOLATION LEVEL REPEATABLE READ, READ ONLY")
+        .execute(&mut *tx)
+        .await?;
+
+    let users = sqlx::query_as!(
+        BackupUser,
+        r#"
+SELECT
+    id, first_name, last_name, username, email, phone_number,
+    CASE WHEN $1 THEN password END as password,
+    role as "role: UserRole", created_at, updated_at, last_login
+FROM users
+ORDER BY created_at, id
+        "#,
+        passwords,
+    )
+    .fetch_all(&mut *tx)
+    .await?;
+
+    let members = sqlx::query_as!(
+        BackupMember,
+        r#"
+SELECT
+    id, name, last_name, gender as "gender: Gender",
+    birthday as "birthday: PartialDate", death_date as "death_date: PartialDate",
+    deceased, burial_place, father_id, mother_id, image_hash, image_type,
+    personal_info, deleted_at
+FROM members
+ORDER BY id
+        "#,
+    )
+    .fetch_all(&mut *tx)
+    .await?;
+
+    let marriages = sqlx::query_as!(
+        BackupMarriage,
+        r#"
+SELECT
+    id, husband_id, wife_id, married_at, ended_at,
+    end_reason as "end_reason: MarriageEndReason", marriage_order
+FROM marriages
+ORDER BY id
+        "#,
+    )
+    .fetch_all(&mut *tx)
+    .await?;
+
+    let media = sqlx::query_as!(
+        BackupMedia,
+        r#"
+SELECT
+    id, member_id, kind as "kind: MediaKind", caption, date as "date: PartialDate",
+    position, file_name, content_type, content_hash, thumbnail_hash, thumbnail_type,
+    uploaded_by, uploaded_at
+FROM member_media
+ORDER BY id
+        "#,
+    )
+    .fetch_all(&mut *tx)
+    .await?;
+
+    let add_requests = sqlx::query_as!(
+        BackupAddRequest,
+        r#"
+SELECT
+    id, name, last_name, gender as "gender: Gender",
+    birthday as "birthday: PartialDate", death_date as "death_date: PartialDate",
+    deceased, burial_place, father_id, mother_id, image_hash, image_type, personal_info,
+    status as "status: RequestStatus", submitted_by, submitted_at, reviewed_at, reviewed_by,
+    rejection_reason
+FROM member_add_requests
+ORDER BY submitted_at, id
+        "#,
+    )
+    .fetch_all(&mut *tx)
+    .await?;
+
+    let changes = sqlx::query_as!(
+        BackupChange,
+        r#"
+SELECT
+    id, member_id, user_id, action as "action: ChangeAction", changed_at, before, after
+FROM member_changes
+ORDER BY id
+        "#,
+    )
+    .fetch_all(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(Backup {
+        version: BACKUP_VERSION,
+        created_at: Utc::now(),
+        users,
+        members,
+        marriages,
+        media,
+        add_requests,
+        changes,
+        files: BTreeMap::new(),
+    })
+}
+
+/// Write a backup as json with every file it refers to,
+/// the files are read from the storage and encoded one at a time so they're never all in memory
+///
+/// returns how many files were written
+pub async fn write<W: AsyncWrite + Unpin>(
+    backup: &Backup,
+    storage: &dyn Storage,
+    out: &mut W,
+) -> Result<usize, BackupError> {
+    let mut rows = serde_json::to_vec(backup).map_err(std::io::Error::from)?;
+    // the files are written as the last field after the closing brace of the rest is taken off
+    rows.pop();
+    out.write_all(&rows).await?;
+    out.write_all(br#","files":{"#).await?;
+
+    let mut keys: Vec<String> = file_keys(backup).into_iter().collect();
+    keys.sort();
+
+    for (i, key) in keys.iter().enumerate() {
+        if i > 0 {
+            out.write_all(b",").await?;
+        }
+
+        // keys are hex and base64 has nothing to escape
+        let content = storage.get(key).await?;
+        let entry = format!(r#""{key}":"{}""#, STANDARD.encode(content));
+        out.write_all(entry.as_bytes()).await?;
+    }
+
+    out.write_all(b"}}").await?;
+    out.flush().await?;
+
+    Ok(keys.len())
+}
+
+/// The storage keys of every file the rows of a backup refer to
+fn file_keys(backup: &Backup) -> HashSet<String> {
+    let members = backup.members.iter().filter_map(|m| m.image_hash.clone());
+    let requests = backup
+        .add_requests
+        .iter()
+        .filter_map(|r| r.image_hash.clone());
+    let media = backup
+        .media
+        .iter()
+        .flat_map(|m| [Some(m.content_hash.clone()), m.thumbnail_hash.clone()])
+        .flatten();
+
+    members.chain(requests).chain(media).collect()
+}
+
+/// Read a backup, refusing backups made by another version of the format
+pub fn parse(data: &[u8]) -> Result<Backup, BackupError> {
+    let header = serde_json::from_slice::<BackupHeader>(data)
+        .map_err(|e| BackupError::InvalidBackup(e.to_string()))?;
+
+    if header.version != BACKUP_VERSION {
+        return Err(BackupError::UnsupportedVersion(
+            header.version,
+            BACKUP_VERSION,
+        ));
+    }
+
+    serde_json::from_slice::<Backup>(data).map_err(|e| BackupError::InvalidBackup(e.to_string()))
+}
+
+/// Replace everything in the database with the contents of a backup
+///
+/// the files are stored first, then all rows are replaced in one transaction,
+/// so a failed restore leaves the database as it was.
+/// sessions of users that aren't in the backup are ended.
+/// users in a backup without password hashes keep their current password,
+/// or can't log in if they're new
+///
+/// every member it changes gets a change in the history made by `user_id`,
+/// if that user is in the backup
+pub async fn restore(
+    db_pool: &PgPool,
+    storage: &dyn Storage,
+    backup: &Backup,
+    user_id: Option<Uuid>,
+) -> Result<BackupSummary, BackupError> {
+    let mut files = Vec::with_capacity(backup.files.len());
+
+    for key in file_keys(backup) {
+        let encoded = backup
+            .files
+            .get(&key)
+            .ok_or_else(|| BackupError::MissingFile(key.clone()))?;
+
+        let content = STANDARD
+            .decode(encoded)
+            .map_err(|_| BackupError::CorruptFile(key.clone()))?;
+
+        if content_key(&content) != key {
+            return Err(BackupError::CorruptFile(key));
+        }
+
+        files.push(content);
+    }
+
+    let replaced = sqlx::query_scalar!(
+        r#"
+SELECT image_hash as "key!" FROM members WHERE image_hash IS NOT NULL
+UNION SELECT image_hash FROM member_add_requests WHERE image_hash IS NOT NULL
+UNION SELECT image_hash FROM member_image_sizes
+UNION SELECT content_hash FROM member_media
+UNION SELECT thumbnail_hash FROM member_media WHERE thumbnail_hash IS NOT NULL
+        "#,
+    )
+    .fetch_all(db_pool)
+    .await?;
+
+    let mut tx = db_pool.begin().await?;
+
+    let mut stored = Vec::with_capacity(files.len());
+    for content in files {
+        stored.push(images::put(&mut tx, storage, &content).await?);
+    }
+
+    match replace_rows(&mut tx, backup, user_id).await {
+        Ok(()) => tx.commit().await?,
+        Err(e) => {
+            tx.rollback().await?;
+            // the stored files may not be used by anything now
+            images::release(db_pool, storage, &stored).await?;
+            return Err(e);
+        }
+    }
+
+    images::release(db_pool, storage, &replaced).await?;
+
+    Ok(BackupSummary::from(backup))
+}
+
+async fn replace_rows(
+    conn: &mut PgConnection,
+    backup: &Backup,
+    user_id: Option<Uuid>,
+) -> Result<(), BackupError> {
+    let user_ids: Vec<_> = backup.users.iter().map(|u| u.id).collect();
+
+    let replaced: Vec<i64> = sqlx::query_scalar!("SELECT id FROM members")
+        .fetch_all(&mut *conn)
+        .await?;
+    let before = snapshots(&mut *conn, &replaced).await?;
+
+    sqlx::query!("DELETE FROM member_changes")
+        .execute(&mut *conn)
+        .await?;
+    sqlx::query!("DELETE FROM marriages")
+        .execute(&mut *conn)
+        .await?;
+    sqlx::query!("DELETE FROM member_media")
+        .execute(&mut *conn)
+        .await?;
+    sqlx::query!("DELETE FROM member_image_sizes")
+        .execute(&mut *conn)
+        .await?;
+    sqlx::query!("DELETE FROM member_add_requests")
+        .execute(&mut *conn)
+        .await?;
+    sqlx::query!("DELETE FROM members")
+        .execute(&mut *conn)
+        .await?;
+    // users in the backup are updated in place so their sessions stay valid
+    sqlx::query!("DELETE FROM users WHERE id <> ALL($1)", &user_ids)
+        .execute(&mut *conn)
+        .await?;
+
+    for chunk in backup.users.chunks(ROWS_PER_INSERT) {
+        let mut query = sqlx::QueryBuilder::new(
+            "INSERT INTO users (id, first_name, last_name, username, email, phone_number, password, role, created_at, updated_at, last_login)",
+        );
+
+        query.push_values(chunk, |mut b, u| {
+            b.push_bind(u.id)
+                .push_bind(&u.first_name)
+                .push_bind(&u.last_name)
+                .push_bind(&u.username)
+                .push_bind(&u.email)
+                .push_bind(&u.phone_number)
+                .push_bind(u.password.as_deref().unwrap_or_default())
+                .push_bind(u.role)
+                .push_bind(u.created_at)
+                .push_bind(u.updated_at)
+                .push_bind(u.last_login);
+        });
+
+        query.push(
+            r#"
+            ON CONFLICT(id)
+            DO UPDATE SET
+            first_name = EXCLUDED.first_name, last_name = EXCLUDED.last_name,
+            username = EXCLUDED.username, email = EXCLUDED.email,
+            phone_number = EXCLUDED.phone_number,
+            password = COALESCE(NULLIF(EXCLUDED.password, ''), users.password),
+            role = EXCLUDED.role, created_at = EXCLUDED.created_at,
+            updated_at = EXCLUDED.updated_at, last_login = EXCLUDED.last_login
+        "#,
+        );
+
+        query.build().execute(&mut *conn).await?;
+    }
+
+    // parents are linked after all members exist, a parent can come after their child
+    for chunk in backup.members.chunks(ROWS_PER_INSERT) {
+        let mut query = sqlx::QueryBuilder::new(
+            "INSERT INTO members (id, name, last_name, gender, birthday, death_date, deceased, burial_place, image_hash, image_type, personal_info, deleted_at)",
+        );
+
+        query.push_values(chunk, |mut b, m| {
+            b.push_bind(m.id)
+                .push_bind(&m.name)
+                .push_bind(&m.last_name)
+                .push_bind(m.gender)
+                .push_bind(m.birthday)
+                .push_bind(m.death_date)
+                .push_bind(m.deceased)
+                .push_bind(&m.burial_place)
+                .push_bind(&m.image_hash)
+                .push_bind(&m.image_type)
+                .push_bind(&m.personal_info)
+                .push_bind(m.deleted_at);
+        });
+
+        query.build().execute(&mut *conn).await?;
+    }
+
+    let ids: Vec<i64> = backup.members.iter().map(|m| m.id).collect();
+    let fathers: Vec<Option<i64>> = backup.members.iter().map(|m| m.father_id).collect();
+    let mothers: Vec<Option<i64>> = backup.members.iter().map(|m| m.mother_id).collect();
+
+    sqlx::query!(
+        r#"
+UPDATE members m
+SET father_id = p.father_id, mother_id = p.mother_id
+FROM unnest($1::INT8[], $2::INT8[], $3::INT8[]) AS p(id, father_id, mother_id)
+WHERE m.id = p.id
+        "#,
+        &ids,
+        &fathers as &[Option<i64>],
+        &mothers as &[Option<i64>],
+    )
+    .execute(&mut *conn)
+    .await?;
+
+    search::update_search_keys(conn, &ids).await?;
+
+    for chunk in backup.marriages.chunks(ROWS_PER_INSERT) {
+        let mut query = sqlx::QueryBuilder::new(
+            "INSERT INTO marriages (id, husband_id, wife_id, married_at, ended_at, end_reason, marriage_order)",
+        );
+
+        query.push_values(chunk, |mut b, m| {
+            b.push_bind(m.id)
+                .push_bind(m.husband_id)
+                .push_bind(m.wife_id)
+                .push_bind(m.married_at)
+                .push_bind(m.ended_at)
+                .push_bind(m.end_reason)
+                .push_bind(m.marriage_order);
+        });
+
+        query.build().execute(&mut *conn).await?;
+    }
+
+    for chunk in backup.media.chunks(ROWS_PER_INSERT) {
+        let mut query = sqlx::QueryBuilder::new(
+            "INSERT INTO member_media (id, member_id, kind, caption, date, position, file_name, content_type, content_hash, thumbnail_hash, thumbnail_type, uploaded_by, uploaded_at)",
+        );
+
+        query.push_values(chunk, |mut b, m| {
+            b.push_bind(m.id)
+                .push_bind(m.member_id)
+                .push_bind(m.kind)
+                .push_bind(&m.caption)
+                .push_bind(m.date)
+                .push_bind(m.position)
+                .push_bind(&m.file_name)
+                .push_bind(&m.content_type)
+                .push_bind(&m.content_hash)
+                .push_bind(&m.thumbnail_hash)
+                .push_bind(&m.thumbnail_type)
+                .push_bind(m.uploaded_by)
+                .push_bind(m.uploaded_at);
+        });
+
+        query.build().execute(&mut *conn).await?;
+    }
+
+    for chunk in backup.add_requests.chunks(ROWS_PER_INSERT) {
+        let mut query = sqlx::QueryBuilder::new(
+            "INSERT INTO member_add_requests (id, name, last_name, gender, birthday, death_date, deceased, burial_place, father_id, mother_id, image_hash, image_type, personal_info, status, submitted_by, submitted_at, reviewed_at, reviewed_by, rejection_reason)",
+        );
+
+        query.push_values(chunk, |mut b, r| {
+            b.push_bind(r.id)
+                .push_bind(&r.name)
+                .push_bind(&r.last_name)
+                .push_bind(r.gender)
+                .push_bind(r.birthday)
+                .push_bind(r.death_date)
+                .push_bind(r.deceased)
+                .push_bind(&r.burial_place)
+                .push_bind(r.father_id)
+                .push_bind(r.mother_id)
+                .push_bind(&r.image_hash)
+                .push_bind(&r.image_type)
+                .push_bind(&r.personal_info)
+                .push_bind(r.status)
+                .push_bind(&r.submitted_by)
+                .push_bind(r.submitted_at)
+                .push_bind(r.reviewed_at)
+                .push_bind(&r.reviewed_by)
+                .push_bind(&r.rejection_reason);
+        });
+
+        query.build().execute(&mut *conn).await?;
+    }
+
+    for chunk in backup.changes.chunks(ROWS_PER_INSERT) {
+        let mut query = sqlx::QueryBuilder::new(
+            "INSERT INTO member_changes (id, member_id, user_id, action, changed_at, before, after)",
+        );
+
+        query.push_values(chunk, |mut b, c| {
+            b.push_bind(c.id)
+                .push_bind(c.member_id)
+                .push_bind(c.user_id)
+                .push_bind(c.action)
+                .push_bind(c.changed_at)
+                .push_bind(&c.before)
+                .push_bind(&c.after);
+        });
+
+        query.build().execute(&mut *conn).await?;
+    }
+
+    // new rows continue after the restored ids
+    sqlx::query!(
+        r#"
+SELECT
+    setval('members_id_seq', COALESCE((SELECT MAX(id) FROM members), 0) + 1, false) as members,
+    setval('marriages_id_seq', COALESCE((SELECT MAX(id) FROM marriages), 0) + 1, false) as marriages,
+    setval('member_media_id_seq', COALESCE((SELECT MAX(id) FROM member_media), 0) + 1, false) as media,
+    setval('member_changes_id_seq', COALESCE((SELECT MAX(id) FROM member_changes), 0) + 1, false) as changes
+        "#,
+    )
+    .fetch_one(&mut *conn)
+    .await?;
+
+    let ids: Vec<i64> = replaced
+        .into_iter()
+        .chain(backup.members.iter().map(|m| m.id))
+        .collect::<HashSet<_>>()
+        .into_iter()
+        .collect();
+
+    record_changes(
+        conn,
+        user_id.filter(|id| user_ids.contains(id)),
+        ChangeAction::RestoreBackup,
+        &ids,
+        &before,
+    )
+    .await?;
+
+    Ok(())
+}
@@ -0,0 +1,79 @@
+use axum::{http::StatusCode, response::IntoResponse};
+
+use crate::{auth::AuthError, storage::StorageError, ErrorResponse};
+
+use super::members::MembersError;
+
+pub mod archive;
+pub mod models;
+pub mod routes;
+
+#[derive(thiserror::Error, Debug)]
+pub enum BackupError {
+    #[error("something went wrong")]
+    Sqlx(#[from] sqlx::Error),
+
+    #[error("bad request")]
+    BadRequest,
+
+    #[error("invalid backup: {0}")]
+    InvalidBackup(String),
+
+    #[error("unsupported backup version {0}, expected {1}")]
+    UnsupportedVersion(u32, u32),
+
+    #[error("backup is missing the file {0}")]
+    MissingFile(String),
+
+    #[error("the file {0} in the backup doesn't match its key")]
+    CorruptFile(String),
+
+    #[error("{0} images are still in the database, run `server move-images` first")]
+    ImagesInDatabase(i64),
+
+    #[error(transparent)]
+    AuthError(#[from] AuthError),
+
+    #[error(transparent)]
+    Storage(#[from] StorageError),
+
+    #[error("failed to write the backup")]
+    Io(#[from] std::io::Error),
+
+    #[error(transparent)]
+    Members(#[from] MembersError),
+}
+
+impl IntoResponse for BackupError {
+    fn into_response(self) -> axum::response::Response {
+        log::error!("{self:#?}");
+
+        match self {
+            BackupError::Sqlx(_) | BackupError::Storage(_) | BackupError::Io(_) => {
+                (StatusCode::INTERNAL_SERVER_ERROR).into_response()
+            }
+            BackupError::AuthError(e) => e.into_response(),
+            BackupError::Members(e) => e.into_response(),
+            BackupError::BadRequest
+            | BackupError::InvalidBackup(_)
+            | BackupError::UnsupportedVersion(..)
+            | BackupError::MissingFile(_)
+            | BackupError::CorruptFile(_) => (
+                StatusCode::BAD_REQUEST,
+                ErrorResponse {
+                    error: self.to_string(),
+                    details: None,
+                },
+            )
+                .into_response(),
+            BackupError::ImagesInDatabase(_) => (
+                StatusCode::CONFLICT,
+                ErrorResponse {
+                    error: self.to_string(),
+                    details: None,
+                },
+            )
+                .into_response(),
+        }
+    }
+}
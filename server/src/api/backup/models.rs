@@ -0,0 +1,185 @@
+use std::collections::BTreeMap;
+
+use chrono::{DateTime, NaiveDateTime, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::{
+    api::{
+        members::{
+            dates::PartialDate,
+            models::{ChangeAction, MarriageEndReason, MediaKind, RequestStatus},
+        },
+        users::models::UserRole,
+    },
+    Gender,
+};
+
+/// The version of the backup format, bumped whenever a restore can't read older backups as is
+pub const BACKUP_VERSION: u32 = 1;
+
+/// Read before the rest of a backup, so a backup from another version
+/// is refused before its rows are parsed
+#[derive(Debug, Deserialize)]
+pub struct BackupHeader {
+    pub version: u32,
+}
+
+/// Everything needed to bring the family tree back as it was,
+/// rows keep their ids so links between them survive a restore
+///
+/// generated image sizes and sessions aren't kept, sizes are made again when requested
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Backup {
+    pub version: u32,
+    pub created_at: DateTime<Utc>,
+    pub users: Vec<BackupUser>,
+    pub members: Vec<BackupMember>,
+    pub marriages: Vec<BackupMarriage>,
+    pub media: Vec<BackupMedia>,
+    pub add_requests: Vec<BackupAddRequest>,
+    pub changes: Vec<BackupChange>,
+    /// the images and documents, base64 encoded and keyed by their storage key,
+    /// they're read from the storage one at a time when the backup is written, see [`write`]
+    ///
+    /// [`write`]: super::archive::write
+    #[serde(skip_serializing)]
+    pub files: BTreeMap<String, String>,
+}
+
+/// The options of a backup download
+#[derive(Debug, Default, Deserialize)]
+pub struct BackupParams {
+    /// keep the password hashes of the users, so they can log in after a restore
+    /// on another server
+    #[serde(default)]
+    pub passwords: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BackupUser {
+    pub id: Uuid,
+    pub first_name: Option<String>,
+    pub last_name: Option<String>,
+    pub username: String,
+    pub email: String,
+    pub phone_number: Option<String>,
+    /// the password hash, never the password itself,
+    /// only kept when the backup is made with the password hashes
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub password: Option<String>,
+    pub role: UserRole,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: Option<DateTime<Utc>>,
+    pub last_login: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BackupMember {
+    pub id: i64,
+    pub name: String,
+    pub last_name: String,
+    pub gender: Gender,
+    pub birthday: Option<PartialDate>,
+    pub death_date: Option<PartialDate>,
+    pub deceased: bool,
+    pub burial_place: Option<String>,
+    pub father_id: Option<i64>,
+    pub mother_id: Option<i64>,
+    pub image_hash: Option<String>,
+    pub image_type: Option<String>,
+    pub personal_info: Option<serde_json::Value>,
+    pub deleted_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BackupMarriage {
+    pub id: i64,
+    pub husband_id: i64,
+    pub wife_id: i64,
+    pub married_at: Option<DateTime<Utc>>,
+    pub ended_at: Option<DateTime<Utc>>,
+    pub end_reason: Option<MarriageEndReason>,
+    pub marriage_order: i32,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BackupMedia {
+    pub id: i64,
+    pub member_id: i64,
+    pub kind: MediaKind,
+    pub caption: Option<String>,
+    pub date: Option<PartialDate>,
+    pub position: i32,
+    pub file_name: Option<String>,
+    pub content_type: String,
+    pub content_hash: String,
+    pub thumbnail_hash: Option<String>,
+    pub thumbnail_type: Option<String>,
+    pub uploaded_by: Option<Uuid>,
+    pub uploaded_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BackupAddRequest {
+    pub id: Uuid,
+    pub name: String,
+    pub last_name: String,
+    pub gender: Gender,
+    pub birthday: Option<PartialDate>,
+    pub death_date: Option<PartialDate>,
+    pub deceased: bool,
+    pub burial_place: Option<String>,
+    pub father_id: Option<i64>,
+    pub mother_id: Option<i64>,
+    pub image_hash: Option<String>,
+    pub image_type: Option<String>,
+    pub personal_info: Option<serde_json::Value>,
+    pub status: RequestStatus,
+    pub submitted_by: Option<String>,
+    pub submitted_at: Option<NaiveDateTime>,
+    pub reviewed_at: Option<NaiveDateTime>,
+    pub reviewed_by: Option<String>,
+    pub rejection_reason: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BackupChange {
+    pub id: i64,
+    pub member_id: i64,
+    pub user_id: Option<Uuid>,
+    pub action: ChangeAction,
+    pub changed_at: DateTime<Utc>,
+    pub before: Option<serde_json::Value>,
+    pub after: Option<serde_json::Value>,
+}
+
+/// How many rows of each kind a backup holds
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BackupSummary {
+    pub version: u32,
+    pub created_at: DateTime<Utc>,
+    pub users: usize,
+    pub members: usize,
+    pub marriages: usize,
+    pub media: usize,
+    pub add_requests: usize,
+    pub changes: usize,
+    pub files: usize,
+}
+
+impl From<&Backup> for BackupSummary {
+    fn from(backup: &Backup) -> Self {
+        Self {
+            version: backup.version,
+            created_at: backup.created_at,
+            users: backup.users.len(),
+            members: backup.members.len(),
+            marriages: backup.marriages.len(),
+            media: backup.media.len(),
+            add_requests: backup.add_requests.len(),
+            changes: backup.changes.len(),
+            files: backup.files.len(),
+        }
+    }
+}
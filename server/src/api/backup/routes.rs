@@ -0,0 +1,94 @@
+use std::sync::Arc;
+
+use axum::{
+    body::Body,
+    extract::{Multipart, Query, State},
+    response::IntoResponse,
+    Json,
+};
+use tokio_util::io::ReaderStream;
+
+use crate::{api::users::models::UserRole, auth::AuthExtractor, InnerAppState};
+
+use super::{
+    archive,
+    models::{BackupParams, BackupSummary},
+    BackupError,
+};
+
+/// Download a backup of everything, see [`archive::create`]
+///
+/// the files are read while the response is sent, a failure on the way ends the response early
+pub async fn get_backup(
+    _auth: AuthExtractor<{ UserRole::Admin as u8 }>,
+    State(state): State<Arc<InnerAppState>>,
+    Query(params): Query<BackupParams>,
+) -> Result<impl IntoResponse, BackupError> {
+    let backup = archive::create(&state.db_pool, params.passwords).await?;
+
+    let headers = [
+        (
+            axum::http::header::CONTENT_TYPE,
+            "application/json".to_string(),
+        ),
+        (
+            axum::http::header::CONTENT_DISPOSITION,
+            format!(
+                r#"attachment; filename="shajarah-backup-{}.json""#,
+                backup.created_at.format("%Y-%m-%d")
+            ),
+        ),
+    ];
+
+    let (mut writer, reader) = tokio::io::duplex(64 * 1024);
+    tokio::spawn(async move {
+        if let Err(e) = archive::write(&backup, state.storage.as_ref(), &mut writer).await {
+            log::error!("failed to write the backup: {e:#?}");
+        }
+    });
+
+    Ok((headers, Body::from_stream(ReaderStream::new(reader))))
+}
+
+/// Replace everything with the contents of a backup, see [`archive::restore`]
+///
+/// uploads are limited in size, larger backups can be restored with `server restore`
+pub async fn restore_backup(
+    auth: AuthExtractor<{ UserRole::Admin as u8 }>,
+    State(state): State<Arc<InnerAppState>>,
+    mut multipart: Multipart,
+) -> Result<Json<BackupSummary>, BackupError> {
+    while let Some(field) = multipart
+        .next_field()
+        .await
+        .map_err(|_e| BackupError::BadRequest)?
+    {
+        match field.name() {
+            Some("backup") => {
+                let data = field.bytes().await.map_err(|e| {
+                    log::error!("{e}");
+                    BackupError::BadRequest
+                })?;
+
+                let backup = archive::parse(&data)?;
+                let summary = archive::restore(
+                    &state.db_pool,
+                    state.storage.as_ref(),
+                    &backup,
+                    Some(auth.current_user.id),
+                )
+                .await?;
+
+                return Ok(Json(summary));
+            }
+            Some(_) => {
+                continue;
+            }
+            None => {
+                return Err(BackupError::BadRequest);
+            }
+        }
+    }
+
+    Err(BackupError::BadRequest)
+}
@@ -0,0 +1,244 @@
+use std::collections::HashMap;
+
+use csv::StringRecord;
+use serde::{de::IntoDeserializer, Deserialize};
+
+use crate::Gender;
+
+use super::{
+    dates::PartialDate,
+    models::{CsvRowError, MemberRow, MemberSnapshot},
+};
+
+/// the columns every row needs, the others can be left out
+const REQUIRED_COLUMNS: [&str; 4] = ["id", "name", "last_name", "gender"];
+
+/// A row of a members CSV file
+pub struct CsvRow {
+    /// the line of the row in the file
+    pub row: usize,
+    pub member: MemberRow,
+    /// the existing member the row stands for when merging, from the `existing_id` column
+    pub existing_id: Option<i64>,
+}
+
+/// The rows of a members CSV file that could be read
+pub struct ParsedCsv {
+    pub rows: Vec<CsvRow>,
+    pub errors: Vec<CsvRowError>,
+}
+
+/// Read a members CSV file like the one [`export_members`](super::routes::export_members) writes,
+/// a row that can't be read is reported with its line and column instead of failing the file
+pub fn parse(data: &str) -> ParsedCsv {
+    let mut parsed = ParsedCsv {
+        rows: Vec::new(),
+        errors: Vec::new(),
+    };
+
+    let mut csv_reader = csv::ReaderBuilder::new()
+        .delimiter(b',')
+        .from_reader(data.as_bytes());
+
+    let headers = match csv_reader.headers() {
+        Ok(headers) => headers.clone(),
+        Err(e) => {
+            parsed.errors.push(CsvRowError {
+                row: 1,
+                column: None,
+                message: e.to_string(),
+            });
+            return parsed;
+        }
+    };
+
+    let missing: Vec<CsvRowError> = REQUIRED_COLUMNS
+        .iter()
+        .filter(|column| !headers.iter().any(|h| h == **column))
+        .map(|column| CsvRowError {
+            row: 1,
+            column: Some(column.to_string()),
+            message: String::from("missing column"),
+        })
+        .collect();
+
+    if !missing.is_empty() {
+        parsed.errors = missing;
+        return parsed;
+    }
+
+    // the line of the first row with each id
+    let mut seen: HashMap<i64, usize> = HashMap::new();
+
+    for record in csv_reader.records() {
+        let record = match record {
+            Ok(record) => record,
+            Err(e) => {
+                parsed.errors.push(CsvRowError {
+                    row: e.position().map(|p| p.line() as usize).unwrap_or_default(),
+                    column: None,
+                    message: e.to_string(),
+                });
+                continue;
+            }
+        };
+
+        let row = record
+            .position()
+            .map(|p| p.line() as usize)
+            .unwrap_or_default();
+
+        match parse_row(row, &headers, &record) {
+            Ok(csv_row) => match seen.get(&csv_row.member.id) {
+                Some(first) => parsed.errors.push(CsvRowError {
+                    row,
+                    column: Some(String::from("id")),
+                    message: format!("id {} is already used on row {first}", csv_row.member.id),
+                }),
+                None => {
+                    seen.insert(csv_row.member.id, row);
+                    parsed.rows.push(csv_row);
+                }
+            },
+            Err(errors) => parsed.errors.extend(errors),
+        }
+    }
+
+    parsed
+}
+
+/// A row being read, with the problems found in it so far
+struct Row<'a> {
+    row: usize,
+    headers: &'a StringRecord,
+    record: &'a StringRecord,
+    errors: Vec<CsvRowError>,
+}
+
+impl<'a> Row<'a> {
+    /// the value in a column, `None` if it's empty or the file doesn't have the column
+    fn value(&self, column: &str) -> Option<&'a str> {
+        self.headers
+            .iter()
+            .position(|h| h == column)
+            .and_then(|i| self.record.get(i))
+            .filter(|value| !value.is_empty())
+    }
+
+    fn parse<T, E: ToString>(
+        &mut self,
+        column: &str,
+        parse: impl FnOnce(&str) -> Result<T, E>,
+    ) -> Option<T> {
+        match parse(self.value(column)?) {
+            Ok(value) => Some(value),
+            Err(e) => {
+                self.error(column, e.to_string());
+                None
+            }
+        }
+    }
+
+    fn required<T, E: ToString>(
+        &mut self,
+        column: &str,
+        parse: impl FnOnce(&str) -> Result<T, E>,
+    ) -> Option<T> {
+        if self.value(column).is_none() {
+            self.error(column, String::from("missing value"));
+            return None;
+        }
+
+        self.parse(column, parse)
+    }
+
+    fn error(&mut self, column: &str, message: String) {
+        self.errors.push(CsvRowError {
+            row: self.row,
+            column: Some(column.to_string()),
+            message,
+        });
+    }
+}
+
+/// Read a row column by column, so every value that can't be read is reported
+fn parse_row(
+    row: usize,
+    headers: &StringRecord,
+    record: &StringRecord,
+) -> Result<CsvRow, Vec<CsvRowError>> {
+    let mut r = Row {
+        row,
+        headers,
+        record,
+        errors: Vec::new(),
+    };
+
+    let id = r.required("id", str::parse::<i64>);
+    let name = r.required("name", |v| Ok::<_, String>(v.to_string()));
+    let last_name = r.value("last_name").unwrap_or_default().to_string();
+    let gender = r.required("gender", |v| {
+        Gender::deserialize(v.into_deserializer())
+            .map_err(|e: serde::de::value::Error| e.to_string())
+    });
+    let birthday = r.parse("birthday", str::parse::<PartialDate>);
+    let death_date = r.parse("death_date", str::parse::<PartialDate>);
+    let deceased = r.parse("deceased", str::parse::<bool>);
+    let burial_place = r.value("burial_place").map(ToString::to_string);
+    let mother_id = r.parse("mother_id", str::parse::<i64>);
+    let father_id = r.parse("father_id", str::parse::<i64>);
+    let existing_id = r.parse("existing_id", str::parse::<i64>);
+
+    match (id, name, gender) {
+        (Some(id), Some(name), Some(gender)) if r.errors.is_empty() => Ok(CsvRow {
+            row,
+            member: MemberRow {
+                id,
+                name,
+                last_name,
+                gender,
+                birthday,
+                death_date,
+                deceased: deceased.unwrap_or_default(),
+                burial_place,
+                image_hash: None,
+                image_type: None,
+                personal_info: None,
+                mother_id,
+                father_id,
+                nasab: None,
+                nasab_latin: None,
+            },
+            existing_id,
+        }),
+        _ => Err(r.errors),
+    }
+}
+
+/// The fields a row would change on an existing member
+pub fn changed_fields(row: &MemberRow, existing: &MemberSnapshot) -> Vec<String> {
+    let fields = [
+        ("name", row.name != existing.name),
+        ("last_name", row.last_name != existing.last_name),
+        ("gender", row.gender != existing.gender),
+        // stored dates are gregorian, see `PartialDate`
+        (
+            "birthday",
+            row.birthday.map(|d| d.to_gregorian()) != existing.birthday,
+        ),
+        (
+            "death_date",
+            row.death_date.map(|d| d.to_gregorian()) != existing.death_date,
+        ),
+        ("deceased", row.deceased != existing.deceased),
+        ("burial_place", row.burial_place != existing.burial_place),
+        ("father_id", row.father_id != existing.father_id),
+        ("mother_id", row.mother_id != existing.mother_id),
+    ];
+
+    fields
+        .into_iter()
+        .filter(|(_, changed)| *changed)
+        .map(|(field, _)| field.to_string())
+        .collect()
+}
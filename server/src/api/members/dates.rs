@@ -0,0 +1,484 @@
+use std::{fmt, str::FromStr};
+
+use chrono::{Datelike, Months, NaiveDate};
+use serde_with::{DeserializeFromStr, SerializeDisplay};
+use sqlx::{
+    encode::IsNull,
+    error::BoxDynError,
+    postgres::{PgArgumentBuffer, PgTypeInfo, PgValueRef},
+    Decode, Encode, PgPool, Postgres, Type,
+};
+
+/// how far a "circa" date can be from the given year
+const CIRCA_YEARS: u32 = 10;
+
+/// julian day number of 1 Muharram 1 AH in the civil tabular calendar
+const HIJRI_EPOCH: i32 = 1948440;
+
+/// julian day number of the day before 0001-01-01 in the proleptic gregorian calendar
+const CE_EPOCH: i32 = 1721425;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DateQualifier {
+    #[default]
+    Exact,
+    Circa,
+    Before,
+    After,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Calendar {
+    #[default]
+    Gregorian,
+    /// the tabular (arithmetic) islamic calendar,
+    /// it can be a day or two off from the sighted calendar
+    Hijri,
+}
+
+/// A date that may only be partially known,
+/// e.g. "1932-05-02", "1932-05", "1932", "circa 1932", "before 1932-05" or "after 1932"
+///
+/// hijri dates end with "AH", e.g. "1350-05-12 AH" or "circa 1350 AH",
+/// they're converted to gregorian when they're stored, so stored dates compare
+/// in one calendar, and to hijri again for display
+///
+/// stored and sent over the API as that text
+#[derive(Debug, Clone, Copy, PartialEq, Eq, SerializeDisplay, DeserializeFromStr)]
+pub struct PartialDate {
+    pub qualifier: DateQualifier,
+    pub calendar: Calendar,
+    pub year: i32,
+    pub month: Option<u32>,
+    pub day: Option<u32>,
+}
+
+#[derive(thiserror::Error, Debug)]
+#[error("invalid date: {0}")]
+pub struct InvalidDate(String);
+
+impl PartialDate {
+    pub fn new(
+        qualifier: DateQualifier,
+        calendar: Calendar,
+        year: i32,
+        month: Option<u32>,
+        day: Option<u32>,
+    ) -> Result<Self, InvalidDate> {
+        let date = Self {
+            qualifier,
+            calendar,
+            year,
+            month,
+            day,
+        };
+
+        let valid = match (month, day) {
+            (None, None) => true,
+            (Some(month), None) => (1..=12).contains(&month),
+            (Some(month), Some(day)) => match calendar {
+                Calendar::Gregorian => NaiveDate::from_ymd_opt(year, month, day).is_some(),
+                Calendar::Hijri => {
+                    (1..=12).contains(&month)
+                        && (1..=hijri_month_length(year, month)).contains(&day)
+                }
+            },
+            (None, Some(_)) => false,
+        };
+
+        if !valid || !(1..=9999).contains(&year) {
+            return Err(InvalidDate(date.to_string()));
+        }
+
+        // a hijri date has to be storable as a gregorian one
+        if calendar == Calendar::Hijri && date.last_day().is_none_or(|last| last.year() > 9999) {
+            return Err(InvalidDate(date.to_string()));
+        }
+
+        Ok(date)
+    }
+
+    pub fn is_exact(&self) -> bool {
+        self.qualifier == DateQualifier::Exact
+    }
+
+    /// the first day of the period this date covers, ignoring the qualifier
+    fn first_day(&self) -> Option<NaiveDate> {
+        let month = self.month.unwrap_or(1);
+        let day = self.day.unwrap_or(1);
+
+        match self.calendar {
+            Calendar::Gregorian => NaiveDate::from_ymd_opt(self.year, month, day),
+            Calendar::Hijri => hijri_to_gregorian(self.year, month, day),
+        }
+    }
+
+    /// the last day of the period this date covers, ignoring the qualifier
+    fn last_day(&self) -> Option<NaiveDate> {
+        match (self.calendar, self.month, self.day) {
+            (Calendar::Gregorian, Some(month), Some(day)) => {
+                NaiveDate::from_ymd_opt(self.year, month, day)
+            }
+            (Calendar::Gregorian, Some(month), None) => {
+                NaiveDate::from_ymd_opt(self.year, month, 1)?
+                    .checked_add_months(Months::new(1))?
+                    .pred_opt()
+            }
+            (Calendar::Gregorian, None, _) => NaiveDate::from_ymd_opt(self.year, 12, 31),
+            (Calendar::Hijri, month, day) => {
+                let month = month.unwrap_or(12);
+                let day = day.unwrap_or_else(|| hijri_month_length(self.year, month));
+                hijri_to_gregorian(self.year, month, day)
+            }
+        }
+    }
+
+    /// the first day this date could be, `None` if there's no lower bound
+    pub fn earliest(&self) -> Option<NaiveDate> {
+        let first = self.first_day()?;
+
+        match self.qualifier {
+            DateQualifier::Exact | DateQualifier::After => Some(first),
+            DateQualifier::Circa => first.checked_sub_months(Months::new(CIRCA_YEARS * 12)),
+            DateQualifier::Before => None,
+        }
+    }
+
+    /// the last day this date could be, `None` if there's no upper bound
+    pub fn latest(&self) -> Option<NaiveDate> {
+        let last = self.last_day()?;
+
+        match self.qualifier {
+            DateQualifier::Exact | DateQualifier::Before => Some(last),
+            DateQualifier::Circa => last.checked_add_months(Months::new(CIRCA_YEARS * 12)),
+            DateQualifier::After => None,
+        }
+    }
+
+    /// whether this date can't possibly come after `other`
+    pub fn is_certainly_before(&self, other: &PartialDate) -> bool {
+        matches!((self.latest(), other.earliest()), (Some(a), Some(b)) if a < b)
+    }
+
+    /// The same date in the given calendar
+    ///
+    /// full dates convert exactly, a month or a year overlaps two of them
+    /// in the other calendar, so the one holding the middle of the period is used
+    pub fn to_calendar(&self, calendar: Calendar) -> PartialDate {
+        if self.calendar == calendar {
+            return *self;
+        }
+
+        let (Some(first), Some(last)) = (self.first_day(), self.last_day()) else {
+            return *self;
+        };
+        let middle = first + (last - first) / 2;
+
+        let (year, month, day) = match calendar {
+            Calendar::Gregorian => (middle.year(), middle.month(), middle.day()),
+            Calendar::Hijri => gregorian_to_hijri(middle),
+        };
+
+        PartialDate {
+            qualifier: self.qualifier,
+            calendar,
+            year,
+            month: self.month.map(|_| month),
+            day: self.day.map(|_| day),
+        }
+    }
+
+    pub fn to_gregorian(&self) -> PartialDate {
+        self.to_calendar(Calendar::Gregorian)
+    }
+
+    /// `None` before the hijra, which has no hijri year
+    pub fn to_hijri(&self) -> Option<PartialDate> {
+        Some(self.to_calendar(Calendar::Hijri)).filter(|date| date.year >= 1)
+    }
+}
+
+fn is_hijri_leap_year(year: i32) -> bool {
+    (14 + 11 * year).rem_euclid(30) < 11
+}
+
+fn hijri_month_length(year: i32, month: u32) -> u32 {
+    if month % 2 == 1 || (month == 12 && is_hijri_leap_year(year)) {
+        30
+    } else {
+        29
+    }
+}
+
+fn hijri_to_julian_day(year: i32, month: u32, day: u32) -> i32 {
+    let month = month as i32;
+
+    day as i32
+        + (59 * (month - 1) + 1) / 2
+        + (year - 1) * 354
+        + (3 + 11 * year).div_euclid(30)
+        + HIJRI_EPOCH
+        - 1
+}
+
+fn hijri_to_gregorian(year: i32, month: u32, day: u32) -> Option<NaiveDate> {
+    NaiveDate::from_num_days_from_ce_opt(hijri_to_julian_day(year, month, day) - CE_EPOCH)
+}
+
+fn gregorian_to_hijri(date: NaiveDate) -> (i32, u32, u32) {
+    let julian_day = date.num_days_from_ce() + CE_EPOCH;
+
+    let year = (30 * (julian_day - HIJRI_EPOCH) + 10646).div_euclid(10631);
+    let month = (1..=12)
+        .rev()
+        .find(|month| hijri_to_julian_day(year, *month, 1) <= julian_day)
+        .unwrap_or(1);
+    let day = julian_day - hijri_to_julian_day(year, month, 1) + 1;
+
+    (year, month, day as u32)
+}
+
+impl From<NaiveDate> for PartialDate {
+    fn from(date: NaiveDate) -> Self {
+        Self {
+            qualifier: DateQualifier::Exact,
+            calendar: Calendar::Gregorian,
+            year: date.year(),
+            month: Some(date.month()),
+            day: Some(date.day()),
+        }
+    }
+}
+
+impl fmt::Display for PartialDate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.qualifier {
+            DateQualifier::Exact => {}
+            DateQualifier::Circa => write!(f, "circa ")?,
+            DateQualifier::Before => write!(f, "before ")?,
+            DateQualifier::After => write!(f, "after ")?,
+        }
+
+        write!(f, "{:04}", self.year)?;
+
+        if let Some(month) = self.month {
+            write!(f, "-{month:02}")?;
+        }
+
+        if let Some(day) = self.day {
+            write!(f, "-{day:02}")?;
+        }
+
+        if self.calendar == Calendar::Hijri {
+            write!(f, " AH")?;
+        }
+
+        Ok(())
+    }
+}
+
+impl FromStr for PartialDate {
+    type Err = InvalidDate;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let invalid = || InvalidDate(s.to_string());
+
+        // old exports have full timestamps, only the date part is kept
+        if let Ok(timestamp) = chrono::DateTime::parse_from_rfc3339(s) {
+            return Ok(timestamp.date_naive().into());
+        }
+
+        let prefixes = [
+            ("circa", DateQualifier::Circa),
+            ("c.", DateQualifier::Circa),
+            ("~", DateQualifier::Circa),
+            ("حوالي", DateQualifier::Circa),
+            ("before", DateQualifier::Before),
+            ("<", DateQualifier::Before),
+            ("قبل", DateQualifier::Before),
+            ("after", DateQualifier::After),
+            (">", DateQualifier::After),
+            ("بعد", DateQualifier::After),
+        ];
+
+        let (qualifier, date) = prefixes
+            .iter()
+            .find_map(|(prefix, qualifier)| {
+                s.get(..prefix.len())
+                    .filter(|p| p.eq_ignore_ascii_case(prefix))
+                    .map(|_| (*qualifier, s[prefix.len()..].trim_start()))
+            })
+            .unwrap_or((DateQualifier::Exact, s));
+
+        let (calendar, date) = ["AH", "هـ", "ه"]
+            .iter()
+            .find_map(|suffix| {
+                let start = date.len().checked_sub(suffix.len())?;
+                date.get(start..)
+                    .filter(|s| s.eq_ignore_ascii_case(suffix))
+                    .map(|_| &date[..start])
+            })
+            .map(|date| (Calendar::Hijri, date.trim_end()))
+            .unwrap_or((Calendar::Gregorian, date));
+
+        let mut parts = date.split('-');
+
+        let year = parts
+            .next()
+            .filter(|y| !y.is_empty())
+            .ok_or_else(invalid)?
+            .parse()
+            .map_err(|_| invalid())?;
+        let month = parts
+            .next()
+            .map(|m| m.parse().map_err(|_| invalid()))
+            .transpose()?;
+        let day = parts
+            .next()
+            .map(|d| d.parse().map_err(|_| invalid()))
+            .transpose()?;
+
+        if parts.next().is_some() {
+            return Err(invalid());
+        }
+
+        PartialDate::new(qualifier, calendar, year, month, day)
+    }
+}
+
+impl Type<Postgres> for PartialDate {
+    fn type_info() -> PgTypeInfo {
+        <String as Type<Postgres>>::type_info()
+    }
+
+    fn compatible(ty: &PgTypeInfo) -> bool {
+        <String as Type<Postgres>>::compatible(ty)
+    }
+}
+
+impl Encode<'_, Postgres> for PartialDate {
+    fn encode_by_ref(&self, buf: &mut PgArgumentBuffer) -> Result<IsNull, BoxDynError> {
+        <String as Encode<Postgres>>::encode(self.to_gregorian().to_string(), buf)
+    }
+}
+
+impl<'r> Decode<'r, Postgres> for PartialDate {
+    fn decode(value: PgValueRef<'r>) -> Result<Self, BoxDynError> {
+        Ok(<&str as Decode<Postgres>>::decode(value)?.parse()?)
+    }
+}
+
+/// The columns that hold partial dates, as (table, column)
+const DATE_COLUMNS: [(&str, &str); 5] = [
+    ("members", "birthday"),
+    ("members", "death_date"),
+    ("member_add_requests", "birthday"),
+    ("member_add_requests", "death_date"),
+    ("member_media", "date"),
+];
+
+/// Convert the hijri dates that were stored as they were entered,
+/// before dates were converted to gregorian when they're stored
+pub async fn convert_stored_hijri_dates(db_pool: &PgPool) -> Result<(), sqlx::Error> {
+    for (table, column) in DATE_COLUMNS {
+        let dates: Vec<String> = sqlx::query_scalar(&format!(
+            "SELECT DISTINCT {column} FROM {table} WHERE {column} LIKE '%AH'"
+        ))
+        .fetch_all(db_pool)
+        .await?;
+
+        for stored in dates {
+            let Ok(date) = stored.parse::<PartialDate>() else {
+                log::warn!("{table}.{column} has an invalid date: {stored}");
+                continue;
+            };
+
+            sqlx::query(&format!(
+                "UPDATE {table} SET {column} = $1 WHERE {column} = $2"
+            ))
+            .bind(date)
+            .bind(&stored)
+            .execute(db_pool)
+            .await?;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(s: &str) -> PartialDate {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn hijri_epoch() {
+        assert_eq!(date("0001-01-01 AH").to_gregorian(), date("0622-07-19"));
+        assert_eq!(date("0622-07-19").to_hijri(), Some(date("0001-01-01 AH")));
+        assert_eq!(date("0622-07-18").to_hijri(), None);
+        assert_eq!(date("0500").to_hijri(), None);
+    }
+
+    #[test]
+    fn hijri_new_year() {
+        assert_eq!(date("1446-01-01 AH").to_gregorian(), date("2024-07-08"));
+        assert_eq!(date("2024-07-08").to_hijri(), Some(date("1446-01-01 AH")));
+        assert_eq!(date("2024-07-07").to_hijri(), Some(date("1445-12-30 AH")));
+    }
+
+    #[test]
+    fn full_dates_round_trip() {
+        for s in ["1932-05-02", "1999-12-31", "2000-02-29", "2024-03-11"] {
+            assert_eq!(date(s).to_hijri().unwrap().to_gregorian(), date(s));
+        }
+    }
+
+    #[test]
+    fn partial_dates_take_the_middle_of_the_period() {
+        // 1932 runs from the middle of 1350 AH to the middle of 1351 AH
+        assert_eq!(date("1932").to_hijri(), Some(date("1351 AH")));
+        assert_eq!(date("circa 1350 AH").to_gregorian(), date("circa 1931"));
+        assert_eq!(date("1932-05").to_hijri().unwrap().month, Some(1));
+    }
+
+    #[test]
+    fn hijri_suffixes() {
+        for s in ["1350 AH", "1350 ah", "1350AH", "1350 هـ", "1350 ه"] {
+            assert_eq!(date(s), date("1350 AH"), "{s}");
+        }
+        assert_eq!(date("1350 AH").to_string(), "1350 AH");
+        assert_eq!(date("قبل 1350-05 هـ").to_string(), "before 1350-05 AH");
+    }
+
+    #[test]
+    fn invalid_dates() {
+        for s in [
+            "",
+            "AH",
+            "1932-13",
+            "1932-02-30",
+            "1350-02-30 AH",
+            "9999 AH",
+            "1932--01",
+            "soon",
+        ] {
+            assert!(s.parse::<PartialDate>().is_err(), "{s}");
+        }
+    }
+
+    #[test]
+    fn qualifiers_widen_the_range() {
+        assert!(date("1930").is_certainly_before(&date("1931")));
+        assert!(!date("circa 1930").is_certainly_before(&date("1931")));
+        assert!(!date("after 1930").is_certainly_before(&date("1990")));
+        assert_eq!(date("before 1930").earliest(), None);
+        assert_eq!(
+            date("circa 1930").earliest(),
+            NaiveDate::from_ymd_opt(1920, 1, 1)
+        );
+    }
+}
@@ -0,0 +1,1264 @@
+use std::collections::{HashMap, HashSet};
+
+use chrono::{DateTime, Datelike, NaiveDate, Utc};
+use indexmap::IndexMap;
+
+use crate::Gender;
+
+use super::{
+    dates::{Calendar, DateQualifier, PartialDate},
+    models::{
+        GedcomImportResponse, GedcomProblem, MarriageEndReason, MarriageResponse, MediaRow,
+        MemberSnapshot, UnmappedTag,
+    },
+    transliteration::to_latin,
+};
+
+/// the personal info keys that GEDCOM facts with no member column are kept under
+pub const BIRTH_PLACE: &str = "مكان الميلاد";
+pub const DEATH_PLACE: &str = "مكان الوفاة";
+pub const OCCUPATION: &str = "المهنة";
+pub const NOTES: &str = "ملاحظات";
+
+const MONTHS: [&str; 12] = [
+    "JAN", "FEB", "MAR", "APR", "MAY", "JUN", "JUL", "AUG", "SEP", "OCT", "NOV", "DEC",
+];
+
+/// A line of a GEDCOM file with the lines nested under it,
+/// CONT and CONC lines are already joined into the value
+#[derive(Debug)]
+pub struct Record {
+    pub line: usize,
+    pub xref: Option<String>,
+    pub tag: String,
+    pub value: String,
+    pub children: Vec<Record>,
+}
+
+impl Record {
+    /// the xref this record points to, e.g. "I1" for "1 HUSB @I1@"
+    fn pointer(&self) -> Option<&str> {
+        self.value
+            .strip_prefix('@')
+            .and_then(|v| v.strip_suffix('@'))
+            .filter(|v| !v.is_empty())
+    }
+}
+
+/// An INDI record mapped to the fields of a member
+#[derive(Debug, Default)]
+pub struct Individual {
+    pub line: usize,
+    pub xref: String,
+    pub name: String,
+    pub last_name: String,
+    pub gender: Option<Gender>,
+    pub birthday: Option<PartialDate>,
+    pub death_date: Option<PartialDate>,
+    pub deceased: bool,
+    pub burial_place: Option<String>,
+    pub personal_info: IndexMap<String, String>,
+    pub father: Option<String>,
+    pub mother: Option<String>,
+}
+
+/// A FAM record with both spouses
+#[derive(Debug)]
+pub struct Marriage {
+    pub husband: String,
+    pub wife: String,
+    pub married_at: Option<NaiveDate>,
+    pub ended_at: Option<NaiveDate>,
+    pub end_reason: Option<MarriageEndReason>,
+}
+
+#[derive(Debug, Default)]
+pub struct Gedcom {
+    pub individuals: Vec<Individual>,
+    pub marriages: Vec<Marriage>,
+}
+
+#[derive(Default)]
+struct Family {
+    line: usize,
+    husband: Option<String>,
+    wife: Option<String>,
+    children: Vec<String>,
+    married_at: Option<NaiveDate>,
+    ended_at: Option<NaiveDate>,
+    divorced: bool,
+}
+
+/// Split a GEDCOM file into its records, lines that can't be read are reported and skipped
+pub fn parse(text: &str, problems: &mut Vec<GedcomProblem>) -> Vec<Record> {
+    let mut records = Vec::new();
+    // the records that later lines can still be nested under, one per level
+    let mut open: Vec<Record> = Vec::new();
+
+    fn close(open: &mut Vec<Record>, records: &mut Vec<Record>, level: usize) {
+        while open.len() > level {
+            let record = open.pop().expect("more open records than the level");
+            match open.last_mut() {
+                Some(parent) => parent.children.push(record),
+                None => records.push(record),
+            }
+        }
+    }
+
+    for (i, line) in text.trim_start_matches('\u{feff}').lines().enumerate() {
+        let number = i + 1;
+        // the end of a value is kept, a CONC line can be split right before a space
+        let line = line.trim_start();
+
+        if line.trim_end().is_empty() {
+            continue;
+        }
+
+        let Some((level, rest)) = line
+            .split_once(' ')
+            .and_then(|(level, rest)| Some((level.parse::<usize>().ok()?, rest)))
+        else {
+            problems.push(GedcomProblem::new(number, "not a GEDCOM line"));
+            continue;
+        };
+
+        if level > open.len() {
+            problems.push(GedcomProblem::new(
+                number,
+                "line is nested deeper than the line before it",
+            ));
+            continue;
+        }
+
+        close(&mut open, &mut records, level);
+
+        let (xref, rest) = match rest.strip_prefix('@') {
+            Some(rest) => match rest.split_once("@ ") {
+                Some((xref, rest)) => (Some(xref.to_string()), rest),
+                None => (Some(rest.trim_end_matches('@').to_string()), ""),
+            },
+            None => (None, rest),
+        };
+
+        let rest = match rest.split_once(' ') {
+            Some(("CONC", _)) => rest,
+            _ => rest.trim_end(),
+        };
+        let (tag, value) = rest.split_once(' ').unwrap_or((rest, ""));
+        // 5.5.1 escapes @ in text as @@
+        let value = value.replace("@@", "@");
+
+        match (tag, open.last_mut()) {
+            ("CONT", Some(parent)) => {
+                parent.value.push('\n');
+                parent.value.push_str(&value);
+            }
+            ("CONC", Some(parent)) => parent.value.push_str(&value),
+            _ => open.push(Record {
+                line: number,
+                xref,
+                tag: tag.to_string(),
+                value,
+                children: Vec::new(),
+            }),
+        }
+    }
+
+    close(&mut open, &mut records, 0);
+
+    records
+}
+
+/// Parse a GEDCOM date, ranges and periods keep their first date
+///
+/// only gregorian dates are read, other calendars are errors
+pub fn parse_date(value: &str) -> Result<PartialDate, String> {
+    let mut words: Vec<&str> = value.split_whitespace().collect();
+
+    // a phrase in parentheses only describes the date
+    if let Some(i) = words.iter().position(|w| w.starts_with('(')) {
+        words.truncate(i);
+    }
+
+    match words.first() {
+        Some(&"@#DGREGORIAN@") | Some(&"GREGORIAN") => {
+            words.remove(0);
+        }
+        Some(calendar)
+            if calendar.starts_with("@#")
+                || calendar.starts_with('_')
+                || ["JULIAN", "HEBREW", "FRENCH_R"].contains(calendar) =>
+        {
+            return Err(format!("{value} isn't in the gregorian calendar"));
+        }
+        _ => {}
+    }
+
+    let qualifier = match words.first() {
+        Some(&"ABT") | Some(&"CAL") | Some(&"EST") => Some(DateQualifier::Circa),
+        Some(&"BEF") | Some(&"TO") => Some(DateQualifier::Before),
+        Some(&"AFT") | Some(&"BET") => Some(DateQualifier::After),
+        Some(&"FROM") | Some(&"INT") => Some(DateQualifier::Exact),
+        _ => None,
+    };
+
+    if qualifier.is_some() {
+        words.remove(0);
+    }
+
+    if let Some(i) = words.iter().position(|w| *w == "AND" || *w == "TO") {
+        words.truncate(i);
+    }
+
+    let invalid = || format!("{value} isn't a date");
+
+    let month = |month: &str| {
+        MONTHS
+            .iter()
+            .position(|m| m.eq_ignore_ascii_case(month))
+            .map(|m| m as u32 + 1)
+            .ok_or_else(invalid)
+    };
+
+    let (day, month, year) = match words.as_slice() {
+        [year] => (None, None, *year),
+        [m, year] => (None, Some(month(m)?), *year),
+        [day, m, year] => (
+            Some(day.parse::<u32>().map_err(|_| invalid())?),
+            Some(month(m)?),
+            *year,
+        ),
+        _ => return Err(invalid()),
+    };
+
+    // dual years like 1750/51 are kept as the first one
+    let year = year
+        .split('/')
+        .next()
+        .and_then(|y| y.parse::<i32>().ok())
+        .ok_or_else(invalid)?;
+
+    PartialDate::new(
+        qualifier.unwrap_or_default(),
+        Calendar::Gregorian,
+        year,
+        month,
+        day,
+    )
+    .map_err(|e| e.to_string())
+}
+
+/// "Given /Surname/ Suffix" to the given names and the surname
+fn split_name(value: &str) -> (String, String) {
+    match value.split_once('/') {
+        Some((given, rest)) => {
+            let (surname, suffix) = rest.split_once('/').unwrap_or((rest, ""));
+            let given = format!("{} {}", given.trim(), suffix.trim());
+
+            (given.trim().to_string(), surname.trim().to_string())
+        }
+        None => (value.trim().to_string(), String::new()),
+    }
+}
+
+struct Reader<'a> {
+    /// NOTE and SNOTE records that notes can point to
+    notes: HashMap<&'a str, &'a Record>,
+    unmapped: IndexMap<String, UnmappedTag>,
+    problems: Vec<GedcomProblem>,
+}
+
+impl<'a> Reader<'a> {
+    fn unmapped(&mut self, path: &str, record: &Record) {
+        let path = match path {
+            "" => record.tag.clone(),
+            path => format!("{path}.{}", record.tag),
+        };
+
+        self.unmapped
+            .entry(path.clone())
+            .or_insert(UnmappedTag {
+                path,
+                count: 0,
+                first_line: record.line,
+            })
+            .count += 1;
+    }
+
+    fn note(&self, record: &Record) -> String {
+        match record.pointer().and_then(|xref| self.notes.get(xref)) {
+            Some(note) => note.value.clone(),
+            None => record.value.clone(),
+        }
+    }
+
+    fn date(&mut self, record: &Record) -> Option<PartialDate> {
+        match parse_date(&record.value) {
+            Ok(date) => Some(date),
+            Err(e) => {
+                self.problems.push(GedcomProblem::new(record.line, &e));
+                None
+            }
+        }
+    }
+
+    /// a full exact date, marriages don't keep partial dates
+    fn full_date(&mut self, record: &Record) -> Option<NaiveDate> {
+        let date = self.date(record)?;
+
+        let full = match (date.is_exact(), date.month, date.day) {
+            (true, Some(month), Some(day)) => NaiveDate::from_ymd_opt(date.year, month, day),
+            _ => None,
+        };
+
+        if full.is_none() {
+            self.problems.push(GedcomProblem::new(
+                record.line,
+                &format!(
+                    "{} isn't a full date, marriages only keep full dates",
+                    record.value
+                ),
+            ));
+        }
+
+        full
+    }
+
+    /// the date and place of an event like BIRT or DEAT
+    fn event(&mut self, path: &str, event: &Record) -> (Option<PartialDate>, Option<String>) {
+        let path = format!("{path}.{}", event.tag);
+        let mut date = None;
+        let mut place = None;
+
+        for child in &event.children {
+            match child.tag.as_str() {
+                "DATE" => date = self.date(child),
+                "PLAC" if !child.value.is_empty() => place = Some(child.value.clone()),
+                _ => self.unmapped(&path, child),
+            }
+        }
+
+        (date, place)
+    }
+
+    fn individual(&mut self, record: &Record) -> Individual {
+        let mut individual = Individual {
+            line: record.line,
+            xref: record.xref.clone().unwrap_or_default(),
+            ..Default::default()
+        };
+        let mut notes = Vec::new();
+
+        for child in &record.children {
+            match child.tag.as_str() {
+                // other names are alternatives, like a married name
+                "NAME" if individual.name.is_empty() => {
+                    let (mut given, mut surname) = split_name(&child.value);
+
+                    for part in &child.children {
+                        match part.tag.as_str() {
+                            "GIVN" => given = part.value.clone(),
+                            "SURN" => surname = part.value.clone(),
+                            _ => self.unmapped("INDI.NAME", part),
+                        }
+                    }
+
+                    individual.name = given;
+                    individual.last_name = surname;
+                }
+                "SEX" => {
+                    individual.gender = match child.value.as_str() {
+                        "M" => Some(Gender::Male),
+                        "F" => Some(Gender::Female),
+                        _ => None,
+                    }
+                }
+                "BIRT" => {
+                    let (date, place) = self.event("INDI", child);
+                    individual.birthday = date;
+                    if let Some(place) = place {
+                        individual
+                            .personal_info
+                            .insert(BIRTH_PLACE.to_string(), place);
+                    }
+                }
+                "DEAT" => {
+                    let (date, place) = self.event("INDI", child);
+                    individual.deceased = true;
+                    individual.death_date = date;
+                    if let Some(place) = place {
+                        individual
+                            .personal_info
+                            .insert(DEATH_PLACE.to_string(), place);
+                    }
+                }
+                "BURI" => {
+                    let (_, place) = self.event("INDI", child);
+                    individual.burial_place = place;
+                }
+                "OCCU" if !child.value.is_empty() => {
+                    individual
+                        .personal_info
+                        .insert(OCCUPATION.to_string(), child.value.clone());
+                }
+                "NOTE" | "SNOTE" => notes.push(self.note(child)),
+                // the families are read from the FAM records
+                "FAMC" | "FAMS" => {}
+                _ => self.unmapped("INDI", child),
+            }
+        }
+
+        if !notes.is_empty() {
+            individual
+                .personal_info
+                .insert(NOTES.to_string(), notes.join("\n"));
+        }
+
+        individual
+    }
+
+    fn family(&mut self, record: &Record) -> Family {
+        let mut family = Family {
+            line: record.line,
+            ..Default::default()
+        };
+
+        for child in &record.children {
+            match (child.tag.as_str(), child.pointer()) {
+                ("HUSB", Some(xref)) => family.husband = Some(xref.to_string()),
+                ("WIFE", Some(xref)) => family.wife = Some(xref.to_string()),
+                ("CHIL", Some(xref)) => {
+                    family.children.push(xref.to_string());
+
+                    for detail in &child.children {
+                        self.unmapped("FAM.CHIL", detail);
+                    }
+                }
+                ("MARR", _) => {
+                    for detail in &child.children {
+                        match detail.tag.as_str() {
+                            "DATE" => family.married_at = self.full_date(detail),
+                            _ => self.unmapped("FAM.MARR", detail),
+                        }
+                    }
+                }
+                ("DIV", _) => {
+                    family.divorced = true;
+
+                    for detail in &child.children {
+                        match detail.tag.as_str() {
+                            "DATE" => family.ended_at = self.full_date(detail),
+                            _ => self.unmapped("FAM.DIV", detail),
+                        }
+                    }
+                }
+                _ => self.unmapped("FAM", child),
+            }
+        }
+
+        family
+    }
+}
+
+/// Read the individuals and families of a GEDCOM 5.5.1 or 7.0 file,
+/// anything that can't be mapped to a member or a marriage is added to the report
+pub fn read(text: &str, report: &mut GedcomImportResponse) -> Gedcom {
+    let mut problems = Vec::new();
+    let records = parse(text, &mut problems);
+
+    let mut reader = Reader {
+        notes: records
+            .iter()
+            .filter(|r| r.tag == "NOTE" || r.tag == "SNOTE")
+            .filter_map(|r| Some((r.xref.as_deref()?, r)))
+            .collect(),
+        unmapped: IndexMap::new(),
+        problems,
+    };
+
+    let mut individuals = Vec::new();
+    let mut families = Vec::new();
+    let mut xrefs = HashSet::new();
+
+    for record in &records {
+        match (record.tag.as_str(), record.xref.as_deref()) {
+            ("INDI", Some(xref)) if !xrefs.insert(xref) => {
+                reader.problems.push(GedcomProblem::new(
+                    record.line,
+                    &format!("{xref} is used by another individual, this one was skipped"),
+                ));
+            }
+            ("INDI", Some(_)) => individuals.push(reader.individual(record)),
+            ("FAM", _) => families.push(reader.family(record)),
+            // the header, the submitter, notes that were pointed to and the end of the file
+            ("HEAD" | "SUBM" | "NOTE" | "SNOTE" | "TRLR", _) => {}
+            _ => reader.unmapped("", record),
+        }
+    }
+
+    let roles: HashMap<&str, Gender> = families
+        .iter()
+        .flat_map(|f| {
+            [
+                f.husband.as_deref().map(|h| (h, Gender::Male)),
+                f.wife.as_deref().map(|w| (w, Gender::Female)),
+            ]
+        })
+        .flatten()
+        .collect();
+
+    // the sex of a spouse is known from their place in the family
+    individuals.retain_mut(|individual| {
+        individual.gender = individual
+            .gender
+            .or_else(|| roles.get(individual.xref.as_str()).copied());
+
+        if individual.gender.is_none() {
+            reader.problems.push(GedcomProblem::new(
+                individual.line,
+                &format!("{} has no sex and was skipped", individual.xref),
+            ));
+        }
+
+        if individual.name.is_empty() {
+            individual.name = String::from("؟");
+            reader.problems.push(GedcomProblem::new(
+                individual.line,
+                &format!("{} has no name", individual.xref),
+            ));
+        }
+
+        individual.gender.is_some()
+    });
+
+    let index: HashMap<String, usize> = individuals
+        .iter()
+        .enumerate()
+        .map(|(i, individual)| (individual.xref.clone(), i))
+        .collect();
+
+    let mut marriages = Vec::new();
+
+    for family in &families {
+        let mut known = |xref: &Option<String>| match xref {
+            Some(xref) if index.contains_key(xref) => Some(xref.clone()),
+            Some(xref) => {
+                reader.problems.push(GedcomProblem::new(
+                    family.line,
+                    &format!("{xref} isn't an imported individual"),
+                ));
+                None
+            }
+            None => None,
+        };
+
+        let husband = known(&family.husband);
+        let wife = known(&family.wife);
+
+        for child in &family.children {
+            let Some(i) = index.get(child) else {
+                reader.problems.push(GedcomProblem::new(
+                    family.line,
+                    &format!("{child} isn't an imported individual"),
+                ));
+                continue;
+            };
+
+            let individual = &mut individuals[*i];
+
+            // a member has one set of parents, the first family they're a child in
+            if individual.father.is_some() || individual.mother.is_some() {
+                reader.problems.push(GedcomProblem::new(
+                    family.line,
+                    &format!("{child} is already the child of another family"),
+                ));
+                continue;
+            }
+
+            individual.father = husband.clone();
+            individual.mother = wife.clone();
+        }
+
+        if let Some((husband, wife)) = husband.zip(wife) {
+            let husband_gender = individuals[index[&husband]].gender;
+            let wife_gender = individuals[index[&wife]].gender;
+
+            // the parent links are checked with the rest of the tree when they're imported
+            if husband_gender != Some(Gender::Male) || wife_gender != Some(Gender::Female) {
+                reader.problems.push(GedcomProblem::new(
+                    family.line,
+                    &format!("{husband} and {wife} aren't a husband and a wife, the marriage was skipped"),
+                ));
+                continue;
+            }
+
+            let widowed =
+                individuals[index[&husband]].deceased || individuals[index[&wife]].deceased;
+
+            marriages.push(Marriage {
+                husband,
+                wife,
+                married_at: family.married_at,
+                ended_at: family.ended_at,
+                end_reason: if family.divorced {
+                    Some(MarriageEndReason::Divorce)
+                } else if widowed {
+                    Some(MarriageEndReason::Death)
+                } else {
+                    None
+                },
+            });
+        }
+    }
+
+    // family names usually only appear on the first generation that's recorded
+    loop {
+        let inherited: Vec<(usize, String)> = individuals
+            .iter()
+            .enumerate()
+            .filter(|(_, i)| i.last_name.is_empty())
+            .filter_map(|(i, individual)| {
+                let father = &individuals[index[individual.father.as_ref()?]];
+                Some((i, father.last_name.clone())).filter(|(_, name)| !name.is_empty())
+            })
+            .collect();
+
+        if inherited.is_empty() {
+            break;
+        }
+
+        for (i, last_name) in inherited {
+            individuals[i].last_name = last_name;
+        }
+    }
+
+    report.unmapped_tags = reader.unmapped.into_values().collect();
+    report.problems = reader.problems;
+
+    Gedcom {
+        individuals,
+        marriages,
+    }
+}
+
+/// GEDCOM lines are limited to 255 characters, longer values are continued with CONC
+const MAX_VALUE_CHARS: usize = 200;
+
+/// Format a date the way GEDCOM writes it, e.g. "ABT 12 MAR 1932"
+///
+/// hijri dates are converted to gregorian, exact ones keep the original as a phrase
+pub fn format_date(date: &PartialDate) -> String {
+    let gregorian = date.to_gregorian();
+
+    let mut value = String::new();
+
+    if let Some(day) = gregorian.day {
+        value.push_str(&format!("{day} "));
+    }
+    if let Some(month) = gregorian.month {
+        value.push_str(&format!("{} ", MONTHS[month as usize - 1]));
+    }
+    value.push_str(&gregorian.year.to_string());
+
+    match (date.qualifier, date.calendar) {
+        (DateQualifier::Exact, Calendar::Hijri) => format!("INT {value} ({date})"),
+        (DateQualifier::Exact, Calendar::Gregorian) => value,
+        (DateQualifier::Circa, _) => format!("ABT {value}"),
+        (DateQualifier::Before, _) => format!("BEF {value}"),
+        (DateQualifier::After, _) => format!("AFT {value}"),
+    }
+}
+
+fn full_date(date: NaiveDate) -> PartialDate {
+    PartialDate {
+        qualifier: DateQualifier::Exact,
+        calendar: Calendar::Gregorian,
+        year: date.year(),
+        month: Some(date.month()),
+        day: Some(date.day()),
+    }
+}
+
+/// where to split off the next line of a long value, away from spaces
+/// since readers often trim the lines they read
+fn split_point(chars: &[char]) -> usize {
+    if chars.len() <= MAX_VALUE_CHARS {
+        return chars.len();
+    }
+
+    (1..=MAX_VALUE_CHARS)
+        .rev()
+        .find(|&i| !chars[i - 1].is_whitespace() && !chars[i].is_whitespace())
+        .unwrap_or(MAX_VALUE_CHARS)
+}
+
+struct Writer {
+    out: String,
+}
+
+impl Writer {
+    /// a record with an xref, e.g. "0 @I1@ INDI"
+    fn record(&mut self, tag: &str, xref: &str) {
+        self.out.push_str(&format!("0 @{xref}@ {tag}\n"));
+    }
+
+    /// a line pointing to a record, e.g. "1 FAMS @F1@"
+    fn pointer(&mut self, level: usize, tag: &str, xref: &str) {
+        self.out.push_str(&format!("{level} {tag} @{xref}@\n"));
+    }
+
+    /// a line with a text value, split into CONT and CONC lines when needed
+    fn line(&mut self, level: usize, tag: &str, value: &str) {
+        let value = value.replace('@', "@@");
+
+        for (i, text) in value.split('\n').enumerate() {
+            let mut chars: &[char] = &text.chars().collect::<Vec<char>>();
+
+            let (first, rest) = chars.split_at(split_point(chars));
+            match i {
+                0 => self.push(level, tag, &String::from_iter(first)),
+                _ => self.push(level + 1, "CONT", &String::from_iter(first)),
+            }
+            chars = rest;
+
+            while !chars.is_empty() {
+                let (chunk, rest) = chars.split_at(split_point(chars));
+                self.push(level + 1, "CONC", &String::from_iter(chunk));
+                chars = rest;
+            }
+        }
+    }
+
+    fn push(&mut self, level: usize, tag: &str, value: &str) {
+        match value {
+            "" => self.out.push_str(&format!("{level} {tag}\n")),
+            value => self.out.push_str(&format!("{level} {tag} {value}\n")),
+        }
+    }
+
+    /// an event like BIRT with its date and place
+    fn event(&mut self, tag: &str, date: Option<&PartialDate>, place: Option<&str>) {
+        self.line(1, tag, "");
+
+        if let Some(date) = date {
+            self.line(2, "DATE", &format_date(date));
+        }
+        if let Some(place) = place {
+            self.line(2, "PLAC", place);
+        }
+    }
+}
+
+/// A couple and their children, or the children of a single known parent
+struct Household<'a> {
+    husband: Option<i64>,
+    wife: Option<i64>,
+    marriage: Option<&'a MarriageResponse>,
+    children: Vec<i64>,
+}
+
+/// Write members and their marriages as a GEDCOM 5.5.1 file
+///
+/// personal info is written to the tags [`read`] takes it from, anything else is kept as notes,
+/// media are referenced by their url on this server, and the nasab of a member
+/// from `nasabs` is an extra name with its transliteration
+pub fn write(
+    members: &[MemberSnapshot],
+    nasabs: &HashMap<i64, String>,
+    marriages: &[MarriageResponse],
+    media: &[MediaRow],
+    exported_at: NaiveDate,
+) -> String {
+    let known: HashSet<i64> = members.iter().map(|m| m.id).collect();
+
+    let mut families: Vec<Household> = Vec::new();
+    let mut couples: HashMap<(Option<i64>, Option<i64>), usize> = HashMap::new();
+
+    for marriage in marriages
+        .iter()
+        .filter(|m| known.contains(&m.husband_id) && known.contains(&m.wife_id))
+    {
+        couples
+            .entry((Some(marriage.husband_id), Some(marriage.wife_id)))
+            .or_insert(families.len());
+
+        families.push(Household {
+            husband: Some(marriage.husband_id),
+            wife: Some(marriage.wife_id),
+            marriage: Some(marriage),
+            children: Vec::new(),
+        });
+    }
+
+    for member in members {
+        let father = member.father_id.filter(|id| known.contains(id));
+        let mother = member.mother_id.filter(|id| known.contains(id));
+
+        if father.is_none() && mother.is_none() {
+            continue;
+        }
+
+        let i = *couples.entry((father, mother)).or_insert_with(|| {
+            families.push(Household {
+                husband: father,
+                wife: mother,
+                marriage: None,
+                children: Vec::new(),
+            });
+            families.len() - 1
+        });
+
+        families[i].children.push(member.id);
+    }
+
+    let mut spouse_in: HashMap<i64, Vec<usize>> = HashMap::new();
+    let mut child_in: HashMap<i64, usize> = HashMap::new();
+
+    for (i, family) in families.iter().enumerate() {
+        for spouse in [family.husband, family.wife].into_iter().flatten() {
+            spouse_in.entry(spouse).or_default().push(i);
+        }
+        for child in &family.children {
+            child_in.insert(*child, i);
+        }
+    }
+
+    let mut media_of: HashMap<i64, Vec<&MediaRow>> = HashMap::new();
+    for m in media {
+        media_of.entry(m.member_id).or_default().push(m);
+    }
+
+    let mut w = Writer { out: String::new() };
+
+    w.line(0, "HEAD", "");
+    w.line(1, "SOUR", "SHAJARAH");
+    w.line(2, "NAME", "Shajarah");
+    w.line(1, "DATE", &format_date(&full_date(exported_at)));
+    w.line(1, "GEDC", "");
+    w.line(2, "VERS", "5.5.1");
+    w.line(2, "FORM", "LINEAGE-LINKED");
+    w.line(1, "CHAR", "UTF-8");
+    w.pointer(1, "SUBM", "U1");
+
+    w.record("SUBM", "U1");
+    w.line(1, "NAME", "Shajarah");
+
+    for member in members {
+        let mut info: IndexMap<String, String> = member
+            .personal_info
+            .as_ref()
+            .and_then(|info| info.as_object())
+            .map(|info| {
+                info.iter()
+                    .map(|(key, value)| {
+                        let value = match value {
+                            serde_json::Value::String(value) => value.clone(),
+                            value => value.to_string(),
+                        };
+                        (key.clone(), value)
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        w.record("INDI", &format!("I{}", member.id));
+
+        w.line(
+            1,
+            "NAME",
+            &format!("{} /{}/", member.name, member.last_name),
+        );
+        w.line(2, "GIVN", &member.name);
+        if !member.last_name.is_empty() {
+            w.line(2, "SURN", &member.last_name);
+        }
+
+        if let Some(nasab) = nasabs.get(&member.id) {
+            w.line(1, "NAME", &format!("{nasab} /{}/", member.last_name));
+            w.line(2, "TYPE", "aka");
+            w.line(
+                2,
+                "ROMN",
+                &format!("{} /{}/", to_latin(nasab), to_latin(&member.last_name)),
+            );
+            w.line(3, "TYPE", "transliteration");
+        }
+
+        w.line(
+            1,
+            "SEX",
+            match member.gender {
+                Gender::Male => "M",
+                Gender::Female => "F",
+            },
+        );
+
+        let birth_place = info.shift_remove(BIRTH_PLACE);
+        if member.birthday.is_some() || birth_place.is_some() {
+            w.event("BIRT", member.birthday.as_ref(), birth_place.as_deref());
+        }
+
+        let death_place = info.shift_remove(DEATH_PLACE);
+        if member.deceased || member.death_date.is_some() || death_place.is_some() {
+            // "Y" says the death happened when nothing else is known about it
+            if member.death_date.is_none() && death_place.is_none() {
+                w.line(1, "DEAT", "Y");
+            } else {
+                w.event("DEAT", member.death_date.as_ref(), death_place.as_deref());
+            }
+        }
+
+        if let Some(place) = &member.burial_place {
+            w.event("BURI", None, Some(place));
+        }
+
+        if let Some(occupation) = info.shift_remove(OCCUPATION) {
+            w.line(1, "OCCU", &occupation);
+        }
+
+        let mut notes: Vec<String> = info.shift_remove(NOTES).into_iter().collect();
+        notes.extend(info.iter().map(|(key, value)| format!("{key}: {value}")));
+
+        for note in notes {
+            w.line(1, "NOTE", &note);
+        }
+
+        if let Some(family) = child_in.get(&member.id) {
+            w.pointer(1, "FAMC", &format!("F{}", family + 1));
+        }
+
+        for family in spouse_in.get(&member.id).into_iter().flatten() {
+            w.pointer(1, "FAMS", &format!("F{}", family + 1));
+        }
+
+        for m in media_of.get(&member.id).into_iter().flatten() {
+            let format = m
+                .content_type
+                .rsplit('/')
+                .next()
+                .map(|f| if f == "jpeg" { "jpg" } else { f })
+                .unwrap_or_default();
+
+            w.line(1, "OBJE", "");
+            w.line(
+                2,
+                "FILE",
+                &format!("/api/members/{}/media/{}", m.member_id, m.id),
+            );
+            w.line(3, "FORM", format);
+            if let Some(caption) = m.caption.as_ref().or(m.file_name.as_ref()) {
+                w.line(3, "TITL", caption);
+            }
+        }
+    }
+
+    for (i, family) in families.iter().enumerate() {
+        w.record("FAM", &format!("F{}", i + 1));
+
+        if let Some(husband) = family.husband {
+            w.pointer(1, "HUSB", &format!("I{husband}"));
+        }
+        if let Some(wife) = family.wife {
+            w.pointer(1, "WIFE", &format!("I{wife}"));
+        }
+        for child in &family.children {
+            w.pointer(1, "CHIL", &format!("I{child}"));
+        }
+
+        if let Some(marriage) = family.marriage {
+            let date = |d: &DateTime<Utc>| full_date(d.date_naive());
+
+            match marriage.married_at.as_ref().map(date) {
+                Some(married_at) => w.event("MARR", Some(&married_at), None),
+                None => w.line(1, "MARR", "Y"),
+            }
+
+            if marriage.end_reason == Some(MarriageEndReason::Divorce) {
+                match marriage.ended_at.as_ref().map(date) {
+                    Some(ended_at) => w.event("DIV", Some(&ended_at), None),
+                    None => w.line(1, "DIV", "Y"),
+                }
+            }
+        }
+    }
+
+    w.line(0, "TRLR", "");
+
+    w.out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read_text(text: &str) -> (Gedcom, GedcomImportResponse) {
+        let mut report = GedcomImportResponse::default();
+        let gedcom = read(text, &mut report);
+
+        (gedcom, report)
+    }
+
+    fn problems(report: &GedcomImportResponse) -> Vec<(usize, &str)> {
+        report
+            .problems
+            .iter()
+            .map(|p| (p.line, p.message.as_str()))
+            .collect()
+    }
+
+    #[test]
+    fn parse_nests_lines_and_joins_continuations() {
+        let mut problems = Vec::new();
+        let records = parse(
+            "0 HEAD\n1 CHAR UTF-8\n0 @N1@ NOTE first\n1 CONT second\n1 CONC  line\n0 TRLR\n",
+            &mut problems,
+        );
+
+        assert!(problems.is_empty());
+        assert_eq!(records.len(), 3);
+        assert_eq!(records[0].children[0].tag, "CHAR");
+        assert_eq!(records[1].xref.as_deref(), Some("N1"));
+        assert_eq!(records[1].value, "first\nsecond line");
+    }
+
+    #[test]
+    fn parse_reports_bad_lines() {
+        let mut problems = Vec::new();
+        let records = parse("0 HEAD\nnonsense\n2 DATE 1932\n0 TRLR", &mut problems);
+
+        assert_eq!(records.len(), 2);
+        assert_eq!(
+            problems.iter().map(|p| p.line).collect::<Vec<_>>(),
+            vec![2, 3]
+        );
+    }
+
+    #[test]
+    fn dates() {
+        let date = |value: &str| parse_date(value).map(|d| d.to_string());
+
+        assert_eq!(date("12 MAR 1932"), Ok(String::from("1932-03-12")));
+        assert_eq!(date("mar 1932"), Ok(String::from("1932-03")));
+        assert_eq!(date("ABT 1932"), Ok(String::from("circa 1932")));
+        assert_eq!(date("BEF 1932"), Ok(String::from("before 1932")));
+        assert_eq!(date("BET 1930 AND 1935"), Ok(String::from("after 1930")));
+        assert_eq!(date("FROM 1930 TO 1935"), Ok(String::from("1930")));
+        assert_eq!(date("INT 1932 (around the war)"), Ok(String::from("1932")));
+        assert_eq!(date("@#DGREGORIAN@ 1750/51"), Ok(String::from("1750")));
+        assert!(date("@#DJULIAN@ 1750").is_err());
+        assert!(date("32 MAR 1932").is_err());
+        assert!(date("sometime").is_err());
+    }
+
+    #[test]
+    fn read_maps_individuals_and_families() {
+        let (gedcom, report) = read_text(
+            "0 HEAD
+0 @I1@ INDI
+1 NAME محمد /الشمري/
+1 SEX M
+1 BIRT
+2 DATE ABT 1900
+2 PLAC حائل
+1 DEAT Y
+0 @I2@ INDI
+1 NAME فاطمة //
+1 SEX F
+0 @I3@ INDI
+1 NAME علي
+1 SEX M
+1 _CUSTOM x
+0 @F1@ FAM
+1 HUSB @I1@
+1 WIFE @I2@
+1 CHIL @I3@
+1 MARR
+2 DATE 1 JAN 1920
+0 TRLR",
+        );
+
+        assert!(report.problems.is_empty(), "{:?}", report.problems);
+        assert_eq!(report.unmapped_tags.len(), 1);
+        assert_eq!(report.unmapped_tags[0].path, "INDI._CUSTOM");
+
+        let [father, mother, child] = gedcom.individuals.as_slice() else {
+            panic!("expected three individuals");
+        };
+        assert_eq!(father.last_name, "الشمري");
+        assert_eq!(
+            father.birthday.map(|d| d.to_string()).as_deref(),
+            Some("circa 1900")
+        );
+        assert_eq!(father.personal_info[BIRTH_PLACE], "حائل");
+        assert!(father.deceased);
+        assert_eq!(mother.gender, Some(Gender::Female));
+        // the family name is taken from the father
+        assert_eq!(child.last_name, "الشمري");
+        assert_eq!(child.father.as_deref(), Some("I1"));
+        assert_eq!(child.mother.as_deref(), Some("I2"));
+
+        assert_eq!(gedcom.marriages.len(), 1);
+        assert_eq!(
+            gedcom.marriages[0].married_at,
+            NaiveDate::from_ymd_opt(1920, 1, 1)
+        );
+        assert_eq!(
+            gedcom.marriages[0].end_reason,
+            Some(MarriageEndReason::Death)
+        );
+    }
+
+    fn snapshot(
+        id: i64,
+        name: &str,
+        gender: Gender,
+        father_id: Option<i64>,
+        mother_id: Option<i64>,
+    ) -> MemberSnapshot {
+        MemberSnapshot {
+            id,
+            name: name.to_string(),
+            last_name: String::from("الشمري"),
+            gender,
+            birthday: None,
+            death_date: None,
+            deceased: false,
+            burial_place: None,
+            father_id,
+            mother_id,
+            personal_info: None,
+            deleted_at: None,
+            image_hash: None,
+            marriages: None,
+            media: None,
+        }
+    }
+
+    #[test]
+    fn export_round_trip() {
+        // long enough to be split into CONC lines, with spaces around every split
+        let notes = "كان يعمل في التجارة بين حائل والكويت ".repeat(20);
+
+        let mut father = snapshot(1, "محمد", Gender::Male, None, None);
+        father.birthday = Some("circa 1900".parse().unwrap());
+        father.death_date = Some("1350-05-12 AH".parse().unwrap());
+        father.deceased = true;
+        father.burial_place = Some(String::from("حائل"));
+        father.personal_info = Some(serde_json::json!({
+            OCCUPATION: "تاجر",
+            NOTES: notes,
+            "الكنية": "أبو علي",
+        }));
+        let mother = snapshot(2, "فاطمة", Gender::Female, None, None);
+        let mut child = snapshot(3, "علي", Gender::Male, Some(1), Some(2));
+        child.birthday = Some("1932-05-02".parse().unwrap());
+
+        let marriage = MarriageResponse {
+            id: 1,
+            husband_id: 1,
+            wife_id: 2,
+            married_at: NaiveDate::from_ymd_opt(1925, 3, 1)
+                .and_then(|d| d.and_hms_opt(0, 0, 0))
+                .map(|d| d.and_utc()),
+            ended_at: None,
+            end_reason: None,
+            marriage_order: 1,
+        };
+
+        let text = write(
+            &[father, mother, child],
+            &HashMap::from([(3, String::from("علي بن محمد"))]),
+            &[marriage],
+            &[],
+            NaiveDate::from_ymd_opt(2026, 10, 17).unwrap(),
+        );
+
+        assert!(text.starts_with(
+            "0 HEAD
+"
+        ));
+        assert!(
+            text.contains(
+                "1 SUBM @U1@
+"
+            ) && text.contains(
+                "0 @U1@ SUBM
+"
+            )
+        );
+        assert!(text.lines().all(|line| line.chars().count() <= 255));
+        assert!(text.lines().any(|line| line.starts_with("2 CONC ")));
+
+        let (gedcom, report) = read_text(&text);
+        assert!(report.problems.is_empty(), "{:?}", report.problems);
+
+        let [father, mother, child] = gedcom.individuals.as_slice() else {
+            panic!("expected three individuals");
+        };
+
+        assert_eq!(
+            (father.name.as_str(), father.last_name.as_str()),
+            ("محمد", "الشمري")
+        );
+        assert_eq!(father.gender, Some(Gender::Male));
+        assert_eq!(
+            father.birthday.map(|d| d.to_string()).as_deref(),
+            Some("circa 1900")
+        );
+        // hijri dates are exported in gregorian
+        assert_eq!(
+            father.death_date.map(|d| d.to_string()).as_deref(),
+            Some("1931-09-25")
+        );
+        assert!(father.deceased);
+        assert_eq!(father.burial_place.as_deref(), Some("حائل"));
+        assert_eq!(father.personal_info[OCCUPATION], "تاجر");
+        assert_eq!(
+            father.personal_info[NOTES],
+            format!("{notes}\nالكنية: أبو علي")
+        );
+
+        assert_eq!(mother.gender, Some(Gender::Female));
+        assert_eq!(
+            child.birthday.map(|d| d.to_string()).as_deref(),
+            Some("1932-05-02")
+        );
+        assert_eq!(child.father.as_deref(), Some("I1"));
+        assert_eq!(child.mother.as_deref(), Some("I2"));
+
+        assert_eq!(gedcom.marriages.len(), 1);
+        assert_eq!(
+            gedcom.marriages[0].married_at,
+            NaiveDate::from_ymd_opt(1925, 3, 1)
+        );
+    }
+
+    #[test]
+    fn duplicate_xrefs_are_reported() {
+        let (gedcom, report) =
+            read_text("0 @I1@ INDI\n1 NAME first\n1 SEX M\n0 @I1@ INDI\n1 NAME second\n1 SEX M\n");
+
+        assert_eq!(gedcom.individuals.len(), 1);
+        assert_eq!(gedcom.individuals[0].name, "first");
+        assert_eq!(
+            problems(&report),
+            vec![(4, "I1 is used by another individual, this one was skipped")]
+        );
+    }
+
+    #[test]
+    fn spouses_of_the_wrong_sex_are_reported() {
+        let (gedcom, report) = read_text(
+            "0 @I1@ INDI\n1 SEX F\n0 @I2@ INDI\n1 SEX F\n0 @I3@ INDI\n1 SEX M\n\
+             0 @F1@ FAM\n1 HUSB @I1@\n1 WIFE @I2@\n1 CHIL @I3@\n",
+        );
+
+        assert!(gedcom.marriages.is_empty());
+        // the child keeps the link, it's checked when the members are imported
+        assert_eq!(gedcom.individuals[2].father.as_deref(), Some("I1"));
+        assert!(problems(&report).contains(&(
+            7,
+            "I1 and I2 aren't a husband and a wife, the marriage was skipped"
+        )));
+    }
+}
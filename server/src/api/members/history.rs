@@ -0,0 +1,86 @@
+use std::collections::HashMap;
+
+use sqlx::{types::Json, PgConnection};
+use uuid::Uuid;
+
+use crate::Gender;
+
+use super::{
+    dates::PartialDate,
+    models::{ChangeAction, MemberSnapshot},
+};
+
+/// postgres takes at most 65535 bind parameters per query
+const CHANGES_PER_INSERT: usize = 10_000;
+
+/// Get the current fields of the given members, members that don't exist are left out
+pub async fn snapshots(
+    conn: &mut PgConnection,
+    ids: &[i64],
+) -> Result<HashMap<i64, MemberSnapshot>, sqlx::Error> {
+    let recs = sqlx::query_as!(
+        MemberSnapshot,
+        r#"
+SELECT
+    id, name, last_name, gender as "gender: Gender",
+    birthday as "birthday: PartialDate", death_date as "death_date: PartialDate",
+    deceased, burial_place, father_id, mother_id, personal_info, deleted_at, image_hash,
+    (
+        SELECT jsonb_agg(to_jsonb(mar) ORDER BY mar.id) FROM marriages mar
+        WHERE mar.husband_id = m.id OR mar.wife_id = m.id
+    ) as marriages,
+    (
+        SELECT jsonb_agg(jsonb_build_object(
+            'id', mm.id, 'kind', mm.kind, 'caption', mm.caption, 'date', mm.date,
+            'position', mm.position, 'file_name', mm.file_name, 'content_hash', mm.content_hash
+        ) ORDER BY mm.position, mm.id)
+        FROM member_media mm WHERE mm.member_id = m.id
+    ) as media
+FROM members m
+WHERE id = ANY($1)
+        "#,
+        ids,
+    )
+    .fetch_all(conn)
+    .await?;
+
+    Ok(recs.into_iter().map(|m| (m.id, m)).collect())
+}
+
+/// Record a change made by `user_id` to the given members, `None` when no user made it,
+/// `before` holds their fields from [`snapshots`] taken before the change
+///
+/// members that didn't actually change are skipped
+pub async fn record_changes(
+    conn: &mut PgConnection,
+    user_id: Option<Uuid>,
+    action: ChangeAction,
+    ids: &[i64],
+    before: &HashMap<i64, MemberSnapshot>,
+) -> Result<(), sqlx::Error> {
+    let after = snapshots(conn, ids).await?;
+
+    let changes: Vec<_> = ids
+        .iter()
+        .map(|id| (*id, before.get(id), after.get(id)))
+        .filter(|(_, before, after)| before != after)
+        .collect();
+
+    for chunk in changes.chunks(CHANGES_PER_INSERT) {
+        let mut query = sqlx::QueryBuilder::new(
+            "INSERT INTO member_changes (member_id, user_id, action, before, after)",
+        );
+
+        query.push_values(chunk, |mut b, (id, before, after)| {
+            b.push_bind(id)
+                .push_bind(user_id)
+                .push_bind(action)
+                .push_bind(before.map(Json))
+                .push_bind(after.map(Json));
+        });
+
+        query.build().execute(&mut *conn).await?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,322 @@
+use std::io::Cursor;
+
+use image::{
+    codecs::{jpeg::JpegEncoder, png::PngEncoder},
+    imageops::FilterType,
+    DynamicImage, ImageDecoder, ImageFormat, ImageReader,
+};
+use sqlx::{PgConnection, PgPool};
+
+use crate::storage::{content_key, Storage};
+
+use super::{models::ImageSize, MembersError};
+
+/// the content types images can be uploaded as
+pub const ACCEPTED_TYPES: [&str; 4] = ["image/png", "image/jpg", "image/jpeg", "image/webp"];
+
+/// nodes are drawn 80px wide and can be zoomed in a few times
+const THUMBNAIL_SIZE: u32 = 256;
+
+/// the member info window is 180px wide, doubled for high density screens
+const MEDIUM_SIZE: u32 = 480;
+
+const JPEG_QUALITY: u8 = 85;
+
+/// Decode an uploaded image, turn it upright and encode it again
+/// so only the pixels are kept and metadata like the GPS location is dropped
+///
+/// images with transparency are stored as png and the rest as jpeg,
+/// returns the image and its content type
+pub async fn normalize(bytes: Vec<u8>) -> Result<(Vec<u8>, &'static str), MembersError> {
+    tokio::task::spawn_blocking(move || {
+        let image = decode(&bytes)?;
+
+        encode(&image)
+    })
+    .await
+    .map_err(|e| {
+        log::error!("{e}");
+        MembersError::SomethingWentWrong
+    })?
+}
+
+/// Make a copy of a stored image in the given size,
+/// returns the image and its content type
+pub async fn resize(
+    bytes: Vec<u8>,
+    size: ImageSize,
+) -> Result<(Vec<u8>, &'static str), MembersError> {
+    tokio::task::spawn_blocking(move || {
+        let image = decode(&bytes)?;
+
+        let image = match size {
+            // nodes are round, so the thumbnail is cropped to a square around the center
+            ImageSize::Thumbnail => {
+                image.resize_to_fill(THUMBNAIL_SIZE, THUMBNAIL_SIZE, FilterType::Triangle)
+            }
+            ImageSize::Medium if image.width() > MEDIUM_SIZE || image.height() > MEDIUM_SIZE => {
+                image.resize(MEDIUM_SIZE, MEDIUM_SIZE, FilterType::Triangle)
+            }
+            ImageSize::Medium | ImageSize::Original => image,
+        };
+
+        encode(&image)
+    })
+    .await
+    .map_err(|e| {
+        log::error!("{e}");
+        MembersError::SomethingWentWrong
+    })?
+}
+
+fn decode(bytes: &[u8]) -> Result<DynamicImage, MembersError> {
+    let invalid = |e: image::ImageError| {
+        log::debug!("{e}");
+        MembersError::InvalidImage
+    };
+
+    let reader = ImageReader::new(Cursor::new(bytes))
+        .with_guessed_format()
+        .map_err(|e| {
+            log::debug!("{e}");
+            MembersError::InvalidImage
+        })?;
+
+    if !matches!(
+        reader.format(),
+        Some(ImageFormat::Png | ImageFormat::Jpeg | ImageFormat::WebP)
+    ) {
+        return Err(MembersError::InvalidImage);
+    }
+
+    let mut decoder = reader.into_decoder().map_err(invalid)?;
+    let orientation = decoder.orientation().map_err(invalid)?;
+    let mut image = DynamicImage::from_decoder(decoder).map_err(invalid)?;
+    image.apply_orientation(orientation);
+
+    Ok(image)
+}
+
+fn encode(image: &DynamicImage) -> Result<(Vec<u8>, &'static str), MembersError> {
+    let mut bytes = Vec::new();
+
+    let result = if image.color().has_alpha() {
+        image
+            .to_rgba8()
+            .write_with_encoder(PngEncoder::new(&mut bytes))
+            .map(|_| "image/png")
+    } else {
+        image
+            .to_rgb8()
+            .write_with_encoder(JpegEncoder::new_with_quality(&mut bytes, JPEG_QUALITY))
+            .map(|_| "image/jpeg")
+    };
+
+    let content_type = result.map_err(|e| {
+        log::error!("{e}");
+        MembersError::SomethingWentWrong
+    })?;
+
+    Ok((bytes, content_type))
+}
+
+/// Lock the given keys until the transaction of `conn` ends
+///
+/// storing a file and adding the row that uses it happen under the lock of its key,
+/// so [`release`] can't delete the file in between
+async fn lock_keys(conn: &mut PgConnection, keys: &[String]) -> Result<(), MembersError> {
+    // always in the same order, so two transactions locking the same keys can't deadlock
+    sqlx::query!(
+        r#"
+SELECT count(pg_advisory_xact_lock(hashtext(key)))
+FROM (SELECT DISTINCT key FROM unnest($1::TEXT[]) key ORDER BY key) keys
+        "#,
+        keys,
+    )
+    .fetch_one(conn)
+    .await?;
+
+    Ok(())
+}
+
+/// Store a file under the lock of its key, returns the key
+///
+/// the row that uses the file has to be added in the same transaction as `conn`
+pub async fn put(
+    conn: &mut PgConnection,
+    storage: &dyn Storage,
+    content: &[u8],
+) -> Result<String, MembersError> {
+    lock_keys(conn, &[content_key(content)]).await?;
+
+    Ok(storage.put(content).await?)
+}
+
+/// Delete the files with the given keys from the storage
+/// if no member, add request, image size or media uses them anymore
+///
+/// files are shared by everything with the same content, so they can only be deleted
+/// after the rows that used them are gone
+pub async fn release(
+    db_pool: &PgPool,
+    storage: &dyn Storage,
+    keys: &[String],
+) -> Result<(), MembersError> {
+    let mut tx = db_pool.begin().await?;
+    lock_keys(&mut tx, keys).await?;
+
+    let unused = sqlx::query_scalar!(
+        r#"
+SELECT key as "key!"
+FROM unnest($1::TEXT[]) key
+WHERE NOT EXISTS (SELECT 1 FROM members WHERE image_hash = key)
+AND NOT EXISTS (SELECT 1 FROM member_add_requests WHERE image_hash = key)
+AND NOT EXISTS (SELECT 1 FROM member_image_sizes WHERE image_hash = key)
+AND NOT EXISTS (SELECT 1 FROM member_media WHERE content_hash = key OR thumbnail_hash = key)
+        "#,
+        keys,
+    )
+    .fetch_all(&mut *tx)
+    .await?;
+
+    for key in unused {
+        // a leftover file only takes space, it shouldn't fail the request
+        if let Err(e) = storage.delete(&key).await {
+            log::error!("failed to delete {key}: {e}");
+        }
+    }
+
+    tx.commit().await?;
+
+    Ok(())
+}
+
+/// How many images and media are still kept in the database
+pub async fn count_in_database(db_pool: &PgPool) -> Result<i64, MembersError> {
+    let count = sqlx::query_scalar!(
+        r#"
+SELECT
+    (SELECT count(*) FROM members WHERE image IS NOT NULL)
+    + (SELECT count(*) FROM member_add_requests WHERE image IS NOT NULL)
+    + (SELECT count(*) FROM member_media WHERE content IS NOT NULL OR thumbnail IS NOT NULL)
+    as "count!"
+        "#,
+    )
+    .fetch_one(db_pool)
+    .await?;
+
+    Ok(count)
+}
+
+/// Move the images and media that are still kept in the database to the storage,
+/// one at a time so they're never all loaded at once
+///
+/// returns how many rows were moved
+pub async fn move_to_storage(
+    db_pool: &PgPool,
+    storage: &dyn Storage,
+) -> Result<usize, MembersError> {
+    let mut moved = 0;
+
+    let ids = sqlx::query_scalar!("SELECT id FROM members WHERE image IS NOT NULL")
+        .fetch_all(db_pool)
+        .await?;
+
+    for id in ids {
+        let image =
+            sqlx::query_scalar!(r#"SELECT image as "image!" FROM members WHERE id = $1"#, id)
+                .fetch_one(db_pool)
+                .await?;
+
+        let mut tx = db_pool.begin().await?;
+        let key = put(&mut tx, storage, &image).await?;
+
+        sqlx::query!(
+            "UPDATE members SET image = NULL, image_hash = $2 WHERE id = $1",
+            id,
+            key,
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+
+        moved += 1;
+    }
+
+    let ids = sqlx::query_scalar!("SELECT id FROM member_add_requests WHERE image IS NOT NULL")
+        .fetch_all(db_pool)
+        .await?;
+
+    for id in ids {
+        let image = sqlx::query_scalar!(
+            r#"SELECT image as "image!" FROM member_add_requests WHERE id = $1"#,
+            id
+        )
+        .fetch_one(db_pool)
+        .await?;
+
+        let mut tx = db_pool.begin().await?;
+        let key = put(&mut tx, storage, &image).await?;
+
+        sqlx::query!(
+            "UPDATE member_add_requests SET image = NULL, image_hash = $2 WHERE id = $1",
+            id,
+            key,
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+
+        moved += 1;
+    }
+
+    let ids = sqlx::query_scalar!(
+        "SELECT id FROM member_media WHERE content IS NOT NULL OR thumbnail IS NOT NULL"
+    )
+    .fetch_all(db_pool)
+    .await?;
+
+    for id in ids {
+        let rec = sqlx::query!(
+            "SELECT content, thumbnail FROM member_media WHERE id = $1",
+            id
+        )
+        .fetch_one(db_pool)
+        .await?;
+
+        let mut tx = db_pool.begin().await?;
+
+        let content_hash = match rec.content {
+            Some(content) => Some(put(&mut tx, storage, &content).await?),
+            None => None,
+        };
+        let thumbnail_hash = match rec.thumbnail {
+            Some(thumbnail) => Some(put(&mut tx, storage, &thumbnail).await?),
+            None => None,
+        };
+
+        sqlx::query!(
+            r#"
+UPDATE member_media
+SET
+    content = NULL, thumbnail = NULL,
+    content_hash = COALESCE($2, content_hash),
+    thumbnail_hash = COALESCE($3, thumbnail_hash)
+WHERE id = $1
+            "#,
+            id,
+            content_hash,
+            thumbnail_hash,
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+
+        moved += 1;
+    }
+
+    Ok(moved)
+}
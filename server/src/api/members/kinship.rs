@@ -0,0 +1,434 @@
+use std::collections::{HashMap, VecDeque};
+
+use crate::Gender;
+
+use super::models::{MemberLinkRow, RelationshipResponse, RelativeResponse};
+
+/// A common ancestor and the lines from both relatives up to them,
+/// each line starts at the relative and ends at the ancestor
+struct Lines<'a> {
+    ups: Vec<&'a MemberLinkRow>,
+    downs: Vec<&'a MemberLinkRow>,
+    /// `Some(true)` when the lines meet at only one parent of a couple,
+    /// `Some(false)` when both parents are shared, `None` when a parent isn't known
+    half: Option<bool>,
+}
+
+impl Lines<'_> {
+    fn up(&self) -> u32 {
+        self.ups.len() as u32 - 1
+    }
+
+    fn down(&self) -> u32 {
+        self.downs.len() as u32 - 1
+    }
+
+    fn ancestor(&self) -> &MemberLinkRow {
+        self.ups[self.ups.len() - 1]
+    }
+}
+
+/// The closest way up from a member to each of their ancestors,
+/// as the number of generations and the child the ancestor was reached from
+fn ancestors(members: &HashMap<i64, MemberLinkRow>, start: i64) -> HashMap<i64, (u32, i64)> {
+    let mut found = HashMap::from([(start, (0, start))]);
+    let mut queue = VecDeque::from([start]);
+
+    while let Some(id) = queue.pop_front() {
+        let Some(member) = members.get(&id) else {
+            continue;
+        };
+        let generations = found[&id].0 + 1;
+
+        for parent_id in [member.father_id, member.mother_id].into_iter().flatten() {
+            if members.contains_key(&parent_id) && !found.contains_key(&parent_id) {
+                found.insert(parent_id, (generations, id));
+                queue.push_back(parent_id);
+            }
+        }
+    }
+
+    found
+}
+
+/// the line from `ancestor` back down to the member `found` was made from, the member first
+fn line<'a>(
+    members: &'a HashMap<i64, MemberLinkRow>,
+    found: &HashMap<i64, (u32, i64)>,
+    ancestor: i64,
+) -> Vec<&'a MemberLinkRow> {
+    let mut line = vec![&members[&ancestor]];
+    let mut id = ancestor;
+
+    while let Some(&(generations, child)) = found.get(&id) {
+        if generations == 0 {
+            break;
+        }
+        line.push(&members[&child]);
+        id = child;
+    }
+
+    line.reverse();
+    line
+}
+
+/// the parent of `child` that isn't `parent`
+fn other_parent(child: &MemberLinkRow, parent: i64) -> Option<i64> {
+    if child.father_id == Some(parent) {
+        child.mother_id
+    } else {
+        child.father_id
+    }
+}
+
+/// Find how `to` is related to `from` through their closest common ancestor,
+/// `members` needs both of them and all their ancestors
+///
+/// `None` when they don't share an ancestor
+pub fn relate(
+    members: &HashMap<i64, MemberLinkRow>,
+    from: i64,
+    to: i64,
+) -> Option<RelationshipResponse> {
+    let from_ancestors = ancestors(members, from);
+    let to_ancestors = ancestors(members, to);
+
+    // the fathers' line first when both parents are as close
+    let (ancestor, _) = from_ancestors
+        .iter()
+        .filter_map(|(id, (up, _))| to_ancestors.get(id).map(|(down, _)| (*id, up + down)))
+        .min_by_key(|(id, generations)| (*generations, members[id].gender != Gender::Male, *id))?;
+
+    let mut lines = Lines {
+        ups: line(members, &from_ancestors, ancestor),
+        downs: line(members, &to_ancestors, ancestor),
+        half: None,
+    };
+
+    let mut common_ancestors = vec![RelativeResponse::from(lines.ancestor())];
+
+    if lines.up() > 0 && lines.down() > 0 {
+        let from_side = other_parent(lines.ups[lines.ups.len() - 2], ancestor);
+        let to_side = other_parent(lines.downs[lines.downs.len() - 2], ancestor);
+
+        if let (Some(from_side), Some(to_side)) = (from_side, to_side) {
+            lines.half = Some(from_side != to_side);
+
+            if from_side == to_side {
+                if let Some(parent) = members.get(&from_side) {
+                    common_ancestors.push(RelativeResponse::from(parent));
+                }
+            }
+        }
+    }
+
+    let (up, down) = (lines.up(), lines.down());
+    let cousins = up > 1 && down > 1;
+
+    Some(RelationshipResponse {
+        from_id: from,
+        to_id: to,
+        arabic: arabic(&lines),
+        english: english(&lines),
+        generations_up: up,
+        generations_down: down,
+        cousin_degree: cousins.then(|| up.min(down) - 1),
+        removed: cousins.then(|| up.abs_diff(down)),
+        half: lines.half.unwrap_or_default(),
+        common_ancestors,
+        path: lines
+            .ups
+            .iter()
+            .chain(lines.downs.iter().rev().skip(1))
+            .map(|m| RelativeResponse::from(*m))
+            .collect(),
+    })
+}
+
+fn gendered<'a>(gender: Gender, male: &'a str, female: &'a str) -> &'a str {
+    match gender {
+        Gender::Male => male,
+        Gender::Female => female,
+    }
+}
+
+/// What `to` is to `from` in English, e.g. "great-uncle" or "second cousin twice removed"
+fn english(lines: &Lines) -> String {
+    let gender = lines.downs[0].gender;
+    let greats = |n: u32| "great-".repeat(n as usize);
+
+    let term = match (lines.up(), lines.down()) {
+        (0, 0) => return String::from("self"),
+        (1, 0) => gendered(gender, "father", "mother").to_string(),
+        (up, 0) => format!(
+            "{}grand{}",
+            greats(up - 2),
+            gendered(gender, "father", "mother")
+        ),
+        (0, 1) => gendered(gender, "son", "daughter").to_string(),
+        (0, down) => format!(
+            "{}grand{}",
+            greats(down - 2),
+            gendered(gender, "son", "daughter")
+        ),
+        (1, 1) => gendered(gender, "brother", "sister").to_string(),
+        (up, 1) => format!("{}{}", greats(up - 2), gendered(gender, "uncle", "aunt")),
+        (1, down) => format!(
+            "{}{}",
+            greats(down - 2),
+            gendered(gender, "nephew", "niece")
+        ),
+        (up, down) => {
+            let removed = match up.abs_diff(down) {
+                0 => String::new(),
+                1 => String::from(" once removed"),
+                2 => String::from(" twice removed"),
+                n => format!(" {n} times removed"),
+            };
+
+            let term = format!("{} cousin{removed}", ordinal(up.min(down) - 1));
+
+            return match lines.half {
+                Some(true) => format!("half {term}"),
+                _ => term,
+            };
+        }
+    };
+
+    match lines.half {
+        Some(true) => format!("half-{term}"),
+        _ => term,
+    }
+}
+
+fn ordinal(n: u32) -> String {
+    let suffix = match (n % 10, n % 100) {
+        (_, 11..=13) => "th",
+        (1, _) => "st",
+        (2, _) => "nd",
+        (3, _) => "rd",
+        _ => "th",
+    };
+
+    match n {
+        1 => String::from("first"),
+        2 => String::from("second"),
+        3 => String::from("third"),
+        n => format!("{n}{suffix}"),
+    }
+}
+
+/// What `to` is to `from` in Arabic, put together the way it's said:
+/// what `to` is to a close relative of `from`, then who that relative is,
+/// e.g. ابن عم الأب for the son of the father's paternal uncle
+fn arabic(lines: &Lines) -> String {
+    let (up, down) = (lines.up() as usize, lines.down() as usize);
+    let (ups, downs) = (&lines.ups, &lines.downs);
+    let gender = downs[0].gender;
+
+    let child = |m: &MemberLinkRow| gendered(m.gender, "ابن", "ابنة");
+    let parent = |m: &MemberLinkRow| gendered(m.gender, "أب", "أم");
+    let grandparent = |m: &MemberLinkRow| gendered(m.gender, "جد", "جدة");
+    // عم and عمة on the father's side, خال and خالة on the mother's
+    let uncle = |side: &MemberLinkRow, m: &MemberLinkRow| match side.gender {
+        Gender::Male => gendered(m.gender, "عم", "عمة"),
+        Gender::Female => gendered(m.gender, "خال", "خالة"),
+    };
+
+    // the words for `to`, down from the relative they're said from
+    let mut words: Vec<&str> = Vec::new();
+    // the member of `ups` the words are said from
+    let owner;
+
+    match (up, down) {
+        (0, 0) => return String::from("نفسه"),
+        (1, 0) => return parent(downs[0]).to_string(),
+        (2, 0) => {
+            return format!(
+                "{} {}",
+                grandparent(downs[0]),
+                gendered(ups[1].gender, "لأب", "لأم")
+            )
+        }
+        (_, 0) => {
+            words.push(grandparent(downs[0]));
+            owner = up - 2;
+        }
+        (0, 1) => return child(downs[0]).to_string(),
+        (0, _) => {
+            words.extend(downs[..down - 2].iter().map(|m| child(m)));
+            words.push(gendered(downs[down - 2].gender, "حفيد", "حفيدة"));
+            owner = 0;
+        }
+        (1, _) => {
+            words.extend(downs[..down - 1].iter().map(|m| child(m)));
+            words.push(gendered(downs[down - 1].gender, "أخ", "أخت"));
+            owner = 0;
+        }
+        (_, _) => {
+            words.extend(downs[..down - 1].iter().map(|m| child(m)));
+            words.push(uncle(ups[up - 1], downs[down - 1]));
+            owner = up - 2;
+        }
+    }
+
+    match owner {
+        0 => {}
+        1 => words.push(parent(ups[1])),
+        2 => words.push(grandparent(ups[2])),
+        _ if down == 0 => return gendered(gender, "جد أعلى", "جدة عليا").to_string(),
+        _ => return gendered(gender, "قريب", "قريبة").to_string(),
+    }
+
+    // relatives too far apart for the terms to still read naturally
+    if words.len() > 4 {
+        return gendered(gender, "قريب", "قريبة").to_string();
+    }
+
+    if words.len() == 1 {
+        let half = match (up, down, lines.half) {
+            (1 | 2, 1, Some(false)) => gendered(gender, " شقيق", " شقيقة"),
+            (1 | 2, 1, Some(true)) => gendered(lines.ancestor().gender, " لأب", " لأم"),
+            _ => "",
+        };
+
+        return format!("{}{half}", words[0]);
+    }
+
+    // only the last word of a chain takes the article, e.g. ابن ابن العم
+    let last = words.len() - 1;
+    words
+        .iter()
+        .enumerate()
+        .map(|(i, word)| {
+            if i == last {
+                format!("ال{word}")
+            } else {
+                word.to_string()
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// (id, gender, father, mother)
+    const FAMILY: &[(i64, Gender, Option<i64>, Option<i64>)] = &[
+        (1, Gender::Male, Some(16), None),       // paternal grandfather
+        (2, Gender::Female, None, None),         // paternal grandmother
+        (3, Gender::Male, Some(1), Some(2)),     // father
+        (4, Gender::Male, Some(1), Some(2)),     // paternal uncle
+        (5, Gender::Female, Some(1), Some(2)),   // paternal aunt
+        (6, Gender::Male, None, None),           // maternal grandfather
+        (7, Gender::Female, None, None),         // maternal grandmother
+        (8, Gender::Female, Some(6), Some(7)),   // mother
+        (9, Gender::Male, Some(6), Some(7)),     // maternal uncle
+        (10, Gender::Male, Some(3), Some(8)),    // the member the others are related to
+        (11, Gender::Male, Some(3), Some(8)),    // brother
+        (12, Gender::Female, None, None),        // the father's other wife
+        (13, Gender::Male, Some(3), Some(12)),   // half-brother
+        (14, Gender::Male, Some(4), None),       // cousin
+        (15, Gender::Male, Some(14), None),      // the cousin's son
+        (16, Gender::Male, None, None),          // great-grandfather
+        (17, Gender::Male, Some(16), None),      // the grandfather's brother
+        (18, Gender::Female, Some(9), None),     // maternal cousin
+        (19, Gender::Female, None, None),        // the grandfather's other wife
+        (20, Gender::Male, Some(1), Some(19)),   // paternal half-uncle
+        (21, Gender::Female, Some(22), Some(8)), // maternal half-sister
+        (22, Gender::Male, None, None),          // the mother's other husband
+        (23, Gender::Male, Some(20), None),      // half cousin
+        (24, Gender::Male, Some(15), None),      // the cousin's grandson
+        (25, Gender::Male, Some(11), None),      // the brother's son
+        (26, Gender::Female, Some(6), Some(7)),  // maternal aunt
+        (27, Gender::Male, None, None),          // not related
+    ];
+
+    fn family() -> HashMap<i64, MemberLinkRow> {
+        FAMILY
+            .iter()
+            .map(|&(id, gender, father_id, mother_id)| {
+                let member = MemberLinkRow {
+                    id,
+                    name: id.to_string(),
+                    last_name: String::new(),
+                    gender,
+                    mother_id,
+                    father_id,
+                    deleted_at: None,
+                };
+                (id, member)
+            })
+            .collect()
+    }
+
+    fn relate_to(from: i64, to: i64) -> (String, String) {
+        let relationship = relate(&family(), from, to).expect("the members are related");
+
+        (relationship.arabic, relationship.english)
+    }
+
+    fn terms(arabic: &str, english: &str) -> (String, String) {
+        (arabic.to_string(), english.to_string())
+    }
+
+    #[test]
+    fn parents_and_children() {
+        assert_eq!(relate_to(10, 10), terms("نفسه", "self"));
+        assert_eq!(relate_to(10, 3), terms("أب", "father"));
+        assert_eq!(relate_to(10, 8), terms("أم", "mother"));
+        assert_eq!(relate_to(3, 10), terms("ابن", "son"));
+        assert_eq!(relate_to(10, 1), terms("جد لأب", "grandfather"));
+        assert_eq!(relate_to(10, 7), terms("جدة لأم", "grandmother"));
+        assert_eq!(relate_to(10, 16), terms("جد الأب", "great-grandfather"));
+    }
+
+    #[test]
+    fn siblings() {
+        assert_eq!(relate_to(10, 11), terms("أخ شقيق", "brother"));
+        assert_eq!(relate_to(10, 13), terms("أخ لأب", "half-brother"));
+        assert_eq!(relate_to(13, 10), terms("أخ لأب", "half-brother"));
+        assert_eq!(relate_to(10, 21), terms("أخت لأم", "half-sister"));
+    }
+
+    #[test]
+    fn uncles_and_aunts() {
+        assert_eq!(relate_to(10, 4), terms("عم شقيق", "uncle"));
+        assert_eq!(relate_to(10, 5), terms("عمة شقيقة", "aunt"));
+        assert_eq!(relate_to(10, 9), terms("خال شقيق", "uncle"));
+        assert_eq!(relate_to(10, 26), terms("خالة شقيقة", "aunt"));
+        assert_eq!(relate_to(10, 20), terms("عم لأب", "half-uncle"));
+        assert_eq!(relate_to(10, 17), terms("عم الأب", "great-uncle"));
+        assert_eq!(relate_to(4, 10), terms("ابن الأخ", "nephew"));
+    }
+
+    #[test]
+    fn cousins() {
+        assert_eq!(relate_to(10, 14), terms("ابن العم", "first cousin"));
+        assert_eq!(relate_to(10, 18), terms("ابنة الخال", "first cousin"));
+        assert_eq!(
+            relate_to(10, 15),
+            terms("ابن ابن العم", "first cousin once removed")
+        );
+        assert_eq!(
+            relate_to(15, 10),
+            terms("ابن عم الأب", "first cousin once removed")
+        );
+        assert_eq!(
+            relate_to(10, 24),
+            terms("ابن ابن ابن العم", "first cousin twice removed")
+        );
+        assert_eq!(relate_to(15, 25), terms("ابن ابن عم الأب", "second cousin"));
+        assert_eq!(relate_to(10, 23), terms("ابن العم", "half first cousin"));
+    }
+
+    #[test]
+    fn unrelated() {
+        let members = family();
+
+        assert!(relate(&members, 10, 27).is_none());
+        assert!(relate(&members, 27, 10).is_none());
+    }
+}
@@ -1,9 +1,20 @@
 use axum::{http::StatusCode, response::IntoResponse};
 
-use crate::{auth::AuthError, ErrorResponse};
+use crate::{auth::AuthError, storage::StorageError, ErrorResponse};
+use models::{AffectedMember, CsvImportRow, CsvRowError, InvalidParent};
 
+pub mod csv_import;
+pub mod dates;
+pub mod gedcom;
+pub mod history;
+pub mod images;
+pub mod kinship;
 pub mod models;
+pub mod nasab;
 pub mod routes;
+pub mod search;
+pub mod transliteration;
+pub mod validation;
 
 #[derive(thiserror::Error, Debug)]
 pub enum MembersError {
@@ -22,6 +33,42 @@ pub enum MembersError {
     #[error("no root member")]
     NoRootMember,
 
+    #[error("marriage not found")]
+    MarriageNotFound,
+
+    #[error("the husband and wife are already married")]
+    MarriageExists,
+
+    #[error("member not found")]
+    MemberNotFound,
+
+    #[error("change not found")]
+    ChangeNotFound,
+
+    #[error("member has no image")]
+    ImageNotFound,
+
+    #[error("media not found")]
+    MediaNotFound,
+
+    #[error("the members aren't related by blood")]
+    NotRelated,
+
+    #[error("member has children")]
+    HasChildren(Vec<AffectedMember>),
+
+    #[error("member has no parent to move the children to")]
+    NoParentToReparent(Vec<AffectedMember>),
+
+    #[error("invalid parents")]
+    InvalidParents(Vec<InvalidParent>),
+
+    #[error("invalid csv file")]
+    InvalidCsv(Vec<CsvRowError>),
+
+    #[error("the import changes existing members, confirm it to continue")]
+    UnconfirmedImport(Vec<CsvImportRow>),
+
     #[error("invalid {0} value")]
     InvalidValue(String),
 
@@ -34,6 +81,9 @@ pub enum MembersError {
     #[error(transparent)]
     AuthError(#[from] AuthError),
 
+    #[error(transparent)]
+    Storage(#[from] StorageError),
+
     #[error(transparent)]
     Anyhow(#[from] anyhow::Error),
 
@@ -43,7 +93,7 @@ pub enum MembersError {
 
 impl IntoResponse for MembersError {
     fn into_response(self) -> axum::response::Response {
-        log::error!("{:#?}", self);
+        log::error!("{self:#?}");
 
         match self {
             MembersError::SomethingWentWrong => (StatusCode::INTERNAL_SERVER_ERROR).into_response(),
@@ -65,6 +115,78 @@ impl IntoResponse for MembersError {
                 },
             )
                 .into_response(),
+            MembersError::MarriageNotFound
+            | MembersError::MemberNotFound
+            | MembersError::ChangeNotFound
+            | MembersError::ImageNotFound
+            | MembersError::MediaNotFound
+            | MembersError::NotRelated => (
+                StatusCode::NOT_FOUND,
+                ErrorResponse {
+                    error: self.to_string(),
+                    details: None,
+                },
+            )
+                .into_response(),
+            MembersError::MarriageExists => (
+                StatusCode::CONFLICT,
+                ErrorResponse {
+                    error: self.to_string(),
+                    details: None,
+                },
+            )
+                .into_response(),
+            MembersError::HasChildren(ref children)
+            | MembersError::NoParentToReparent(ref children) => (
+                StatusCode::CONFLICT,
+                ErrorResponse {
+                    error: self.to_string(),
+                    details: Some(
+                        children
+                            .iter()
+                            .map(|c| format!("{}: {} {}", c.id, c.name, c.last_name))
+                            .collect(),
+                    ),
+                },
+            )
+                .into_response(),
+            MembersError::InvalidParents(ref problems) => (
+                StatusCode::BAD_REQUEST,
+                ErrorResponse {
+                    error: self.to_string(),
+                    details: Some(problems.iter().map(ToString::to_string).collect()),
+                },
+            )
+                .into_response(),
+            MembersError::InvalidCsv(ref errors) => (
+                StatusCode::BAD_REQUEST,
+                ErrorResponse {
+                    error: self.to_string(),
+                    details: Some(errors.iter().map(ToString::to_string).collect()),
+                },
+            )
+                .into_response(),
+            MembersError::UnconfirmedImport(ref updated) => (
+                StatusCode::CONFLICT,
+                ErrorResponse {
+                    error: self.to_string(),
+                    details: Some(
+                        updated
+                            .iter()
+                            .map(|m| {
+                                format!(
+                                    "{}: {} {} ({})",
+                                    m.id,
+                                    m.name,
+                                    m.last_name,
+                                    m.changed_fields.join(", ")
+                                )
+                            })
+                            .collect(),
+                    ),
+                },
+            )
+                .into_response(),
             MembersError::InvalidValue(_) => (
                 StatusCode::BAD_REQUEST,
                 ErrorResponse {
@@ -99,6 +221,16 @@ impl IntoResponse for MembersError {
             )
                 .into_response(),
             MembersError::Garde(_) => (StatusCode::BAD_REQUEST).into_response(),
+            // the file is missing from the storage, e.g. `server move-images` wasn't run yet
+            MembersError::Storage(StorageError::NotFound(_)) => (
+                StatusCode::NOT_FOUND,
+                ErrorResponse {
+                    error: MembersError::ImageNotFound.to_string(),
+                    details: None,
+                },
+            )
+                .into_response(),
+            MembersError::Storage(_) => (StatusCode::INTERNAL_SERVER_ERROR).into_response(),
         }
     }
 }
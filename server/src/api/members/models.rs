@@ -1,5 +1,7 @@
+use std::collections::{HashMap, HashSet};
+
 use anyhow::anyhow;
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, NaiveDate, Utc};
 use garde::Validate;
 use indexmap::IndexMap;
 use serde::{Deserialize, Serialize};
@@ -7,12 +9,17 @@ use uuid::Uuid;
 
 use crate::Gender;
 
+use super::{dates::PartialDate, nasab::Nasab};
+
 #[derive(Deserialize, Serialize)]
 pub struct CreateMember {
     pub name: String,
     pub last_name: String,
     pub gender: Gender,
-    pub birthday: chrono::DateTime<chrono::Utc>,
+    pub birthday: Option<PartialDate>,
+    pub death_date: Option<PartialDate>,
+    pub deceased: bool,
+    pub burial_place: Option<String>,
     pub mother_id: Option<i64>,
     pub father_id: Option<i64>,
     pub image: Option<Vec<u8>>,
@@ -27,7 +34,10 @@ pub struct CreateMemberBuilder {
     name: Option<String>,
     last_name: Option<String>,
     gender: Option<Gender>,
-    birthday: Option<chrono::DateTime<chrono::Utc>>,
+    birthday: Option<PartialDate>,
+    death_date: Option<PartialDate>,
+    deceased: bool,
+    burial_place: Option<String>,
     mother_id: Option<i64>,
     father_id: Option<i64>,
     image: Option<Vec<u8>>,
@@ -57,11 +67,26 @@ impl CreateMemberBuilder {
         self
     }
 
-    pub fn birthday(&mut self, birthday: chrono::DateTime<chrono::Utc>) -> &mut Self {
+    pub fn birthday(&mut self, birthday: PartialDate) -> &mut Self {
         self.birthday = Some(birthday);
         self
     }
 
+    pub fn death_date(&mut self, death_date: PartialDate) -> &mut Self {
+        self.death_date = Some(death_date);
+        self
+    }
+
+    pub fn deceased(&mut self, deceased: bool) -> &mut Self {
+        self.deceased = deceased;
+        self
+    }
+
+    pub fn burial_place(&mut self, burial_place: String) -> &mut Self {
+        self.burial_place = Some(burial_place);
+        self
+    }
+
     pub fn mother_id(&mut self, mother_id: i64) -> &mut Self {
         self.mother_id = Some(mother_id);
         self
@@ -95,19 +120,25 @@ impl CreateMemberBuilder {
         let gender = self
             .gender
             .ok_or(anyhow!("gender field was not provided"))?;
-        let birthday = self
-            .birthday
-            .ok_or(anyhow!("birthday field was not provided"))?;
-
         if self.image.is_some() != self.image_type.is_some() {
             return Err(anyhow!("image or image_type was not added"));
         }
 
+        if let Some((birthday, death_date)) = self.birthday.zip(self.death_date) {
+            if death_date.is_certainly_before(&birthday) {
+                return Err(anyhow!("death_date is before birthday"));
+            }
+        }
+
         Ok(CreateMember {
             name,
             last_name,
             gender,
-            birthday,
+            birthday: self.birthday,
+            // a member with a date of death is deceased even if the flag wasn't set
+            deceased: self.deceased || self.death_date.is_some(),
+            death_date: self.death_date,
+            burial_place: self.burial_place,
             mother_id: self.mother_id,
             father_id: self.father_id,
             image: self.image,
@@ -123,7 +154,10 @@ pub struct UpdateMember {
     pub name: Option<String>,
     pub last_name: Option<String>,
     pub gender: Option<Gender>,
-    pub birthday: Option<chrono::DateTime<chrono::Utc>>,
+    pub birthday: Option<PartialDate>,
+    pub death_date: Option<PartialDate>,
+    pub deceased: Option<bool>,
+    pub burial_place: Option<String>,
     pub mother_id: Option<i64>,
     pub father_id: Option<i64>,
     pub info: Option<IndexMap<String, serde_json::Value>>,
@@ -136,7 +170,13 @@ pub struct UpdateMemberBuilder {
     name: Option<String>,
     last_name: Option<String>,
     gender: Option<Gender>,
-    birthday: Option<chrono::DateTime<chrono::Utc>>,
+    birthday: Option<PartialDate>,
+    pub remove_birthday: bool,
+    death_date: Option<PartialDate>,
+    pub remove_death_date: bool,
+    deceased: Option<bool>,
+    burial_place: Option<String>,
+    pub remove_burial_place: bool,
     mother_id: Option<i64>,
     pub remove_mother_id: bool,
     father_id: Option<i64>,
@@ -169,11 +209,41 @@ impl UpdateMemberBuilder {
         self
     }
 
-    pub fn birthday(&mut self, birthday: chrono::DateTime<chrono::Utc>) -> &mut Self {
+    pub fn birthday(&mut self, birthday: PartialDate) -> &mut Self {
         self.birthday = Some(birthday);
         self
     }
 
+    pub fn remove_birthday(&mut self, remove: bool) -> &mut Self {
+        self.remove_birthday = remove;
+        self
+    }
+
+    pub fn death_date(&mut self, death_date: PartialDate) -> &mut Self {
+        self.death_date = Some(death_date);
+        self
+    }
+
+    pub fn remove_death_date(&mut self, remove: bool) -> &mut Self {
+        self.remove_death_date = remove;
+        self
+    }
+
+    pub fn deceased(&mut self, deceased: bool) -> &mut Self {
+        self.deceased = Some(deceased);
+        self
+    }
+
+    pub fn burial_place(&mut self, burial_place: String) -> &mut Self {
+        self.burial_place = Some(burial_place);
+        self
+    }
+
+    pub fn remove_burial_place(&mut self, remove: bool) -> &mut Self {
+        self.remove_burial_place = remove;
+        self
+    }
+
     pub fn mother_id(&mut self, mother_id: i64) -> &mut Self {
         self.mother_id = Some(mother_id);
         self
@@ -214,17 +284,45 @@ impl UpdateMemberBuilder {
         self
     }
 
-    pub fn build(self, id: i64) -> anyhow::Result<UpdateMember> {
+    /// `birthday` and `death_date` are the member's stored dates,
+    /// the ones that aren't being changed are checked against the new ones
+    pub fn build(
+        self,
+        id: i64,
+        birthday: Option<PartialDate>,
+        death_date: Option<PartialDate>,
+    ) -> anyhow::Result<UpdateMember> {
         if self.image.is_some() != self.image_type.is_some() {
             return Err(anyhow!("image or image_type was not added"));
         }
 
+        let birthday = match self.birthday {
+            Some(birthday) => Some(birthday),
+            None if self.remove_birthday => None,
+            None => birthday,
+        };
+        let death_date = match self.death_date {
+            Some(death_date) => Some(death_date),
+            None if self.remove_death_date => None,
+            None => death_date,
+        };
+
+        if let Some((birthday, death_date)) = birthday.zip(death_date) {
+            if death_date.is_certainly_before(&birthday) {
+                return Err(anyhow!("death_date is before birthday"));
+            }
+        }
+
         Ok(UpdateMember {
             id,
             name: self.name,
             last_name: self.last_name,
             gender: self.gender,
             birthday: self.birthday,
+            // setting a date of death marks the member as deceased
+            deceased: self.deceased.or(self.death_date.map(|_| true)),
+            death_date: self.death_date,
+            burial_place: self.burial_place,
             mother_id: self.mother_id,
             father_id: self.father_id,
             image: self.image,
@@ -241,15 +339,26 @@ pub struct MemberRow {
     pub name: String,
     pub last_name: String,
     pub gender: Gender,
-    pub birthday: Option<chrono::DateTime<chrono::Utc>>,
+    pub birthday: Option<PartialDate>,
+    #[serde(default)]
+    pub death_date: Option<PartialDate>,
+    #[serde(default)]
+    pub deceased: bool,
+    #[serde(default)]
+    pub burial_place: Option<String>,
     #[serde(skip)]
-    pub image: Option<Vec<u8>>,
+    pub image_hash: Option<String>,
     #[serde(skip)]
     pub image_type: Option<String>,
     #[serde(skip)]
     pub personal_info: Option<serde_json::Value>,
     pub mother_id: Option<i64>,
     pub father_id: Option<i64>,
+    /// only exported, see [`Nasab`]
+    #[serde(default)]
+    pub nasab: Option<String>,
+    #[serde(default)]
+    pub nasab_latin: Option<String>,
 }
 
 #[allow(dead_code)]
@@ -258,71 +367,240 @@ pub struct MemberRowWithParents {
     pub id: i64,
     pub name: String,
     pub gender: Gender,
-    pub birthday: Option<chrono::DateTime<chrono::Utc>>,
+    pub birthday: Option<PartialDate>,
+    pub death_date: Option<PartialDate>,
+    pub deceased: bool,
+    pub burial_place: Option<String>,
     pub last_name: String,
-    pub image: Option<Vec<u8>>,
-    pub image_type: Option<String>,
+    pub image_hash: Option<String>,
     pub mother_id: Option<i64>,
     pub father_id: Option<i64>,
     pub personal_info: Option<serde_json::Value>,
     pub mother_name: Option<String>,
     pub mother_gender: Option<Gender>,
-    pub mother_birthday: Option<chrono::DateTime<chrono::Utc>>,
+    pub mother_birthday: Option<PartialDate>,
     pub mother_last_name: Option<String>,
     pub father_name: Option<String>,
     pub father_gender: Option<Gender>,
-    pub father_birthday: Option<chrono::DateTime<chrono::Utc>>,
+    pub father_birthday: Option<PartialDate>,
     pub father_last_name: Option<String>,
+    /// the member's name and their fathers', see [`Nasab::new`]
+    pub nasab: String,
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct MemberResponse {
     pub id: i64,
     pub name: String,
+    pub nasab: Nasab,
     pub gender: Gender,
-    pub birthday: Option<DateTime<Utc>>,
+    /// in the gregorian calendar
+    pub birthday: Option<PartialDate>,
+    pub birthday_hijri: Option<PartialDate>,
+    /// in the gregorian calendar
+    pub death_date: Option<PartialDate>,
+    pub death_date_hijri: Option<PartialDate>,
+    pub deceased: bool,
+    pub burial_place: Option<String>,
     pub last_name: String,
     pub father_id: Option<i64>,
     pub mother_id: Option<i64>,
     pub personal_info: Option<IndexMap<String, String>>,
+    pub spouses: Vec<SpouseResponse>,
     pub children: Vec<MemberResponse>,
-    pub image: Option<Vec<u8>>,
-    pub image_type: Option<String>,
+    /// the ids of the children that are built elsewhere in the tree,
+    /// under their father
+    pub child_refs: Vec<i64>,
+    pub images: Option<ImageUrls>,
+}
+
+/// The sizes member images are served in
+#[derive(Debug, Default, Clone, Copy, PartialEq, sqlx::Type, Deserialize, Serialize)]
+#[sqlx(type_name = "image_size", rename_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum ImageSize {
+    /// a small square for the tree nodes
+    Thumbnail,
+    /// for the member info window
+    Medium,
+    #[default]
+    Original,
+}
+
+impl std::fmt::Display for ImageSize {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ImageSize::Thumbnail => write!(f, "thumbnail"),
+            ImageSize::Medium => write!(f, "medium"),
+            ImageSize::Original => write!(f, "original"),
+        }
+    }
+}
+
+/// Where to get each size of a member's image from
+///
+/// the hash of the image is part of the urls so they change with the image,
+/// which lets clients cache the image for as long as the url stays the same
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct ImageUrls {
+    pub thumbnail: String,
+    pub medium: String,
+    pub original: String,
+}
+
+impl ImageUrls {
+    /// `None` when the member has no image
+    pub fn new(id: i64, image_hash: Option<&str>) -> Option<Self> {
+        let hash = image_hash?;
+        let url = |size: ImageSize| format!("/api/members/{id}/image?size={size}&v={hash}");
+
+        Some(ImageUrls {
+            thumbnail: url(ImageSize::Thumbnail),
+            medium: url(ImageSize::Medium),
+            original: url(ImageSize::Original),
+        })
+    }
 }
 
 impl MemberResponse {
-    pub fn add_all_children(&mut self, all_members: &[MemberRowWithParents]) {
-        self.children = all_members
-            .iter()
-            .filter(|m| {
-                m.father_id.is_some_and(|fid| fid == self.id)
-                    || m.mother_id.is_some_and(|mid| mid == self.id)
-            })
-            .map(|m| MemberResponse {
-                id: m.id,
-                name: m.name.clone(),
-                gender: m.gender,
-                birthday: m.birthday,
-                last_name: m.last_name.clone(),
-                father_id: m.father_id,
-                mother_id: m.mother_id,
-                personal_info: m.personal_info.as_ref().and_then(|p| {
-                    p.as_object().map(|o| {
-                        o.into_iter()
-                            .map(|(k, v)| (k.to_string(), v.as_str().unwrap_or("").to_string()))
-                            .rev()
-                            .collect::<IndexMap<String, String>>()
-                    })
-                }),
-                children: vec![],
-                image: m.image.clone(),
-                image_type: m.image_type.clone(),
-            })
-            .collect();
-        for child in &mut self.children {
-            child.add_all_children(all_members);
+    fn from_row(m: &MemberRowWithParents, spouses: Vec<SpouseResponse>) -> Self {
+        MemberResponse {
+            id: m.id,
+            name: m.name.clone(),
+            nasab: Nasab::new(&m.nasab, &m.last_name),
+            gender: m.gender,
+            birthday: m.birthday.map(|d| d.to_gregorian()),
+            birthday_hijri: m.birthday.and_then(|d| d.to_hijri()),
+            death_date: m.death_date.map(|d| d.to_gregorian()),
+            death_date_hijri: m.death_date.and_then(|d| d.to_hijri()),
+            deceased: m.deceased,
+            burial_place: m.burial_place.clone(),
+            last_name: m.last_name.clone(),
+            father_id: m.father_id,
+            mother_id: m.mother_id,
+            personal_info: m.personal_info.as_ref().and_then(|p| {
+                p.as_object().map(|o| {
+                    o.into_iter()
+                        .map(|(k, v)| (k.to_string(), v.as_str().unwrap_or("").to_string()))
+                        .rev()
+                        .collect::<IndexMap<String, String>>()
+                })
+            }),
+            spouses,
+            children: Vec::new(),
+            child_refs: Vec::new(),
+            images: ImageUrls::new(m.id, m.image_hash.as_deref()),
+        }
+    }
+}
+
+/// Build the tree under `root_id` out of `members`
+///
+/// the children and spouses of every member are indexed once up front,
+/// so building a member doesn't go over the members and marriages again.
+/// every member is built once: a child of two members in the tree, e.g. from a marriage
+/// between cousins, is built under the father and only referenced by id under the mother,
+/// and a member that's their own ancestor is referenced instead of built again
+pub fn build_tree(
+    root_id: i64,
+    members: &[MemberRowWithParents],
+    marriages: &[MarriageRowWithSpouses],
+) -> Option<MemberResponse> {
+    let mut children: HashMap<i64, Vec<&MemberRowWithParents>> = HashMap::new();
+    let mut root = None;
+
+    for member in members {
+        if member.id == root_id {
+            root = Some(member);
+        }
+
+        for parent_id in [member.father_id, member.mother_id].into_iter().flatten() {
+            children.entry(parent_id).or_default().push(member);
+        }
+    }
+
+    let root = root?;
+    let spouses = spouses_by_member(marriages);
+
+    // the members under the root, to know whether a child's father is in the tree
+    let mut in_tree = HashSet::from([root.id]);
+    let mut stack = vec![root.id];
+    while let Some(id) = stack.pop() {
+        for child in children.get(&id).into_iter().flatten() {
+            if in_tree.insert(child.id) {
+                stack.push(child.id);
+            }
+        }
+    }
+
+    fn build(
+        member: &MemberRowWithParents,
+        children: &HashMap<i64, Vec<&MemberRowWithParents>>,
+        spouses: &HashMap<i64, Vec<SpouseResponse>>,
+        in_tree: &HashSet<i64>,
+        built: &mut HashSet<i64>,
+    ) -> MemberResponse {
+        let mut response =
+            MemberResponse::from_row(member, spouses.get(&member.id).cloned().unwrap_or_default());
+
+        built.insert(member.id);
+        for child in children.get(&member.id).into_iter().flatten() {
+            let under_father = child
+                .father_id
+                .is_some_and(|father_id| father_id != member.id && in_tree.contains(&father_id));
+
+            if under_father || built.contains(&child.id) {
+                response.child_refs.push(child.id);
+            } else {
+                response
+                    .children
+                    .push(build(child, children, spouses, in_tree, built));
+            }
+        }
+
+        response
+    }
+
+    Some(build(
+        root,
+        &children,
+        &spouses,
+        &in_tree,
+        &mut HashSet::new(),
+    ))
+}
+
+/// the spouses of every member that's married, ordered by their marriage order
+pub fn spouses_by_member(
+    marriages: &[MarriageRowWithSpouses],
+) -> HashMap<i64, Vec<SpouseResponse>> {
+    let mut spouses: HashMap<i64, Vec<SpouseResponse>> = HashMap::new();
+
+    for marriage in marriages {
+        for member_id in [marriage.husband_id, marriage.wife_id] {
+            if let Some(spouse) = marriage.spouse_of(member_id) {
+                spouses.entry(member_id).or_default().push(spouse);
+            }
         }
     }
+
+    for member_spouses in spouses.values_mut() {
+        member_spouses.sort_by_key(|s| s.marriage_order);
+    }
+
+    spouses
+}
+
+/// collects the spouses of a member ordered by their marriage order
+pub fn spouses_of(member_id: i64, marriages: &[MarriageRowWithSpouses]) -> Vec<SpouseResponse> {
+    let mut spouses = marriages
+        .iter()
+        .filter_map(|m| m.spouse_of(member_id))
+        .collect::<Vec<SpouseResponse>>();
+
+    spouses.sort_by_key(|s| s.marriage_order);
+
+    spouses
 }
 
 /// non-recursive MemberResponse
@@ -330,14 +608,457 @@ impl MemberResponse {
 pub struct MemberResponseBrief {
     pub id: i64,
     pub name: String,
+    pub nasab: Nasab,
     pub gender: Gender,
-    pub birthday: Option<DateTime<Utc>>,
+    /// in the gregorian calendar
+    pub birthday: Option<PartialDate>,
+    pub birthday_hijri: Option<PartialDate>,
+    /// in the gregorian calendar
+    pub death_date: Option<PartialDate>,
+    pub death_date_hijri: Option<PartialDate>,
+    pub deceased: bool,
+    pub burial_place: Option<String>,
     pub last_name: String,
     pub father_id: Option<i64>,
     pub mother_id: Option<i64>,
     pub personal_info: Option<IndexMap<String, String>>,
-    pub image: Option<Vec<u8>>,
-    pub image_type: Option<String>,
+    pub images: Option<ImageUrls>,
+}
+
+impl From<MemberRowWithParents> for MemberResponseBrief {
+    fn from(m: MemberRowWithParents) -> Self {
+        MemberResponseBrief {
+            id: m.id,
+            nasab: Nasab::new(&m.nasab, &m.last_name),
+            name: m.name,
+            gender: m.gender,
+            birthday: m.birthday.map(|d| d.to_gregorian()),
+            birthday_hijri: m.birthday.and_then(|d| d.to_hijri()),
+            death_date: m.death_date.map(|d| d.to_gregorian()),
+            death_date_hijri: m.death_date.and_then(|d| d.to_hijri()),
+            deceased: m.deceased,
+            burial_place: m.burial_place,
+            last_name: m.last_name,
+            father_id: m.father_id,
+            mother_id: m.mother_id,
+            personal_info: m.personal_info.as_ref().and_then(|p| {
+                p.as_object().map(|o| {
+                    o.into_iter()
+                        .map(|(k, v)| (k.to_string(), v.as_str().unwrap_or("").to_string()))
+                        .collect::<IndexMap<String, String>>()
+                })
+            }),
+            images: ImageUrls::new(m.id, m.image_hash.as_deref()),
+        }
+    }
+}
+
+/// An ancestor of a member, and how many generations up from the member they are
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct AncestorResponse {
+    /// 1 for parents, 2 for grandparents, the closest way up when there's more than one
+    pub generation: i32,
+    #[serde(flatten)]
+    pub member: MemberResponseBrief,
+}
+
+/// A member that shares a parent with another member
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct SiblingResponse {
+    /// whether only one parent is shared, when both members have both parents
+    pub half: bool,
+    #[serde(flatten)]
+    pub member: MemberResponseBrief,
+}
+
+/// A member with no parents, and the size of the family under them
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct RootMemberResponse {
+    pub id: i64,
+    pub name: String,
+    pub last_name: String,
+    pub gender: Gender,
+    pub descendants_count: i64,
+}
+
+/// The parts of a member needed to walk the tree
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct MemberLinkRow {
+    pub id: i64,
+    pub name: String,
+    pub last_name: String,
+    pub gender: Gender,
+    pub mother_id: Option<i64>,
+    pub father_id: Option<i64>,
+    pub deleted_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize, Serialize, PartialEq)]
+#[serde(tag = "reason", rename_all = "snake_case")]
+pub enum UnreachableReason {
+    /// the member points to a parent that doesn't exist
+    MissingParent { parent_id: i64 },
+    /// the member's parent was moved to the trash
+    ParentInTrash { parent_id: i64 },
+    /// the member has no parents, isn't the root, and isn't married to anyone in the tree
+    NoParents,
+    /// the member's line goes up to a member that isn't connected to the tree
+    DisconnectedAncestor { ancestor_id: i64 },
+    /// the member is their own ancestor
+    AncestryCycle,
+}
+
+impl UnreachableReason {
+    pub fn describe(&self) -> String {
+        match self {
+            UnreachableReason::MissingParent { parent_id } => {
+                format!("مرتبط بوالد غير موجود ({parent_id})")
+            }
+            UnreachableReason::ParentInTrash { parent_id } => {
+                format!("أحد والديه في سلة المحذوفات ({parent_id})")
+            }
+            UnreachableReason::NoParents => String::from("بدون والدين"),
+            UnreachableReason::DisconnectedAncestor { ancestor_id } => {
+                format!("ينتمي إلى فرع غير مرتبط بالشجرة ({ancestor_id})")
+            }
+            UnreachableReason::AncestryCycle => String::from("جزء من حلقة في النسب"),
+        }
+    }
+}
+
+/// A member that can't be reached from the root of the tree
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct UnreachableMemberResponse {
+    pub id: i64,
+    pub name: String,
+    pub last_name: String,
+    pub gender: Gender,
+    pub father_id: Option<i64>,
+    pub mother_id: Option<i64>,
+    #[serde(flatten)]
+    pub reason: UnreachableReason,
+}
+
+/// A member on the line between two relatives
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct RelativeResponse {
+    pub id: i64,
+    pub name: String,
+    pub last_name: String,
+    pub gender: Gender,
+}
+
+impl From<&MemberLinkRow> for RelativeResponse {
+    fn from(member: &MemberLinkRow) -> Self {
+        Self {
+            id: member.id,
+            name: member.name.clone(),
+            last_name: member.last_name.clone(),
+            gender: member.gender,
+        }
+    }
+}
+
+/// How a member is related by blood to another one
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct RelationshipResponse {
+    pub from_id: i64,
+    pub to_id: i64,
+    /// what `to` is to `from`, e.g. ابن عم
+    pub arabic: String,
+    /// what `to` is to `from`, e.g. first cousin once removed
+    pub english: String,
+    /// generations from `from` up to the common ancestors
+    pub generations_up: u32,
+    /// generations from the common ancestors down to `to`
+    pub generations_down: u32,
+    /// 1 for first cousins, none for closer relatives
+    pub cousin_degree: Option<u32>,
+    /// how many generations apart cousins are
+    pub removed: Option<u32>,
+    /// whether the line goes through only one of two known parents, e.g. half brothers
+    pub half: bool,
+    /// the closest ancestors `from` and `to` share, both parents when they share the couple
+    pub common_ancestors: Vec<RelativeResponse>,
+    /// from `from` up to a common ancestor and down to `to`
+    pub path: Vec<RelativeResponse>,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize, Serialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ParentSide {
+    Father,
+    Mother,
+}
+
+impl ParentSide {
+    pub fn gender(&self) -> Gender {
+        match self {
+            ParentSide::Father => Gender::Male,
+            ParentSide::Mother => Gender::Female,
+        }
+    }
+}
+
+impl std::fmt::Display for ParentSide {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ParentSide::Father => write!(f, "father"),
+            ParentSide::Mother => write!(f, "mother"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Deserialize, Serialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ParentProblem {
+    /// the member is their own parent
+    OwnParent,
+    /// a father that isn't male or a mother that isn't female
+    WrongGender,
+    /// the parent is also a descendant of the member
+    AncestryCycle,
+    /// the parent was born after the member or too shortly before them
+    ParentTooYoung,
+    /// the parent was born too long before the member
+    ParentTooOld,
+    /// the parent died before the member could have been born
+    ParentDiedBeforeBirth,
+    /// the parent is in the trash
+    ParentDeleted,
+}
+
+/// A parent link that can't be right
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
+pub struct InvalidParent {
+    pub member_id: i64,
+    pub parent_id: i64,
+    pub side: ParentSide,
+    pub problem: ParentProblem,
+}
+
+impl std::fmt::Display for InvalidParent {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let Self {
+            member_id,
+            parent_id,
+            side,
+            problem,
+        } = self;
+
+        write!(f, "member {member_id}: {side} {parent_id} ")?;
+
+        match problem {
+            ParentProblem::OwnParent => write!(f, "is the member themselves"),
+            ParentProblem::WrongGender => write!(f, "is not {}", side.gender()),
+            ParentProblem::AncestryCycle => write!(f, "is also a descendant of the member"),
+            ParentProblem::ParentTooYoung => write!(f, "was born too shortly before the member"),
+            ParentProblem::ParentTooOld => write!(f, "was born too long before the member"),
+            ParentProblem::ParentDiedBeforeBirth => {
+                write!(f, "died before the member was born")
+            }
+            ParentProblem::ParentDeleted => write!(f, "is in the trash"),
+        }
+    }
+}
+
+/// A member in the trash
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct DeletedMemberResponse {
+    pub id: i64,
+    pub name: String,
+    pub last_name: String,
+    pub gender: Gender,
+    pub father_id: Option<i64>,
+    pub mother_id: Option<i64>,
+    pub deleted_at: DateTime<Utc>,
+}
+
+/// What to do with a member's children when the member is deleted
+#[derive(Debug, Clone, Copy, Default, Deserialize, Serialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum DeletePolicy {
+    /// don't delete a member that still has children
+    #[default]
+    Refuse,
+    /// move the children to the deleted member's own parent
+    Reparent,
+    /// delete the member with all their descendants
+    Cascade,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct AffectedMember {
+    pub id: i64,
+    pub name: String,
+    pub last_name: String,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct DeleteMemberResponse {
+    pub policy: DeletePolicy,
+    /// the members moved to the trash, the requested member first
+    pub deleted: Vec<AffectedMember>,
+    /// the children that now point at the deleted member's parent
+    pub reparented: Vec<AffectedMember>,
+}
+
+#[derive(Debug, Clone, Copy, sqlx::Type, Serialize, Deserialize, PartialEq)]
+#[sqlx(type_name = "member_change_action", rename_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum ChangeAction {
+    Create,
+    Edit,
+    Delete,
+    Restore,
+    Purge,
+    Import,
+    Approve,
+    Revert,
+    RestoreBackup,
+}
+
+impl ChangeAction {
+    pub fn describe(&self) -> &'static str {
+        match self {
+            ChangeAction::Create => "إضافة",
+            ChangeAction::Edit => "تعديل",
+            ChangeAction::Delete => "حذف",
+            ChangeAction::Restore => "استعادة",
+            ChangeAction::Purge => "حذف نهائي",
+            ChangeAction::Import => "استيراد",
+            ChangeAction::Approve => "قبول طلب",
+            ChangeAction::Revert => "إرجاع",
+            ChangeAction::RestoreBackup => "استعادة نسخة احتياطية",
+        }
+    }
+
+    /// whether the member still exists after this kind of change,
+    /// so it can be reverted to
+    pub fn leaves_member(&self) -> bool {
+        *self != ChangeAction::Purge
+    }
+}
+
+/// A member's fields as they were at some point, kept in the change history
+///
+/// their image, marriages and media are kept too, so changes to them are in the history,
+/// but a revert only brings back the member's own fields
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct MemberSnapshot {
+    pub id: i64,
+    pub name: String,
+    pub last_name: String,
+    pub gender: Gender,
+    #[serde(default)]
+    pub birthday: Option<PartialDate>,
+    #[serde(default)]
+    pub death_date: Option<PartialDate>,
+    #[serde(default)]
+    pub deceased: bool,
+    #[serde(default)]
+    pub burial_place: Option<String>,
+    pub father_id: Option<i64>,
+    pub mother_id: Option<i64>,
+    #[serde(default)]
+    pub personal_info: Option<serde_json::Value>,
+    #[serde(default)]
+    pub deleted_at: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub image_hash: Option<String>,
+    #[serde(default)]
+    pub marriages: Option<serde_json::Value>,
+    #[serde(default)]
+    pub media: Option<serde_json::Value>,
+}
+
+#[allow(dead_code)]
+#[derive(Debug, sqlx::FromRow)]
+pub struct MemberChangeRow {
+    pub id: i64,
+    pub member_id: i64,
+    pub user_id: Option<Uuid>,
+    pub username: Option<String>,
+    pub action: ChangeAction,
+    pub changed_at: DateTime<Utc>,
+    pub before: Option<serde_json::Value>,
+    pub after: Option<serde_json::Value>,
+}
+
+/// A field that changed, `null` stands for no value
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct FieldChange {
+    pub field: String,
+    pub before: serde_json::Value,
+    pub after: serde_json::Value,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct MemberChangeResponse {
+    pub id: i64,
+    pub member_id: i64,
+    /// the member's name after the change, or before it if the change removed them
+    pub name: String,
+    pub last_name: String,
+    pub action: ChangeAction,
+    /// the user who made the change, `None` if they were removed
+    pub user_id: Option<Uuid>,
+    pub username: Option<String>,
+    pub changed_at: DateTime<Utc>,
+    pub changes: Vec<FieldChange>,
+}
+
+impl From<MemberChangeRow> for MemberChangeResponse {
+    fn from(row: MemberChangeRow) -> Self {
+        let empty = serde_json::Map::new();
+        let before = row
+            .before
+            .as_ref()
+            .and_then(|b| b.as_object())
+            .unwrap_or(&empty);
+        let after = row
+            .after
+            .as_ref()
+            .and_then(|a| a.as_object())
+            .unwrap_or(&empty);
+
+        let name_of = |field: &str| {
+            after
+                .get(field)
+                .or_else(|| before.get(field))
+                .and_then(|v| v.as_str())
+                .unwrap_or_default()
+                .to_string()
+        };
+
+        let changes = before
+            .keys()
+            .chain(after.keys().filter(|k| !before.contains_key(*k)))
+            .filter(|field| *field != "id")
+            .filter_map(|field| {
+                let before = before.get(field).cloned().unwrap_or_default();
+                let after = after.get(field).cloned().unwrap_or_default();
+
+                (before != after).then(|| FieldChange {
+                    field: field.clone(),
+                    before,
+                    after,
+                })
+            })
+            .collect();
+
+        Self {
+            id: row.id,
+            member_id: row.member_id,
+            name: name_of("name"),
+            last_name: name_of("last_name"),
+            action: row.action,
+            user_id: row.user_id,
+            username: row.username,
+            changed_at: row.changed_at,
+            changes,
+        }
+    }
 }
 
 #[allow(dead_code)]
@@ -346,9 +1067,12 @@ pub struct RequestedMemberRow {
     pub id: Uuid,
     pub name: String,
     pub gender: Gender,
-    pub birthday: Option<chrono::DateTime<chrono::Utc>>,
+    pub birthday: Option<PartialDate>,
+    pub death_date: Option<PartialDate>,
+    pub deceased: bool,
+    pub burial_place: Option<String>,
     pub last_name: String,
-    pub image: Option<Vec<u8>>,
+    pub image_hash: Option<String>,
     pub image_type: Option<String>,
     pub mother_id: Option<i64>,
     pub father_id: Option<i64>,
@@ -362,20 +1086,23 @@ pub struct RequestedMemberRowWithParents {
     pub id: Uuid,
     pub name: String,
     pub gender: Gender,
-    pub birthday: Option<chrono::DateTime<chrono::Utc>>,
+    pub birthday: Option<PartialDate>,
+    pub death_date: Option<PartialDate>,
+    pub deceased: bool,
+    pub burial_place: Option<String>,
     pub last_name: String,
-    pub image: Option<Vec<u8>>,
+    pub image_hash: Option<String>,
     pub image_type: Option<String>,
     pub mother_id: Option<i64>,
     pub father_id: Option<i64>,
     pub personal_info: Option<serde_json::Value>,
     pub mother_name: Option<String>,
     pub mother_gender: Option<Gender>,
-    pub mother_birthday: Option<chrono::DateTime<chrono::Utc>>,
+    pub mother_birthday: Option<PartialDate>,
     pub mother_last_name: Option<String>,
     pub father_name: Option<String>,
     pub father_gender: Option<Gender>,
-    pub father_birthday: Option<chrono::DateTime<chrono::Utc>>,
+    pub father_birthday: Option<PartialDate>,
     pub father_last_name: Option<String>,
     pub status: RequestStatus,
 }
@@ -385,12 +1112,20 @@ pub struct RequestedMemberResponseBrief {
     pub id: Uuid,
     pub name: String,
     pub gender: Gender,
-    pub birthday: Option<DateTime<Utc>>,
+    /// in the gregorian calendar
+    pub birthday: Option<PartialDate>,
+    pub birthday_hijri: Option<PartialDate>,
+    /// in the gregorian calendar
+    pub death_date: Option<PartialDate>,
+    pub death_date_hijri: Option<PartialDate>,
+    pub deceased: bool,
+    pub burial_place: Option<String>,
     pub last_name: String,
     pub father_id: Option<i64>,
     pub mother_id: Option<i64>,
     pub personal_info: Option<IndexMap<String, String>>,
-    pub image: Option<Vec<u8>>,
+    /// the key of the image in the storage
+    pub image_hash: Option<String>,
     pub image_type: Option<String>,
     pub status: RequestStatus,
 }
@@ -410,3 +1145,288 @@ pub struct MemberInvite {
     #[garde(email)]
     pub email: String,
 }
+
+#[derive(Debug, Clone, Copy, sqlx::Type, Serialize, Deserialize, PartialEq)]
+#[sqlx(type_name = "marriage_end_reason", rename_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum MarriageEndReason {
+    Divorce,
+    Death,
+}
+
+/// Used for both adding and editing a marriage,
+/// editing replaces all the fields of the marriage
+#[derive(Debug, Deserialize, Serialize)]
+pub struct CreateMarriage {
+    pub husband_id: i64,
+    pub wife_id: i64,
+    pub married_at: Option<NaiveDate>,
+    pub ended_at: Option<NaiveDate>,
+    pub end_reason: Option<MarriageEndReason>,
+    /// the order of this marriage among the husband's marriages,
+    /// defaults to the one after his last marriage
+    pub marriage_order: Option<i32>,
+}
+
+#[derive(Debug, sqlx::FromRow)]
+pub struct MarriageRowWithSpouses {
+    pub id: i64,
+    pub husband_id: i64,
+    pub wife_id: i64,
+    pub married_at: Option<chrono::DateTime<chrono::Utc>>,
+    pub ended_at: Option<chrono::DateTime<chrono::Utc>>,
+    pub end_reason: Option<MarriageEndReason>,
+    pub marriage_order: i32,
+    pub husband_name: String,
+    pub husband_last_name: String,
+    pub wife_name: String,
+    pub wife_last_name: String,
+}
+
+impl MarriageRowWithSpouses {
+    /// returns the other side of the marriage if `member_id` is part of it
+    pub fn spouse_of(&self, member_id: i64) -> Option<SpouseResponse> {
+        let (id, name, last_name, gender) = if self.husband_id == member_id {
+            (
+                self.wife_id,
+                &self.wife_name,
+                &self.wife_last_name,
+                Gender::Female,
+            )
+        } else if self.wife_id == member_id {
+            (
+                self.husband_id,
+                &self.husband_name,
+                &self.husband_last_name,
+                Gender::Male,
+            )
+        } else {
+            return None;
+        };
+
+        Some(SpouseResponse {
+            id,
+            marriage_id: self.id,
+            name: name.clone(),
+            last_name: last_name.clone(),
+            gender,
+            married_at: self.married_at,
+            ended_at: self.ended_at,
+            end_reason: self.end_reason,
+            marriage_order: self.marriage_order,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct MarriageResponse {
+    pub id: i64,
+    pub husband_id: i64,
+    pub wife_id: i64,
+    pub married_at: Option<DateTime<Utc>>,
+    pub ended_at: Option<DateTime<Utc>>,
+    pub end_reason: Option<MarriageEndReason>,
+    pub marriage_order: i32,
+}
+
+/// A member's spouse as seen from that member
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct SpouseResponse {
+    /// the spouse's member id
+    pub id: i64,
+    pub marriage_id: i64,
+    pub name: String,
+    pub last_name: String,
+    pub gender: Gender,
+    pub married_at: Option<DateTime<Utc>>,
+    pub ended_at: Option<DateTime<Utc>>,
+    pub end_reason: Option<MarriageEndReason>,
+    pub marriage_order: i32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, sqlx::Type, Deserialize, Serialize)]
+#[sqlx(type_name = "media_kind", rename_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum MediaKind {
+    Photo,
+    /// a scanned document, like a birth certificate or an old letter
+    Document,
+}
+
+#[derive(Debug, sqlx::FromRow)]
+pub struct MediaRow {
+    pub id: i64,
+    pub member_id: i64,
+    pub kind: MediaKind,
+    pub caption: Option<String>,
+    pub date: Option<PartialDate>,
+    pub position: i32,
+    pub file_name: Option<String>,
+    pub content_type: String,
+    pub content_hash: String,
+    pub has_thumbnail: bool,
+    /// the member's image is the same as this media
+    pub is_profile: bool,
+    pub uploaded_at: DateTime<Utc>,
+}
+
+/// A photo or document in a member's gallery
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct MediaResponse {
+    pub id: i64,
+    pub member_id: i64,
+    pub kind: MediaKind,
+    pub caption: Option<String>,
+    /// in the gregorian calendar
+    pub date: Option<PartialDate>,
+    pub date_hijri: Option<PartialDate>,
+    /// the gallery is sorted by this
+    pub position: i32,
+    /// the name of the uploaded file
+    pub file_name: Option<String>,
+    pub content_type: String,
+    pub url: String,
+    /// only images have thumbnails
+    pub thumbnail_url: Option<String>,
+    pub is_profile: bool,
+    pub uploaded_at: DateTime<Utc>,
+}
+
+impl From<MediaRow> for MediaResponse {
+    fn from(m: MediaRow) -> Self {
+        let url = |size: ImageSize| {
+            format!(
+                "/api/members/{}/media/{}?size={size}&v={}",
+                m.member_id, m.id, m.content_hash
+            )
+        };
+
+        MediaResponse {
+            id: m.id,
+            member_id: m.member_id,
+            kind: m.kind,
+            caption: m.caption,
+            date: m.date.map(|d| d.to_gregorian()),
+            date_hijri: m.date.and_then(|d| d.to_hijri()),
+            position: m.position,
+            file_name: m.file_name,
+            content_type: m.content_type,
+            url: url(ImageSize::Original),
+            thumbnail_url: m.has_thumbnail.then(|| url(ImageSize::Thumbnail)),
+            is_profile: m.is_profile,
+            uploaded_at: m.uploaded_at,
+        }
+    }
+}
+
+/// The fields of a media that can be changed after it's uploaded,
+/// the caption and date are replaced
+#[derive(Debug, Deserialize)]
+pub struct UpdateMedia {
+    pub caption: Option<String>,
+    pub date: Option<PartialDate>,
+    pub position: Option<i32>,
+}
+
+/// What was imported from a GEDCOM file and what was left out
+#[derive(Debug, Default, Serialize)]
+pub struct GedcomImportResponse {
+    pub members_added: usize,
+    pub marriages_added: usize,
+    /// tags that have no place in a member or a marriage, grouped by where they appear
+    pub unmapped_tags: Vec<UnmappedTag>,
+    /// lines or records that were read only partially or skipped
+    pub problems: Vec<GedcomProblem>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct UnmappedTag {
+    /// the tag and the tags it's nested in, e.g. "INDI.BIRT.SOUR"
+    pub path: String,
+    pub count: usize,
+    pub first_line: usize,
+}
+
+#[derive(Debug, Serialize)]
+pub struct GedcomProblem {
+    /// the line in the file the problem was found on
+    pub line: usize,
+    pub message: String,
+}
+
+impl GedcomProblem {
+    pub fn new(line: usize, message: &str) -> Self {
+        Self {
+            line,
+            message: message.to_string(),
+        }
+    }
+}
+
+/// How the ids in a members CSV file are used
+#[derive(Debug, Clone, Copy, Default, Deserialize, Serialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum CsvImportMode {
+    /// the ids are member ids, rows replace the members with the same id
+    #[default]
+    Replace,
+    /// the ids only link the rows of the file to each other, every row is added as a new member
+    /// unless its `existing_id` column names the member it stands for
+    Merge,
+}
+
+/// What importing a members CSV file does, or would do on a dry run
+#[derive(Debug, Default, Serialize)]
+pub struct CsvImportReport {
+    pub dry_run: bool,
+    pub new: Vec<CsvImportRow>,
+    pub updated: Vec<CsvImportRow>,
+    pub unchanged: Vec<CsvImportRow>,
+    pub errors: Vec<CsvRowError>,
+    /// sent back as `confirm` to import a file that updates existing members
+    pub confirmation: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CsvImportRow {
+    /// the line of the row in the file
+    pub row: usize,
+    /// the id in the file
+    pub id: i64,
+    /// the member the row was written to, unknown for new members on a dry run of a merge
+    pub member_id: Option<i64>,
+    pub name: String,
+    pub last_name: String,
+    /// the fields of an existing member the row changes
+    pub changed_fields: Vec<String>,
+}
+
+impl CsvImportRow {
+    pub fn new(row: usize, member: &MemberRow, changed_fields: Vec<String>) -> Self {
+        Self {
+            row,
+            id: member.id,
+            member_id: Some(member.id),
+            name: member.name.clone(),
+            last_name: member.last_name.clone(),
+            changed_fields,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct CsvRowError {
+    /// the line of the row in the file
+    pub row: usize,
+    pub column: Option<String>,
+    pub message: String,
+}
+
+impl std::fmt::Display for CsvRowError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.column {
+            Some(column) => write!(f, "row {}, {column}: {}", self.row, self.message),
+            None => write!(f, "row {}: {}", self.row, self.message),
+        }
+    }
+}
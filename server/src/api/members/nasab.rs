@@ -0,0 +1,38 @@
+use serde::{Deserialize, Serialize};
+
+use super::transliteration::to_latin;
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct NasabConfig {
+    /// how many fathers are named after a member, e.g. 2 gives محمد بن أحمد بن علي
+    pub depth: u32,
+}
+
+impl Default for NasabConfig {
+    fn default() -> Self {
+        Self { depth: 3 }
+    }
+}
+
+/// The full name of a member, their name and their fathers' names followed by the last name
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Nasab {
+    pub arabic: String,
+    pub latin: String,
+}
+
+impl Nasab {
+    /// `chain` is the member's name and their fathers' as `member_nasabs` gives it in the database
+    pub fn new(chain: &str, last_name: &str) -> Self {
+        let arabic = if last_name.is_empty() {
+            chain.to_string()
+        } else {
+            format!("{chain} {last_name}")
+        };
+
+        Self {
+            latin: to_latin(&arabic),
+            arabic,
+        }
+    }
+}
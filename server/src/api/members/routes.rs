@@ -1,102 +1,411 @@
-use std::sync::Arc;
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    sync::Arc,
+};
 
 use axum::{
     extract::{Multipart, Path, Query, State},
-    response::IntoResponse,
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
     Json,
 };
-use chrono::{NaiveDate, NaiveTime, Utc};
+use chrono::{DateTime, NaiveDate, NaiveTime, Utc};
 use indexmap::IndexMap;
 use serde::Deserialize;
 use serde_with::{serde_as, DisplayFromStr};
+use sqlx::{PgConnection, PgPool};
 use uuid::Uuid;
 
-use crate::{api::users::models::UserRole, auth::AuthExtractor, Gender, InnerAppState};
+use crate::{
+    api::users::models::UserRole,
+    auth::AuthExtractor,
+    storage::{content_key, Storage},
+    Gender, InnerAppState,
+};
 
 use super::{
+    csv_import::{self, CsvRow},
+    dates::PartialDate,
+    gedcom,
+    history::{record_changes, snapshots},
+    images, kinship,
     models::{
-        CreateMemberBuilder, MemberResponse, MemberResponseBrief, MemberRow, MemberRowWithParents,
-        RequestStatus, RequestedMemberResponseBrief, RequestedMemberRow,
-        RequestedMemberRowWithParents, UpdateMemberBuilder,
+        build_tree, spouses_of, AffectedMember, AncestorResponse, ChangeAction, CreateMarriage,
+        CreateMemberBuilder, CsvImportMode, CsvImportReport, CsvImportRow, CsvRowError,
+        DeleteMemberResponse, DeletePolicy, DeletedMemberResponse, GedcomImportResponse,
+        GedcomProblem, ImageSize, InvalidParent, MarriageEndReason, MarriageResponse,
+        MarriageRowWithSpouses, MediaKind, MediaResponse, MediaRow, MemberChangeResponse,
+        MemberChangeRow, MemberLinkRow, MemberResponse, MemberResponseBrief, MemberRow,
+        MemberRowWithParents, MemberSnapshot, ParentSide, RelationshipResponse, RequestStatus,
+        RequestedMemberResponseBrief, RequestedMemberRow, RequestedMemberRowWithParents,
+        RootMemberResponse, SiblingResponse, SpouseResponse, UnreachableMemberResponse,
+        UnreachableReason, UpdateMedia, UpdateMemberBuilder,
     },
+    nasab::Nasab,
+    search,
+    validation::{check_parents, parent_problems},
     MembersError,
 };
 
-const FIELDS_LIMIT: i32 = 10;
+const FIELDS_LIMIT: i32 = 13;
+
+/// dates are stored as timestamps at the start of the day
+fn date_to_timestamp(date: NaiveDate) -> DateTime<Utc> {
+    date.and_time(NaiveTime::from_hms_opt(0, 0, 1).expect("00:00:01 should be a valid time"))
+        .and_utc()
+}
+
+#[serde_as]
+#[derive(Clone, Deserialize)]
+pub struct MembersParams {
+    /// the member to build the tree from,
+    /// defaults to the first member with no parents
+    #[serde_as(as = "Option<DisplayFromStr>")]
+    pub root: Option<i64>,
+}
 
 /// Get family members
+///
+/// only the root and their descendants are loaded, see [`build_tree`]
 #[axum::debug_handler]
 pub async fn get_members(
     State(state): State<Arc<InnerAppState>>,
+    Query(params): Query<MembersParams>,
 ) -> anyhow::Result<Json<Option<MemberResponse>>, MembersError> {
+    let root_id = match params.root {
+        Some(root_id) => root_id,
+        None => {
+            let root_id = sqlx::query_scalar!(
+                r#"
+SELECT min(id) FROM members
+WHERE father_id IS NULL AND mother_id IS NULL AND deleted_at IS NULL"#,
+            )
+            .fetch_one(&state.db_pool)
+            .await?;
+
+            match root_id {
+                Some(root_id) => root_id,
+                None => {
+                    let any_members = sqlx::query_scalar!(
+                        r#"SELECT EXISTS(SELECT 1 FROM members WHERE deleted_at IS NULL) as "exists!""#,
+                    )
+                    .fetch_one(&state.db_pool)
+                    .await?;
+
+                    if !any_members {
+                        return Ok(Json(None));
+                    }
+
+                    return Err(MembersError::NoRootMember);
+                }
+            }
+        }
+    };
+
     let recs = sqlx::query_as!(
         MemberRowWithParents,
         r#"
+WITH RECURSIVE tree AS (
+    SELECT id FROM members WHERE id = $1 AND deleted_at IS NULL
+    UNION
+    SELECT m.id FROM members m
+    INNER JOIN tree t ON m.father_id = t.id OR m.mother_id = t.id
+    WHERE m.deleted_at IS NULL
+)
+SELECT
+    m.id as "id!",
+    m.name as "name!",
+    m.gender as "gender!: Gender",
+    m.birthday as "birthday?: PartialDate",
+    m.death_date as "death_date?: PartialDate",
+    m.deceased as "deceased!",
+    m.burial_place as "burial_place?",
+    m.last_name as "last_name!",
+    m.image_hash as "image_hash?",
+    m.personal_info as "personal_info?",
+    mother.id AS "mother_id?",
+    mother.name AS "mother_name?",
+    mother.gender AS "mother_gender?: Gender",
+    mother.birthday AS "mother_birthday?: PartialDate",
+    mother.last_name AS "mother_last_name?",
+    father.id AS "father_id?",
+    father.name AS "father_name?",
+    father.gender AS "father_gender?: Gender",
+    father.birthday AS "father_birthday?: PartialDate",
+    father.last_name AS "father_last_name?",
+    nasab.nasab as "nasab!"
+FROM
+    members m
+INNER JOIN
+    member_nasabs($2, ARRAY(SELECT id FROM tree)) nasab ON nasab.id = m.id
+LEFT JOIN
+    members mother ON m.mother_id = mother.id
+LEFT JOIN
+    members father ON m.father_id = father.id
+WHERE
+    m.id IN (SELECT id FROM tree)
+ORDER BY
+    m.id
+    "#,
+        root_id,
+        state.nasab.depth as i32,
+    )
+    .fetch_all(&state.db_pool)
+    .await?;
+
+    let marriages = get_all_marriages(&state.db_pool).await?;
+
+    let root = build_tree(root_id, &recs, &marriages).ok_or(MembersError::NoRootMember)?;
+
+    Ok(Json(Some(root)))
+}
+
+/// Get the members that have no parents, each one of them is the root of a family
+#[axum::debug_handler]
+pub async fn get_root_members(
+    State(state): State<Arc<InnerAppState>>,
+) -> anyhow::Result<Json<Vec<RootMemberResponse>>, MembersError> {
+    let roots = sqlx::query_as!(
+        RootMemberResponse,
+        r#"
+WITH RECURSIVE descendants AS (
+    SELECT id AS root_id, id FROM members
+    WHERE father_id IS NULL AND mother_id IS NULL AND deleted_at IS NULL
+    UNION
+    SELECT descendants.root_id, m.id FROM members m
+    INNER JOIN descendants
+        ON m.father_id = descendants.id OR m.mother_id = descendants.id
+    WHERE m.deleted_at IS NULL
+)
+SELECT
+    m.id,
+    m.name,
+    m.last_name,
+    m.gender as "gender: Gender",
+    (SELECT COUNT(*) - 1 FROM descendants WHERE descendants.root_id = m.id) as "descendants_count!"
+FROM
+    members m
+WHERE
+    m.father_id IS NULL AND m.mother_id IS NULL AND m.deleted_at IS NULL
+ORDER BY
+    m.id
+        "#,
+    )
+    .fetch_all(&state.db_pool)
+    .await?;
+
+    Ok(Json(roots))
+}
+
+/// The members with the given ids and their parents, members in the trash are left out
+async fn member_rows(
+    state: &InnerAppState,
+    ids: &[i64],
+) -> Result<Vec<MemberRowWithParents>, MembersError> {
+    sqlx::query_as!(
+        MemberRowWithParents,
+        r#"
 SELECT
     m.id,
     m.name,
     m.gender as "gender: Gender",
-    m.birthday,
+    m.birthday as "birthday: PartialDate",
+    m.death_date as "death_date: PartialDate",
+    m.deceased,
+    m.burial_place,
     m.last_name,
-    m.image,
-    m.image_type,
+    m.image_hash,
     m.personal_info,
-    mother.id AS mother_id,
-    mother.name AS mother_name,
-    mother.gender AS "mother_gender: Gender",
-    mother.birthday AS mother_birthday,
-    mother.last_name AS mother_last_name,
-    father.id AS father_id,
-    father.name AS father_name,
-    father.gender AS "father_gender: Gender",
-    father.birthday AS father_birthday,
-    father.last_name AS father_last_name
+    mother.id AS "mother_id?",
+    mother.name AS "mother_name?",
+    mother.gender AS "mother_gender?: Gender",
+    mother.birthday AS "mother_birthday?: PartialDate",
+    mother.last_name AS "mother_last_name?",
+    father.id AS "father_id?",
+    father.name AS "father_name?",
+    father.gender AS "father_gender?: Gender",
+    father.birthday AS "father_birthday?: PartialDate",
+    father.last_name AS "father_last_name?",
+    nasab.nasab as "nasab!"
 FROM
     members m
+INNER JOIN
+    member_nasabs($2, $1) nasab ON nasab.id = m.id
 LEFT JOIN
     members mother ON m.mother_id = mother.id
 LEFT JOIN
-    members father ON m.father_id = father.id;
-    "#,
+    members father ON m.father_id = father.id
+WHERE
+    m.id = ANY($1) AND m.deleted_at IS NULL
+ORDER BY
+    m.id
+        "#,
+        ids,
+        state.nasab.depth as i32,
+    )
+    .fetch_all(&state.db_pool)
+    .await
+    .map_err(Into::into)
+}
+
+/// Get a family member with their spouses and children,
+/// the children are brief, their own children are left out
+pub async fn get_member(
+    State(state): State<Arc<InnerAppState>>,
+    Path(id): Path<i64>,
+) -> anyhow::Result<Json<MemberResponse>, MembersError> {
+    descendants_tree(&state, id, Some(1)).await.map(Json)
+}
+
+#[serde_as]
+#[derive(Clone, Deserialize)]
+pub struct GenerationsParams {
+    /// how many generations to go up or down, all of them when not set
+    #[serde_as(as = "Option<DisplayFromStr>")]
+    pub generations: Option<u32>,
+}
+
+/// Get the ancestors of a family member, parents first
+pub async fn get_member_ancestors(
+    State(state): State<Arc<InnerAppState>>,
+    Path(id): Path<i64>,
+    Query(params): Query<GenerationsParams>,
+) -> anyhow::Result<Json<Vec<AncestorResponse>>, MembersError> {
+    // an ancestor reached through more than one line is only kept once per generation,
+    // and no line is longer than there are members, in case one is their own ancestor
+    let ancestors = sqlx::query!(
+        r#"
+WITH RECURSIVE ancestors AS (
+    SELECT id, father_id, mother_id, 0 AS generation
+    FROM members WHERE id = $1 AND deleted_at IS NULL
+    UNION
+    SELECT m.id, m.father_id, m.mother_id, a.generation + 1
+    FROM members m
+    INNER JOIN ancestors a ON m.id = a.father_id OR m.id = a.mother_id
+    WHERE m.deleted_at IS NULL
+        AND a.generation < COALESCE($2::INT4, (SELECT COUNT(*) FROM members)::INT4)
+)
+SELECT id as "id!", MIN(generation) as "generation!" FROM ancestors GROUP BY id
+        "#,
+        id,
+        params.generations.map(|g| g as i32),
     )
     .fetch_all(&state.db_pool)
     .await?;
 
-    if recs.is_empty() {
-        return Ok(Json(None));
+    if ancestors.is_empty() {
+        return Err(MembersError::MemberNotFound);
     }
 
-    let Some(root) = recs
+    let generations: HashMap<i64, i32> = ancestors.iter().map(|a| (a.id, a.generation)).collect();
+    let ids: Vec<i64> = ancestors
         .iter()
-        .find(|rec| rec.father_id.is_none() && rec.mother_id.is_none())
-    else {
-        return Err(MembersError::NoRootMember);
-    };
+        .map(|a| a.id)
+        .filter(|a| *a != id)
+        .collect();
 
-    let mut root = MemberResponse {
-        id: root.id,
-        name: root.name.clone(),
-        gender: root.gender,
-        birthday: root.birthday,
-        last_name: root.last_name.clone(),
-        father_id: None,
-        mother_id: None,
-        personal_info: root.personal_info.as_ref().and_then(|p| {
-            p.as_object().map(|o| {
-                o.into_iter()
-                    .map(|(k, v)| (k.to_string(), v.as_str().unwrap_or("").to_string()))
-                    .rev()
-                    .collect::<IndexMap<String, String>>()
-            })
-        }),
-        children: Vec::new(),
-        image: root.image.clone(),
-        image_type: root.image_type.clone(),
-    };
+    let mut ancestors: Vec<AncestorResponse> = member_rows(&state, &ids)
+        .await?
+        .into_iter()
+        .map(|m| AncestorResponse {
+            generation: generations[&m.id],
+            member: m.into(),
+        })
+        .collect();
 
-    root.add_all_children(&recs);
+    ancestors.sort_by_key(|a| (a.generation, a.member.id));
 
-    Ok(Json(Some(root)))
+    Ok(Json(ancestors))
+}
+
+/// Get the tree under a family member
+pub async fn get_member_descendants(
+    State(state): State<Arc<InnerAppState>>,
+    Path(id): Path<i64>,
+    Query(params): Query<GenerationsParams>,
+) -> anyhow::Result<Json<MemberResponse>, MembersError> {
+    descendants_tree(&state, id, params.generations)
+        .await
+        .map(Json)
+}
+
+/// The tree under a member down to the given number of generations,
+/// see [`build_tree`]
+async fn descendants_tree(
+    state: &InnerAppState,
+    id: i64,
+    generations: Option<u32>,
+) -> Result<MemberResponse, MembersError> {
+    // like the ancestors, a descendant is kept once per generation
+    let ids = sqlx::query_scalar!(
+        r#"
+WITH RECURSIVE descendants AS (
+    SELECT id, 0 AS generation
+    FROM members WHERE id = $1 AND deleted_at IS NULL
+    UNION
+    SELECT m.id, d.generation + 1
+    FROM members m
+    INNER JOIN descendants d ON m.father_id = d.id OR m.mother_id = d.id
+    WHERE m.deleted_at IS NULL
+        AND d.generation < COALESCE($2::INT4, (SELECT COUNT(*) FROM members)::INT4)
+)
+SELECT DISTINCT id as "id!" FROM descendants
+        "#,
+        id,
+        generations.map(|g| g as i32),
+    )
+    .fetch_all(&state.db_pool)
+    .await?;
+
+    let recs = member_rows(state, &ids).await?;
+    let marriages = marriages_of(&state.db_pool, &ids).await?;
+
+    build_tree(id, &recs, &marriages).ok_or(MembersError::MemberNotFound)
+}
+
+/// Get the members that share a parent with a family member
+pub async fn get_member_siblings(
+    State(state): State<Arc<InnerAppState>>,
+    Path(id): Path<i64>,
+) -> anyhow::Result<Json<Vec<SiblingResponse>>, MembersError> {
+    let member = sqlx::query!(
+        "SELECT father_id, mother_id FROM members WHERE id = $1 AND deleted_at IS NULL",
+        id,
+    )
+    .fetch_optional(&state.db_pool)
+    .await?
+    .ok_or(MembersError::MemberNotFound)?;
+
+    let ids = sqlx::query_scalar!(
+        r#"
+SELECT id FROM members
+WHERE id <> $1 AND deleted_at IS NULL AND (father_id = $2 OR mother_id = $3)
+        "#,
+        id,
+        member.father_id,
+        member.mother_id,
+    )
+    .fetch_all(&state.db_pool)
+    .await?;
+
+    let siblings = member_rows(&state, &ids)
+        .await?
+        .into_iter()
+        .map(|s| {
+            let parents = [
+                (member.father_id, s.father_id),
+                (member.mother_id, s.mother_id),
+            ];
+
+            SiblingResponse {
+                half: parents.iter().all(|(a, b)| a.is_some() && b.is_some())
+                    && parents.iter().any(|(a, b)| a != b),
+                member: s.into(),
+            }
+        })
+        .collect();
+
+    Ok(Json(siblings))
 }
 
 #[serde_as]
@@ -118,16 +427,63 @@ pub async fn get_members_flat(
     let per_page = params.per_page.unwrap_or(10);
 
     let recs: Vec<MemberRowWithParents> = if let Some(search_term) = params.query {
+        let search_key = search::search_key(&search_term);
+        // a member can also be found by their id
+        let search_id = search_term.trim().parse::<i64>().ok();
+
         sqlx::query_as(
             r#"
+        WITH matches AS (
+            SELECT
+                m.id,
+                CASE
+                    WHEN m.id = $6 THEN 0
+                    WHEN m.search_name LIKE normalize_arabic($1) || '%'
+                        OR normalize_arabic(m.last_name) LIKE normalize_arabic($1) || '%' THEN 1
+                    WHEN m.search_name LIKE '%' || normalize_arabic($1) || '%' THEN 2
+                    ELSE 3
+                END AS rank,
+                GREATEST(
+                    word_similarity(normalize_arabic($1), m.search_name),
+                    strict_word_similarity($5, coalesce(m.search_key, ''))
+                ) AS similarity
+            FROM
+                members m
+            WHERE
+            m.deleted_at IS NULL AND
+            -- every condition is on an indexed column as it is,
+            -- so the matches are found by the indexes instead of going over every member
+            (
+                m.id = $6
+                OR m.search_name LIKE '%' || normalize_arabic($1) || '%'
+                -- names that were typed a little differently, close enough by their trigrams
+                OR normalize_arabic($1) <% m.search_name
+                -- the name in the other script or spelled another way, see `search_key`
+                OR (
+                    $5 <> ''
+                    AND m.search_key LIKE '%' || $5 || '%'
+                    AND ' ' || m.search_key LIKE '% ' || $5 || '%'
+                )
+                OR (
+                    $5 <> ''
+                    AND $5 <<% m.search_key
+                    AND strict_word_similarity($5, m.search_key) > 0.6
+                )
+            )
+            ORDER BY rank, similarity DESC, m.name ASC
+            OFFSET $2
+            LIMIT $3
+        )
         SELECT
             m.id,
             m.name,
             m.gender,
             m.birthday,
+            m.death_date,
+            m.deceased,
+            m.burial_place,
             m.last_name,
-            m.image,
-            m.image_type,
+            m.image_hash,
             m.personal_info,
             mother.id as mother_id,
             mother.name AS mother_name,
@@ -138,75 +494,51 @@ pub async fn get_members_flat(
             father.name AS father_name,
             father.gender AS father_gender,
             father.birthday AS father_birthday,
-            father.last_name AS father_last_name
+            father.last_name AS father_last_name,
+            nasab.nasab
         FROM
-            members m
+            matches
+        INNER JOIN
+            members m ON m.id = matches.id
+        -- only the nasabs of the page are made
+        INNER JOIN
+            member_nasabs($4, ARRAY(SELECT id FROM matches)) nasab ON nasab.id = m.id
         LEFT JOIN
             members mother ON m.mother_id = mother.id
         LEFT JOIN
             members father ON m.father_id = father.id
-        WHERE
-        (
-            to_tsvector('simple', 
-                coalesce(m.name, '') || ' ' || 
-                coalesce(m.last_name, '') || ' ' || 
-                coalesce(cast(m.id as text), '') || ' ' ||
-                coalesce(m.personal_info::text, '') || ' ' ||
-                coalesce(mother.name, '') || ' ' ||
-                coalesce(mother.last_name, '') || ' ' ||
-                coalesce(father.name, '') || ' ' ||
-                coalesce(father.last_name, '')
-            ) @@ plainto_tsquery('simple', $1)
-            OR
-            (
-                m.name ILIKE '%' || $1 || '%' OR
-                m.last_name ILIKE '%' || $1 || '%' OR
-                cast(m.id as text) LIKE '%' || $1 || '%' OR
-                m.personal_info::text ILIKE '%' || $1 || '%' OR
-                coalesce(mother.name, '') ILIKE '%' || $1 || '%' OR
-                coalesce(mother.last_name, '') ILIKE '%' || $1 || '%' OR
-                coalesce(father.name, '') ILIKE '%' || $1 || '%' OR
-                coalesce(father.last_name, '') ILIKE '%' || $1 || '%'
-            )
-        )
         ORDER BY
-        (
-            CASE 
-                WHEN to_tsvector('simple', 
-                    coalesce(m.name, '') || ' ' || 
-                    coalesce(m.last_name, '') || ' ' || 
-                    coalesce(cast(m.id as text), '') || ' ' ||
-                    coalesce(m.personal_info::text, '') || ' ' ||
-                    coalesce(mother.name, '') || ' ' ||
-                    coalesce(mother.last_name, '') || ' ' ||
-                    coalesce(father.name, '') || ' ' ||
-                    coalesce(father.last_name, '')
-                ) @@ plainto_tsquery('simple', $1) THEN 1
-                WHEN m.name ILIKE $1 || '%' OR m.last_name ILIKE $1 || '%' THEN 2
-                ELSE 3
-            END
-        ),
-        m.name ASC
-        OFFSET $2
-        LIMIT $3;
+            matches.rank, matches.similarity DESC, m.name ASC;
             "#,
         )
         .bind(search_term)
         .bind((params.page.unwrap_or(0) * per_page).saturating_sub(1) as i32)
         .bind(per_page as i32)
+        .bind(state.nasab.depth as i32)
+        .bind(search_key)
+        .bind(search_id)
         .fetch_all(&state.db_pool)
         .await?
     } else {
         sqlx::query_as(
             r#"
+        WITH page AS (
+            SELECT id FROM members
+            WHERE deleted_at IS NULL
+            ORDER BY id, name ASC
+            OFFSET $1
+            LIMIT $2
+        )
         SELECT
             m.id,
             m.name,
             m.gender,
             m.birthday,
+            m.death_date,
+            m.deceased,
+            m.burial_place,
             m.last_name,
-            m.image,
-            m.image_type,
+            m.image_hash,
             m.personal_info,
             mother.id as mother_id,
             mother.name AS mother_name,
@@ -217,234 +549,355 @@ pub async fn get_members_flat(
             father.name AS father_name,
             father.gender AS father_gender,
             father.birthday AS father_birthday,
-            father.last_name AS father_last_name
+            father.last_name AS father_last_name,
+            nasab.nasab
         FROM
             members m
+        INNER JOIN
+            member_nasabs($3, ARRAY(SELECT id FROM page)) nasab ON nasab.id = m.id
         LEFT JOIN
             members mother ON m.mother_id = mother.id
         LEFT JOIN
             members father ON m.father_id = father.id
+        WHERE
+            m.id IN (SELECT id FROM page)
         ORDER BY
-            m.id, m.name ASC
-        OFFSET $1
-        LIMIT $2;
+            m.id, m.name ASC;
             "#,
         )
         .bind((params.page.unwrap_or(0) * per_page).saturating_sub(1) as i32)
         .bind(per_page as i32)
+        .bind(state.nasab.depth as i32)
         .fetch_all(&state.db_pool)
         .await?
     };
 
     // Convert to response format
-    let members: Vec<MemberResponseBrief> = recs
-        .into_iter()
-        .map(|m| MemberResponseBrief {
-            id: m.id,
-            name: m.name,
-            gender: m.gender,
-            birthday: m.birthday,
-            last_name: m.last_name,
-            father_id: m.father_id,
-            mother_id: m.mother_id,
-            personal_info: m.personal_info.as_ref().and_then(|p| {
-                p.as_object().map(|o| {
-                    o.into_iter()
-                        .map(|(k, v)| (k.to_string(), v.as_str().unwrap_or("").to_string()))
-                        .collect::<IndexMap<String, String>>()
-                })
-            }),
-            image: m.image,
-            image_type: m.image_type,
-        })
-        .collect();
+    let members: Vec<MemberResponseBrief> = recs.into_iter().map(Into::into).collect();
 
     Ok(Json(members))
 }
 
-/// Add a family member
-pub async fn add_member(
-    _auth: AuthExtractor<{ UserRole::Admin as u8 }>,
-    State(state): State<Arc<InnerAppState>>,
-    mut multipart: Multipart,
-) -> anyhow::Result<(), MembersError> {
-    let mut limit = FIELDS_LIMIT;
-    let mut create_member_builder = CreateMemberBuilder::new();
-
-    while let Some(field) = multipart
-        .next_field()
-        .await
-        .map_err(|_e| MembersError::SomethingWentWrong)?
-    {
-        match field.name() {
-            Some("name") => {
-                let Ok(name) = field.text().await else {
-                    return Err(MembersError::InvalidValue(String::from("name")));
-                };
+#[derive(Debug, Deserialize)]
+pub struct ImageParams {
+    #[serde(default)]
+    pub size: ImageSize,
+    /// the hash of the image the url was made for, see [`ImageUrls`]
+    pub v: Option<String>,
+}
 
-                if name.is_empty() {
-                    return Err(MembersError::InvalidValue(String::from("name")));
-                }
+/// The caching headers of a file that's identified by the hash of its content
+struct FileVersion {
+    etag: String,
+    cache_control: &'static str,
+}
 
-                create_member_builder.name(name);
-            }
-            Some("last_name") => {
-                let Ok(last_name) = field.text().await else {
-                    return Err(MembersError::InvalidValue(String::from("last_name")));
-                };
+impl FileVersion {
+    /// urls that have the current hash can be cached forever since new content gets a new url
+    fn new(hash: &str, size: ImageSize, params: &ImageParams) -> Self {
+        let cache_control = if params.v.as_deref() == Some(hash) {
+            "public, max-age=31536000, immutable"
+        } else {
+            "no-cache"
+        };
 
-                if last_name.is_empty() {
-                    return Err(MembersError::InvalidValue(String::from("last_name")));
-                }
+        FileVersion {
+            etag: format!("\"{hash}-{size}\""),
+            cache_control,
+        }
+    }
 
-                create_member_builder.last_name(last_name);
-            }
-            Some("gender") => {
-                let Ok(gender) = field.text().await else {
-                    return Err(MembersError::InvalidValue(String::from("gender")));
-                };
-                if gender.to_lowercase() == "male" {
-                    create_member_builder.gender(Gender::Male);
-                } else if gender.to_lowercase() == "female" {
-                    create_member_builder.gender(Gender::Female);
-                } else {
-                    return Err(MembersError::InvalidValue(String::from("gender")));
-                }
-            }
-            Some("birthday") => {
-                let Ok(birthday) = field.text().await else {
-                    return Err(MembersError::InvalidValue(String::from("birthday")));
-                };
-                let birthday = NaiveDate::parse_from_str(&birthday, "%Y-%m-%d")
-                    .map_err(|e| {
-                        log::error!("birthday error: {e}");
-                        MembersError::InvalidValue(String::from("birthday"))
-                    })?
-                    .and_time(
-                        NaiveTime::from_hms_opt(0, 0, 1).expect("00:00:01 should be a valid time"),
-                    )
-                    .and_utc();
-                create_member_builder.birthday(birthday);
-            }
-            Some("father_id") => {
-                let Ok(father_id) = field.text().await else {
-                    return Err(MembersError::InvalidValue(String::from("father_id")));
-                };
+    /// the response to send if the client already has this version
+    fn not_modified(&self, headers: &HeaderMap) -> Option<Response> {
+        let not_modified = headers
+            .get(header::IF_NONE_MATCH)
+            .and_then(|v| v.to_str().ok())
+            .is_some_and(|v| {
+                v.split(',')
+                    .any(|tag| tag.trim() == self.etag || tag.trim() == "*")
+            });
 
-                if father_id.is_empty() {
-                    continue;
-                }
+        not_modified.then(|| {
+            (
+                StatusCode::NOT_MODIFIED,
+                [
+                    (header::ETAG, self.etag.clone()),
+                    (header::CACHE_CONTROL, self.cache_control.to_string()),
+                ],
+            )
+                .into_response()
+        })
+    }
 
-                create_member_builder.father_id(
-                    father_id
-                        .parse()
-                        .map_err(|_e| MembersError::InvalidValue(String::from("father_id")))?,
-                );
-            }
-            Some("mother_id") => {
-                let Ok(mother_id) = field.text().await else {
-                    return Err(MembersError::InvalidValue(String::from("mother_id")));
-                };
+    fn response(self, content_type: String, content: Vec<u8>) -> Response {
+        (
+            [
+                (header::CONTENT_TYPE, content_type),
+                (header::ETAG, self.etag),
+                (header::CACHE_CONTROL, self.cache_control.to_string()),
+            ],
+            content,
+        )
+            .into_response()
+    }
+}
 
-                if mother_id.is_empty() {
-                    continue;
-                }
+/// Get the image of a family member, see [`FileVersion`] for how it's cached
+///
+/// smaller sizes are made from the image the first time they are requested and kept
+/// until the image changes
+pub async fn get_member_image(
+    State(state): State<Arc<InnerAppState>>,
+    Path(id): Path<i64>,
+    Query(params): Query<ImageParams>,
+    headers: HeaderMap,
+) -> anyhow::Result<Response, MembersError> {
+    let rec = sqlx::query!(
+        r#"
+SELECT image_hash as "image_hash!", image_type as "image_type!"
+FROM members
+WHERE id = $1 AND deleted_at IS NULL AND image_hash IS NOT NULL AND image_type IS NOT NULL
+        "#,
+        id,
+    )
+    .fetch_optional(&state.db_pool)
+    .await?
+    .ok_or(MembersError::ImageNotFound)?;
 
-                create_member_builder.mother_id(
-                    mother_id
-                        .parse()
-                        .map_err(|_e| MembersError::InvalidValue(String::from("mother_id")))?,
-                );
-            }
-            Some("image") => {
-                if let Some(image_content_type) = field.content_type() {
-                    let image_content_type = image_content_type.to_string();
-                    match image_content_type.as_str() {
-                        "image/png" | "image/jpg" | "image/jpeg" => {
-                            let Ok(image) = field.bytes().await else {
-                                return Err(MembersError::InvalidValue(String::from("image")));
-                            };
+    let version = FileVersion::new(&rec.image_hash, params.size, &params);
 
-                            if image.is_empty() {
-                                continue;
-                            }
+    if let Some(response) = version.not_modified(&headers) {
+        return Ok(response);
+    }
 
-                            create_member_builder.image(image.to_vec());
-                            create_member_builder.image_type(image_content_type.to_string());
-                        }
-                        mime_type => {
-                            log::debug!("{mime_type}");
-                            return Err(MembersError::InvalidImage);
-                        }
-                    }
-                } else {
-                    continue;
-                }
-            }
-            Some("info") => {
-                let Ok(info) = field.text().await else {
-                    return Err(MembersError::InvalidValue(String::from("info")));
-                };
+    let (image, image_type) = match params.size {
+        ImageSize::Original => (state.storage.get(&rec.image_hash).await?, rec.image_type),
+        size => {
+            get_member_image_size(
+                &state.db_pool,
+                state.storage.as_ref(),
+                id,
+                size,
+                &rec.image_hash,
+            )
+            .await?
+        }
+    };
 
-                if info.is_empty() {
-                    continue;
-                }
+    Ok(version.response(image_type, image))
+}
 
-                create_member_builder.info(
-                    serde_json::from_str(&info)
-                        .map_err(|_e| MembersError::InvalidValue(String::from("info")))?,
-                );
-            }
-            Some(field) => return Err(MembersError::InvalidField(field.to_string())),
-            None => {
-                return Err(MembersError::BadRequest);
-            }
-        }
-        if limit > 0 {
-            limit -= 1;
-        } else {
-            break;
+/// Get a smaller copy of a member image and its content type, making it if it's missing
+/// or was made from an older image
+async fn get_member_image_size(
+    db_pool: &PgPool,
+    storage: &dyn Storage,
+    id: i64,
+    size: ImageSize,
+    image_hash: &str,
+) -> Result<(Vec<u8>, String), MembersError> {
+    let rec = sqlx::query!(
+        r#"
+SELECT image_hash, image_type, source_hash FROM member_image_sizes
+WHERE member_id = $1 AND size = $2
+        "#,
+        id,
+        size as ImageSize,
+    )
+    .fetch_optional(db_pool)
+    .await?;
+
+    if let Some(rec) = &rec {
+        if rec.source_hash == image_hash {
+            return Ok((storage.get(&rec.image_hash).await?, rec.image_type.clone()));
         }
     }
 
-    let create_member = create_member_builder.build()?;
-    let info = create_member.info.and_then(|info| {
-        sqlx::types::JsonValue::deserialize(serde::de::value::MapDeserializer::new(
-            info.into_iter(),
-        ))
-        .ok()
-    });
+    let original = storage.get(image_hash).await?;
+    let (image, image_type) = images::resize(original, size).await?;
 
-    sqlx::query!(
+    let mut tx = db_pool.begin().await?;
+    let key = images::put(&mut tx, storage, &image).await?;
+
+    // only replaces the copy that was read above, if another request made the copy
+    // in the meantime nothing is changed
+    let saved = sqlx::query_scalar!(
         r#"
-    INSERT INTO members (name, gender, birthday, last_name, father_id, mother_id, image, image_type, personal_info)
-    VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)
+INSERT INTO member_image_sizes (member_id, size, image_hash, image_type, source_hash)
+VALUES ($1, $2, $3, $4, $5)
+ON CONFLICT (member_id, size) DO UPDATE
+SET image_hash = EXCLUDED.image_hash, image_type = EXCLUDED.image_type, source_hash = EXCLUDED.source_hash
+WHERE member_image_sizes.image_hash = $6
+RETURNING member_id
+        "#,
+        id,
+        size as ImageSize,
+        key,
+        image_type,
+        image_hash,
+        rec.as_ref().map(|rec| rec.image_hash.clone()),
+    )
+    .fetch_optional(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+
+    if saved.is_none() {
+        // the copy the other request made is used instead
+        images::release(db_pool, storage, &[key]).await?;
+
+        let rec = sqlx::query!(
+            r#"
+SELECT image_hash, image_type FROM member_image_sizes
+WHERE member_id = $1 AND size = $2
             "#,
-        create_member.name,
-        create_member.gender as _,
-        create_member.birthday,
-        create_member.last_name,
-        create_member.father_id,
-        create_member.mother_id,
-        create_member.image,
-        create_member.image_type,
-        info,
+            id,
+            size as ImageSize,
+        )
+        .fetch_one(db_pool)
+        .await?;
+
+        return Ok((storage.get(&rec.image_hash).await?, rec.image_type));
+    }
+
+    // the copy of the older image
+    if let Some(rec) = rec {
+        images::release(db_pool, storage, &[rec.image_hash]).await?;
+    }
+
+    Ok((image, image_type.to_string()))
+}
+
+/// the content types documents can be uploaded as besides images
+const DOCUMENT_TYPES: [&str; 1] = ["application/pdf"];
+
+/// Get the media of the given member, or only the media with `media_id`
+async fn get_media_rows(
+    db_pool: &PgPool,
+    member_id: i64,
+    media_id: Option<i64>,
+) -> Result<Vec<MediaRow>, sqlx::Error> {
+    sqlx::query_as!(
+        MediaRow,
+        r#"
+SELECT
+    mm.id, mm.member_id, mm.kind as "kind: MediaKind", mm.caption,
+    mm.date as "date: PartialDate", mm.position, mm.file_name, mm.content_type,
+    mm.content_hash as "content_hash!",
+    mm.thumbnail_hash IS NOT NULL as "has_thumbnail!",
+    COALESCE(m.image_hash = mm.content_hash, false) as "is_profile!",
+    mm.uploaded_at
+FROM member_media mm
+INNER JOIN members m ON m.id = mm.member_id
+WHERE mm.member_id = $1 AND ($2::INT8 IS NULL OR mm.id = $2)
+ORDER BY mm.position, mm.id
+        "#,
+        member_id,
+        media_id,
     )
-    .execute(&state.db_pool)
+    .fetch_all(db_pool)
+    .await
+}
+
+async fn get_one_media(
+    db_pool: &PgPool,
+    member_id: i64,
+    media_id: i64,
+) -> Result<MediaResponse, MembersError> {
+    get_media_rows(db_pool, member_id, Some(media_id))
+        .await?
+        .into_iter()
+        .next()
+        .map(MediaResponse::from)
+        .ok_or(MembersError::MediaNotFound)
+}
+
+/// Get the photos and documents of a family member
+pub async fn get_member_media(
+    State(state): State<Arc<InnerAppState>>,
+    Path(id): Path<i64>,
+) -> anyhow::Result<Json<Vec<MediaResponse>>, MembersError> {
+    let exists = sqlx::query_scalar!(
+        r#"SELECT EXISTS(SELECT 1 FROM members WHERE id = $1 AND deleted_at IS NULL) as "exists!""#,
+        id,
+    )
+    .fetch_one(&state.db_pool)
     .await?;
 
-    Ok(())
+    if !exists {
+        return Err(MembersError::MemberNotFound);
+    }
+
+    let media = get_media_rows(&state.db_pool, id, None)
+        .await?
+        .into_iter()
+        .map(MediaResponse::from)
+        .collect();
+
+    Ok(Json(media))
 }
 
-/// Edit a family member
-pub async fn edit_member(
-    _auth: AuthExtractor<{ UserRole::Admin as u8 }>,
+/// Get the file of a photo or document, see [`FileVersion`] for how it's cached
+///
+/// only the thumbnail and original sizes are kept for media
+pub async fn get_member_media_file(
+    State(state): State<Arc<InnerAppState>>,
+    Path((id, media_id)): Path<(i64, i64)>,
+    Query(params): Query<ImageParams>,
+    headers: HeaderMap,
+) -> anyhow::Result<Response, MembersError> {
+    let size = match params.size {
+        ImageSize::Thumbnail => ImageSize::Thumbnail,
+        ImageSize::Medium | ImageSize::Original => ImageSize::Original,
+    };
+
+    let rec = sqlx::query!(
+        r#"
+SELECT mm.content_hash, mm.content_type, mm.thumbnail_hash, mm.thumbnail_type
+FROM member_media mm
+INNER JOIN members m ON m.id = mm.member_id
+WHERE mm.id = $2 AND mm.member_id = $1 AND m.deleted_at IS NULL
+        "#,
+        id,
+        media_id,
+    )
+    .fetch_optional(&state.db_pool)
+    .await?
+    .ok_or(MembersError::MediaNotFound)?;
+
+    let version = FileVersion::new(&rec.content_hash, size, &params);
+
+    if let Some(response) = version.not_modified(&headers) {
+        return Ok(response);
+    }
+
+    let (key, content_type) = match size {
+        ImageSize::Thumbnail => match rec.thumbnail_hash.zip(rec.thumbnail_type) {
+            Some(thumbnail) => thumbnail,
+            None => return Err(MembersError::ImageNotFound),
+        },
+        _ => (rec.content_hash, rec.content_type),
+    };
+
+    let content = state.storage.get(&key).await?;
+
+    Ok(version.response(content_type, content))
+}
+
+/// Add a photo or document to the end of a member's gallery
+///
+/// takes the `file`, its `kind` and optionally a `caption` and `date`,
+/// images are normalized like member images and get a thumbnail
+pub async fn add_member_media(
+    auth: AuthExtractor<{ UserRole::Admin as u8 }>,
     State(state): State<Arc<InnerAppState>>,
     Path(id): Path<i64>,
     mut multipart: Multipart,
-) -> anyhow::Result<(), MembersError> {
-    let mut limit = FIELDS_LIMIT;
-    let mut update_member_builder = UpdateMemberBuilder::new();
+) -> anyhow::Result<Json<MediaResponse>, MembersError> {
+    let mut kind = None;
+    let mut caption = None;
+    let mut date = None;
+    let mut file = None;
 
     while let Some(field) = multipart
         .next_field()
@@ -452,439 +905,2714 @@ pub async fn edit_member(
         .map_err(|_e| MembersError::SomethingWentWrong)?
     {
         match field.name() {
-            Some("name") => {
-                let Ok(name) = field.text().await else {
-                    return Err(MembersError::InvalidValue(String::from("name")));
-                };
-                update_member_builder.name(name);
-            }
-            Some("last_name") => {
-                let Ok(last_name) = field.text().await else {
-                    return Err(MembersError::InvalidValue(String::from("last_name")));
-                };
-                update_member_builder.last_name(last_name);
-            }
-            Some("gender") => {
-                let Ok(gender) = field.text().await else {
-                    return Err(MembersError::InvalidValue(String::from("gender")));
+            Some("kind") => {
+                let Ok(value) = field.text().await else {
+                    return Err(MembersError::InvalidValue(String::from("kind")));
                 };
-                if gender.to_lowercase() == "male" {
-                    update_member_builder.gender(Gender::Male);
-                } else if gender.to_lowercase() == "female" {
-                    update_member_builder.gender(Gender::Female);
-                } else {
-                    return Err(MembersError::InvalidValue(String::from("gender")));
-                }
-            }
-            Some("birthday") => {
-                let Ok(birthday) = field.text().await else {
-                    return Err(MembersError::InvalidValue(String::from("birthday")));
-                };
-                let birthday = NaiveDate::parse_from_str(&birthday, "%Y-%m-%d")
-                    .map_err(|e| {
-                        log::error!("birthday error: {e}");
-                        MembersError::InvalidValue(String::from("birthday"))
-                    })?
-                    .and_time(
-                        NaiveTime::from_hms_opt(0, 0, 1).expect("00:00:01 should be a valid time"),
-                    )
-                    .and_utc();
-                update_member_builder.birthday(birthday);
+
+                kind = Some(match value.as_str() {
+                    "photo" => MediaKind::Photo,
+                    "document" => MediaKind::Document,
+                    _ => return Err(MembersError::InvalidValue(String::from("kind"))),
+                });
             }
-            Some("father_id") => {
-                let Ok(father_id) = field.text().await else {
-                    return Err(MembersError::InvalidValue(String::from("father_id")));
+            Some("caption") => {
+                let Ok(value) = field.text().await else {
+                    return Err(MembersError::InvalidValue(String::from("caption")));
                 };
 
-                if father_id.is_empty() {
-                    update_member_builder.remove_father_id(true);
-                    continue;
-                }
-
-                update_member_builder.father_id(
-                    father_id
-                        .parse()
-                        .map_err(|_e| MembersError::InvalidValue(String::from("father_id")))?,
-                );
+                caption = Some(value).filter(|c| !c.trim().is_empty());
             }
-            Some("mother_id") => {
-                let Ok(mother_id) = field.text().await else {
-                    return Err(MembersError::InvalidValue(String::from("mother_id")));
+            Some("date") => {
+                let Ok(value) = field.text().await else {
+                    return Err(MembersError::InvalidValue(String::from("date")));
                 };
 
-                if mother_id.is_empty() {
-                    update_member_builder.remove_mother_id(true);
+                if value.is_empty() {
                     continue;
                 }
 
-                update_member_builder.mother_id(
-                    mother_id
-                        .parse()
-                        .map_err(|_e| MembersError::InvalidValue(String::from("mother_id")))?,
-                );
-            }
-            Some("image") => {
-                if let Some(image_content_type) = field.content_type() {
-                    let image_content_type = image_content_type.to_string();
-                    match image_content_type.as_str() {
-                        "image/png" | "image/jpg" | "image/jpeg" => {
-                            let Ok(image) = field.bytes().await else {
-                                return Err(MembersError::InvalidValue(String::from("image")));
-                            };
-
-                            // TODO: support removing member image
-                            // if image.is_empty() {
-
-                            // }
-
-                            update_member_builder.image(image.to_vec());
-                            update_member_builder.image_type(image_content_type);
-                        }
-                        _ => {
-                            return Err(MembersError::InvalidImage);
-                        }
-                    }
-                } else {
-                    continue;
-                }
+                date = Some(value.parse::<PartialDate>().map_err(|e| {
+                    log::error!("date error: {e}");
+                    MembersError::InvalidValue(String::from("date"))
+                })?);
             }
-            Some("info") => {
-                let Ok(info) = field.text().await else {
-                    return Err(MembersError::InvalidValue(String::from("info")));
+            Some("file") => {
+                let content_type = field.content_type().unwrap_or_default().to_string();
+                let file_name = field.file_name().map(str::to_string);
+                let Ok(content) = field.bytes().await else {
+                    return Err(MembersError::InvalidValue(String::from("file")));
                 };
 
-                if info.is_empty() {
-                    update_member_builder.remove_info(true);
-                    continue;
+                if content.is_empty() {
+                    return Err(MembersError::InvalidValue(String::from("file")));
                 }
 
-                update_member_builder.info(
-                    serde_json::from_str(&info)
-                        .map_err(|_e| MembersError::InvalidValue(String::from("info")))?,
-                );
+                file = Some((content.to_vec(), content_type, file_name));
             }
-            Some(field) => {
-                return Err(MembersError::InvalidField(field.to_string()));
+            Some(field_name) => {
+                return Err(MembersError::InvalidField(field_name.to_string()));
             }
             None => {
                 return Err(MembersError::BadRequest);
             }
         }
-        if limit > 0 {
-            limit -= 1;
-        } else {
-            break;
-        }
     }
 
-    let remove_father_id = update_member_builder.remove_father_id;
-    let remove_mother_id = update_member_builder.remove_mother_id;
-    let remove_info = update_member_builder.remove_info;
-    let update_member = update_member_builder.build(id)?;
+    let kind = kind.ok_or(MembersError::InvalidValue(String::from("kind")))?;
+    let (content, content_type, file_name) =
+        file.ok_or(MembersError::InvalidValue(String::from("file")))?;
 
-    let mut tx = state.db_pool.begin().await?;
+    let (content, content_type, thumbnail) =
+        if images::ACCEPTED_TYPES.contains(&content_type.as_str()) {
+            let (content, content_type) = images::normalize(content).await?;
+            let thumbnail = images::resize(content.clone(), ImageSize::Thumbnail).await?;
 
-    if let Some(name) = &update_member.name {
-        sqlx::query!(
-            r#"
-    UPDATE members
-    SET name = $2
-    WHERE id = $1
-            "#,
-            id,
-            name,
-        )
-        .execute(&mut *tx)
-        .await?;
-    }
+            (content, content_type.to_string(), Some(thumbnail))
+        } else if kind == MediaKind::Document && DOCUMENT_TYPES.contains(&content_type.as_str()) {
+            (content, content_type, None)
+        } else if kind == MediaKind::Photo {
+            return Err(MembersError::InvalidImage);
+        } else {
+            return Err(MembersError::InvalidValue(String::from("file")));
+        };
+
+    let mut tx = state.db_pool.begin().await?;
+
+    let content_hash = images::put(&mut tx, state.storage.as_ref(), &content).await?;
+    let (thumbnail_hash, thumbnail_type) = match thumbnail {
+        Some((thumbnail, thumbnail_type)) => (
+            Some(images::put(&mut tx, state.storage.as_ref(), &thumbnail).await?),
+            Some(thumbnail_type.to_string()),
+        ),
+        None => (None, None),
+    };
+
+    let before = snapshots(&mut tx, &[id]).await?;
+
+    let media_id = sqlx::query_scalar!(
+        r#"
+INSERT INTO member_media (
+    member_id, kind, caption, date, position, file_name,
+    content_hash, content_type, thumbnail_hash, thumbnail_type, uploaded_by
+)
+SELECT
+    m.id, $2, $3, $4,
+    COALESCE((SELECT max(position) + 1 FROM member_media WHERE member_id = m.id), 0),
+    $5, $6, $7, $8, $9, $10
+FROM members m
+WHERE m.id = $1 AND m.deleted_at IS NULL
+RETURNING id
+        "#,
+        id,
+        kind as MediaKind,
+        caption,
+        date as Option<PartialDate>,
+        file_name,
+        content_hash,
+        content_type,
+        thumbnail_hash,
+        thumbnail_type,
+        auth.current_user.id,
+    )
+    .fetch_optional(&mut *tx)
+    .await?
+    .ok_or(MembersError::MemberNotFound)?;
+
+    record_changes(
+        &mut tx,
+        Some(auth.current_user.id),
+        ChangeAction::Edit,
+        &[id],
+        &before,
+    )
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(Json(get_one_media(&state.db_pool, id, media_id).await?))
+}
+
+/// Edit the caption, date or position of a photo or document
+pub async fn edit_member_media(
+    auth: AuthExtractor<{ UserRole::Admin as u8 }>,
+    State(state): State<Arc<InnerAppState>>,
+    Path((id, media_id)): Path<(i64, i64)>,
+    Json(payload): Json<UpdateMedia>,
+) -> anyhow::Result<Json<MediaResponse>, MembersError> {
+    let mut tx = state.db_pool.begin().await?;
+    let before = snapshots(&mut tx, &[id]).await?;
+
+    let result = sqlx::query!(
+        r#"
+UPDATE member_media
+SET caption = $3, date = $4, position = COALESCE($5, position)
+WHERE id = $2 AND member_id = $1
+        "#,
+        id,
+        media_id,
+        payload.caption.filter(|c| !c.trim().is_empty()),
+        payload.date as Option<PartialDate>,
+        payload.position,
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    if result.rows_affected() == 0 {
+        return Err(MembersError::MediaNotFound);
+    }
+
+    record_changes(
+        &mut tx,
+        Some(auth.current_user.id),
+        ChangeAction::Edit,
+        &[id],
+        &before,
+    )
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(Json(get_one_media(&state.db_pool, id, media_id).await?))
+}
+
+/// Delete a photo or document, the member image stays even if it was picked from it
+pub async fn delete_member_media(
+    auth: AuthExtractor<{ UserRole::Admin as u8 }>,
+    State(state): State<Arc<InnerAppState>>,
+    Path((id, media_id)): Path<(i64, i64)>,
+) -> anyhow::Result<(), MembersError> {
+    let mut tx = state.db_pool.begin().await?;
+    let before = snapshots(&mut tx, &[id]).await?;
+
+    let rec = sqlx::query!(
+        r#"
+DELETE FROM member_media WHERE id = $2 AND member_id = $1
+RETURNING content_hash, thumbnail_hash
+        "#,
+        id,
+        media_id,
+    )
+    .fetch_optional(&mut *tx)
+    .await?
+    .ok_or(MembersError::MediaNotFound)?;
+
+    record_changes(
+        &mut tx,
+        Some(auth.current_user.id),
+        ChangeAction::Edit,
+        &[id],
+        &before,
+    )
+    .await?;
+
+    tx.commit().await?;
+
+    let keys: Vec<String> = [Some(rec.content_hash), rec.thumbnail_hash]
+        .into_iter()
+        .flatten()
+        .collect();
+
+    images::release(&state.db_pool, state.storage.as_ref(), &keys).await?;
+
+    Ok(())
+}
+
+/// Use a photo from the gallery as the member image
+pub async fn set_profile_media(
+    auth: AuthExtractor<{ UserRole::Admin as u8 }>,
+    State(state): State<Arc<InnerAppState>>,
+    Path((id, media_id)): Path<(i64, i64)>,
+) -> anyhow::Result<Json<MediaResponse>, MembersError> {
+    let has_thumbnail = sqlx::query_scalar!(
+        r#"SELECT thumbnail_hash IS NOT NULL as "has_thumbnail!" FROM member_media WHERE id = $2 AND member_id = $1"#,
+        id,
+        media_id,
+    )
+    .fetch_optional(&state.db_pool)
+    .await?
+    .ok_or(MembersError::MediaNotFound)?;
+
+    // only images have thumbnails
+    if !has_thumbnail {
+        return Err(MembersError::InvalidImage);
+    }
+
+    let mut tx = state.db_pool.begin().await?;
+    let before = snapshots(&mut tx, &[id]).await?;
+
+    sqlx::query!(
+        r#"
+UPDATE members
+SET image_hash = mm.content_hash, image_type = mm.content_type
+FROM member_media mm
+WHERE members.id = $1 AND mm.id = $2
+        "#,
+        id,
+        media_id,
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    record_changes(
+        &mut tx,
+        Some(auth.current_user.id),
+        ChangeAction::Edit,
+        &[id],
+        &before,
+    )
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(Json(get_one_media(&state.db_pool, id, media_id).await?))
+}
+
+/// Add a family member
+pub async fn add_member(
+    auth: AuthExtractor<{ UserRole::Admin as u8 }>,
+    State(state): State<Arc<InnerAppState>>,
+    mut multipart: Multipart,
+) -> anyhow::Result<(), MembersError> {
+    let mut limit = FIELDS_LIMIT;
+    let mut create_member_builder = CreateMemberBuilder::new();
+
+    while let Some(field) = multipart
+        .next_field()
+        .await
+        .map_err(|_e| MembersError::SomethingWentWrong)?
+    {
+        match field.name() {
+            Some("name") => {
+                let Ok(name) = field.text().await else {
+                    return Err(MembersError::InvalidValue(String::from("name")));
+                };
+
+                if name.is_empty() {
+                    return Err(MembersError::InvalidValue(String::from("name")));
+                }
+
+                create_member_builder.name(name);
+            }
+            Some("last_name") => {
+                let Ok(last_name) = field.text().await else {
+                    return Err(MembersError::InvalidValue(String::from("last_name")));
+                };
+
+                if last_name.is_empty() {
+                    return Err(MembersError::InvalidValue(String::from("last_name")));
+                }
+
+                create_member_builder.last_name(last_name);
+            }
+            Some("gender") => {
+                let Ok(gender) = field.text().await else {
+                    return Err(MembersError::InvalidValue(String::from("gender")));
+                };
+                if gender.to_lowercase() == "male" {
+                    create_member_builder.gender(Gender::Male);
+                } else if gender.to_lowercase() == "female" {
+                    create_member_builder.gender(Gender::Female);
+                } else {
+                    return Err(MembersError::InvalidValue(String::from("gender")));
+                }
+            }
+            Some("birthday") => {
+                let Ok(birthday) = field.text().await else {
+                    return Err(MembersError::InvalidValue(String::from("birthday")));
+                };
+
+                if birthday.is_empty() {
+                    continue;
+                }
+
+                let birthday = birthday.parse::<PartialDate>().map_err(|e| {
+                    log::error!("birthday error: {e}");
+                    MembersError::InvalidValue(String::from("birthday"))
+                })?;
+                create_member_builder.birthday(birthday);
+            }
+            Some("death_date") => {
+                let Ok(death_date) = field.text().await else {
+                    return Err(MembersError::InvalidValue(String::from("death_date")));
+                };
+
+                if death_date.is_empty() {
+                    continue;
+                }
+
+                let death_date = death_date.parse::<PartialDate>().map_err(|e| {
+                    log::error!("death_date error: {e}");
+                    MembersError::InvalidValue(String::from("death_date"))
+                })?;
+                create_member_builder.death_date(death_date);
+            }
+            Some("deceased") => {
+                let Ok(deceased) = field.text().await else {
+                    return Err(MembersError::InvalidValue(String::from("deceased")));
+                };
+
+                create_member_builder.deceased(
+                    deceased
+                        .parse()
+                        .map_err(|_e| MembersError::InvalidValue(String::from("deceased")))?,
+                );
+            }
+            Some("burial_place") => {
+                let Ok(burial_place) = field.text().await else {
+                    return Err(MembersError::InvalidValue(String::from("burial_place")));
+                };
+
+                if burial_place.is_empty() {
+                    continue;
+                }
+
+                create_member_builder.burial_place(burial_place);
+            }
+            Some("father_id") => {
+                let Ok(father_id) = field.text().await else {
+                    return Err(MembersError::InvalidValue(String::from("father_id")));
+                };
+
+                if father_id.is_empty() {
+                    continue;
+                }
+
+                create_member_builder.father_id(
+                    father_id
+                        .parse()
+                        .map_err(|_e| MembersError::InvalidValue(String::from("father_id")))?,
+                );
+            }
+            Some("mother_id") => {
+                let Ok(mother_id) = field.text().await else {
+                    return Err(MembersError::InvalidValue(String::from("mother_id")));
+                };
+
+                if mother_id.is_empty() {
+                    continue;
+                }
+
+                create_member_builder.mother_id(
+                    mother_id
+                        .parse()
+                        .map_err(|_e| MembersError::InvalidValue(String::from("mother_id")))?,
+                );
+            }
+            Some("image") => {
+                if let Some(image_content_type) = field.content_type() {
+                    let image_content_type = image_content_type.to_string();
+                    match image_content_type.as_str() {
+                        content_type if images::ACCEPTED_TYPES.contains(&content_type) => {
+                            let Ok(image) = field.bytes().await else {
+                                return Err(MembersError::InvalidValue(String::from("image")));
+                            };
+
+                            if image.is_empty() {
+                                continue;
+                            }
+
+                            let (image, image_type) = images::normalize(image.to_vec()).await?;
+
+                            create_member_builder.image(image);
+                            create_member_builder.image_type(image_type.to_string());
+                        }
+                        mime_type => {
+                            log::debug!("{mime_type}");
+                            return Err(MembersError::InvalidImage);
+                        }
+                    }
+                } else {
+                    continue;
+                }
+            }
+            Some("info") => {
+                let Ok(info) = field.text().await else {
+                    return Err(MembersError::InvalidValue(String::from("info")));
+                };
+
+                if info.is_empty() {
+                    continue;
+                }
+
+                create_member_builder.info(
+                    serde_json::from_str(&info)
+                        .map_err(|_e| MembersError::InvalidValue(String::from("info")))?,
+                );
+            }
+            Some(field) => return Err(MembersError::InvalidField(field.to_string())),
+            None => {
+                return Err(MembersError::BadRequest);
+            }
+        }
+        if limit > 0 {
+            limit -= 1;
+        } else {
+            break;
+        }
+    }
+
+    let create_member = create_member_builder.build()?;
+    let info = create_member.info.and_then(|info| {
+        sqlx::types::JsonValue::deserialize(serde::de::value::MapDeserializer::new(
+            info.into_iter(),
+        ))
+        .ok()
+    });
+
+    let mut tx = state.db_pool.begin().await?;
+
+    let image_hash = match &create_member.image {
+        Some(image) => Some(images::put(&mut tx, state.storage.as_ref(), image).await?),
+        None => None,
+    };
+
+    let id = sqlx::query_scalar!(
+        r#"
+    INSERT INTO members (name, gender, birthday, death_date, deceased, burial_place, last_name, father_id, mother_id, image_hash, image_type, personal_info)
+    VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12)
+    RETURNING id
+            "#,
+        create_member.name,
+        create_member.gender as _,
+        create_member.birthday as _,
+        create_member.death_date as _,
+        create_member.deceased,
+        create_member.burial_place,
+        create_member.last_name,
+        create_member.father_id,
+        create_member.mother_id,
+        image_hash,
+        create_member.image_type,
+        info,
+    )
+    .fetch_one(&mut *tx)
+    .await?;
+
+    search::update_search_keys(&mut tx, &[id]).await?;
+    check_parents(&mut tx, &[id], &[]).await?;
+
+    record_changes(
+        &mut tx,
+        Some(auth.current_user.id),
+        ChangeAction::Create,
+        &[id],
+        &HashMap::new(),
+    )
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(())
+}
+
+/// Edit a family member
+pub async fn edit_member(
+    auth: AuthExtractor<{ UserRole::Admin as u8 }>,
+    State(state): State<Arc<InnerAppState>>,
+    Path(id): Path<i64>,
+    mut multipart: Multipart,
+) -> anyhow::Result<(), MembersError> {
+    let mut limit = FIELDS_LIMIT;
+    let mut update_member_builder = UpdateMemberBuilder::new();
+
+    while let Some(field) = multipart
+        .next_field()
+        .await
+        .map_err(|_e| MembersError::SomethingWentWrong)?
+    {
+        match field.name() {
+            Some("name") => {
+                let Ok(name) = field.text().await else {
+                    return Err(MembersError::InvalidValue(String::from("name")));
+                };
+                update_member_builder.name(name);
+            }
+            Some("last_name") => {
+                let Ok(last_name) = field.text().await else {
+                    return Err(MembersError::InvalidValue(String::from("last_name")));
+                };
+                update_member_builder.last_name(last_name);
+            }
+            Some("gender") => {
+                let Ok(gender) = field.text().await else {
+                    return Err(MembersError::InvalidValue(String::from("gender")));
+                };
+                if gender.to_lowercase() == "male" {
+                    update_member_builder.gender(Gender::Male);
+                } else if gender.to_lowercase() == "female" {
+                    update_member_builder.gender(Gender::Female);
+                } else {
+                    return Err(MembersError::InvalidValue(String::from("gender")));
+                }
+            }
+            Some("birthday") => {
+                let Ok(birthday) = field.text().await else {
+                    return Err(MembersError::InvalidValue(String::from("birthday")));
+                };
+
+                if birthday.is_empty() {
+                    update_member_builder.remove_birthday(true);
+                    continue;
+                }
+
+                let birthday = birthday.parse::<PartialDate>().map_err(|e| {
+                    log::error!("birthday error: {e}");
+                    MembersError::InvalidValue(String::from("birthday"))
+                })?;
+                update_member_builder.birthday(birthday);
+            }
+            Some("death_date") => {
+                let Ok(death_date) = field.text().await else {
+                    return Err(MembersError::InvalidValue(String::from("death_date")));
+                };
+
+                if death_date.is_empty() {
+                    update_member_builder.remove_death_date(true);
+                    continue;
+                }
+
+                let death_date = death_date.parse::<PartialDate>().map_err(|e| {
+                    log::error!("death_date error: {e}");
+                    MembersError::InvalidValue(String::from("death_date"))
+                })?;
+                update_member_builder.death_date(death_date);
+            }
+            Some("deceased") => {
+                let Ok(deceased) = field.text().await else {
+                    return Err(MembersError::InvalidValue(String::from("deceased")));
+                };
+
+                update_member_builder.deceased(
+                    deceased
+                        .parse()
+                        .map_err(|_e| MembersError::InvalidValue(String::from("deceased")))?,
+                );
+            }
+            Some("burial_place") => {
+                let Ok(burial_place) = field.text().await else {
+                    return Err(MembersError::InvalidValue(String::from("burial_place")));
+                };
+
+                if burial_place.is_empty() {
+                    update_member_builder.remove_burial_place(true);
+                    continue;
+                }
+
+                update_member_builder.burial_place(burial_place);
+            }
+            Some("father_id") => {
+                let Ok(father_id) = field.text().await else {
+                    return Err(MembersError::InvalidValue(String::from("father_id")));
+                };
+
+                if father_id.is_empty() {
+                    update_member_builder.remove_father_id(true);
+                    continue;
+                }
+
+                update_member_builder.father_id(
+                    father_id
+                        .parse()
+                        .map_err(|_e| MembersError::InvalidValue(String::from("father_id")))?,
+                );
+            }
+            Some("mother_id") => {
+                let Ok(mother_id) = field.text().await else {
+                    return Err(MembersError::InvalidValue(String::from("mother_id")));
+                };
+
+                if mother_id.is_empty() {
+                    update_member_builder.remove_mother_id(true);
+                    continue;
+                }
+
+                update_member_builder.mother_id(
+                    mother_id
+                        .parse()
+                        .map_err(|_e| MembersError::InvalidValue(String::from("mother_id")))?,
+                );
+            }
+            Some("image") => {
+                if let Some(image_content_type) = field.content_type() {
+                    let image_content_type = image_content_type.to_string();
+                    match image_content_type.as_str() {
+                        content_type if images::ACCEPTED_TYPES.contains(&content_type) => {
+                            let Ok(image) = field.bytes().await else {
+                                return Err(MembersError::InvalidValue(String::from("image")));
+                            };
+
+                            // TODO: support removing member image
+                            // if image.is_empty() {
+
+                            // }
+
+                            let (image, image_type) = images::normalize(image.to_vec()).await?;
+
+                            update_member_builder.image(image);
+                            update_member_builder.image_type(image_type.to_string());
+                        }
+                        _ => {
+                            return Err(MembersError::InvalidImage);
+                        }
+                    }
+                } else {
+                    continue;
+                }
+            }
+            Some("info") => {
+                let Ok(info) = field.text().await else {
+                    return Err(MembersError::InvalidValue(String::from("info")));
+                };
+
+                if info.is_empty() {
+                    update_member_builder.remove_info(true);
+                    continue;
+                }
+
+                update_member_builder.info(
+                    serde_json::from_str(&info)
+                        .map_err(|_e| MembersError::InvalidValue(String::from("info")))?,
+                );
+            }
+            Some(field) => {
+                return Err(MembersError::InvalidField(field.to_string()));
+            }
+            None => {
+                return Err(MembersError::BadRequest);
+            }
+        }
+        if limit > 0 {
+            limit -= 1;
+        } else {
+            break;
+        }
+    }
+
+    let remove_father_id = update_member_builder.remove_father_id;
+    let remove_mother_id = update_member_builder.remove_mother_id;
+    let remove_info = update_member_builder.remove_info;
+    let remove_birthday = update_member_builder.remove_birthday;
+    let remove_death_date = update_member_builder.remove_death_date;
+    let remove_burial_place = update_member_builder.remove_burial_place;
+
+    let mut tx = state.db_pool.begin().await?;
+
+    let stored = sqlx::query!(
+        r#"
+SELECT birthday as "birthday: PartialDate", death_date as "death_date: PartialDate"
+FROM members
+WHERE id = $1
+        "#,
+        id,
+    )
+    .fetch_optional(&mut *tx)
+    .await?
+    .ok_or(MembersError::MemberNotFound)?;
+    let update_member = update_member_builder.build(id, stored.birthday, stored.death_date)?;
+
+    let before = snapshots(&mut tx, &[id]).await?;
+    let known = parent_problems(&mut tx, &[id]).await?;
+
+    if let Some(name) = &update_member.name {
+        sqlx::query!(
+            r#"
+    UPDATE members
+    SET name = $2
+    WHERE id = $1
+            "#,
+            id,
+            name,
+        )
+        .execute(&mut *tx)
+        .await?;
+    }
 
     if let Some(last_name) = &update_member.last_name {
         log::debug!("id: {}", update_member.id);
-        log::debug!("last_name: {}", last_name);
+        log::debug!("last_name: {last_name}");
+
+        sqlx::query!(
+            r#"
+UPDATE members
+SET last_name = $1::TEXT
+WHERE id = $2"#,
+            last_name,
+            id,
+        )
+        .execute(&mut *tx)
+        .await?;
+    }
+
+    if let Some(birthday) = &update_member.birthday {
+        sqlx::query!(
+            r#"
+    UPDATE members
+    SET birthday = $2
+    WHERE id = $1
+            "#,
+            id,
+            birthday as _,
+        )
+        .execute(&mut *tx)
+        .await?;
+    } else if remove_birthday {
+        sqlx::query!(
+            r#"
+    UPDATE members
+    SET birthday = NULL
+    WHERE id = $1
+            "#,
+            id,
+        )
+        .execute(&mut *tx)
+        .await?;
+    }
+
+    if let Some(death_date) = &update_member.death_date {
+        sqlx::query!(
+            r#"
+    UPDATE members
+    SET death_date = $2
+    WHERE id = $1
+            "#,
+            id,
+            death_date as _,
+        )
+        .execute(&mut *tx)
+        .await?;
+    } else if remove_death_date {
+        sqlx::query!(
+            r#"
+    UPDATE members
+    SET death_date = NULL
+    WHERE id = $1
+            "#,
+            id,
+        )
+        .execute(&mut *tx)
+        .await?;
+    }
+
+    if let Some(deceased) = &update_member.deceased {
+        sqlx::query!(
+            r#"
+    UPDATE members
+    SET deceased = $2
+    WHERE id = $1
+            "#,
+            id,
+            deceased,
+        )
+        .execute(&mut *tx)
+        .await?;
+    }
+
+    if let Some(burial_place) = &update_member.burial_place {
+        sqlx::query!(
+            r#"
+    UPDATE members
+    SET burial_place = $2
+    WHERE id = $1
+            "#,
+            id,
+            burial_place,
+        )
+        .execute(&mut *tx)
+        .await?;
+    } else if remove_burial_place {
+        sqlx::query!(
+            r#"
+    UPDATE members
+    SET burial_place = NULL
+    WHERE id = $1
+            "#,
+            id,
+        )
+        .execute(&mut *tx)
+        .await?;
+    }
+
+    if let Some(gender) = &update_member.gender {
+        sqlx::query!(
+            r#"
+UPDATE members
+SET gender = $2
+WHERE id = $1
+            "#,
+            id,
+            gender as _,
+        )
+        .execute(&mut *tx)
+        .await?;
+    }
+
+    if let Some(mother_id) = &update_member.mother_id {
+        sqlx::query!(
+            r#"
+    UPDATE members
+    SET mother_id = $2
+    WHERE id = $1
+            "#,
+            id,
+            mother_id
+        )
+        .execute(&mut *tx)
+        .await?;
+    } else if remove_mother_id {
+        sqlx::query!(
+            r#"
+    UPDATE members
+    SET mother_id = NULL
+    WHERE id = $1
+            "#,
+            id,
+        )
+        .execute(&mut *tx)
+        .await?;
+    }
+
+    if let Some(father_id) = &update_member.father_id {
+        sqlx::query!(
+            r#"
+    UPDATE members
+    SET father_id = $2
+    WHERE id = $1
+            "#,
+            id,
+            father_id
+        )
+        .execute(&mut *tx)
+        .await?;
+    } else if remove_father_id {
+        sqlx::query!(
+            r#"
+    UPDATE members
+    SET father_id = NULL
+    WHERE id = $1
+            "#,
+            id,
+        )
+        .execute(&mut *tx)
+        .await?;
+    }
+
+    if let Some(info) = &update_member.info {
+        let info = sqlx::types::JsonValue::deserialize(serde::de::value::MapDeserializer::new(
+            info.clone().into_iter(),
+        ))
+        .map_err(|_e| MembersError::SomethingWentWrong)?;
+
+        sqlx::query!(
+            r#"
+    UPDATE members
+    SET personal_info = $2
+    WHERE id = $1
+            "#,
+            id,
+            info,
+        )
+        .execute(&mut *tx)
+        .await?;
+    } else if remove_info {
+        sqlx::query!(
+            r#"
+    UPDATE members
+    SET personal_info = NULL
+    WHERE id = $1
+            "#,
+            id,
+        )
+        .execute(&mut *tx)
+        .await?;
+    }
+
+    // the image that was replaced, removed once the change is committed
+    let mut old_image_hash = None;
+
+    if let Some((image, image_type)) = &update_member
+        .image
+        .and_then(|i| update_member.image_type.map(|it| (i, it)))
+    {
+        let image_hash = images::put(&mut tx, state.storage.as_ref(), image).await?;
+
+        old_image_hash = sqlx::query_scalar!(
+            r#"
+UPDATE members m
+SET image_hash = $2, image_type = $3
+FROM members old
+WHERE m.id = $1 AND old.id = m.id
+RETURNING old.image_hash"#,
+            update_member.id,
+            image_hash,
+            image_type,
+        )
+        .fetch_one(&mut *tx)
+        .await?;
+    }
+
+    if update_member.name.is_some() || update_member.last_name.is_some() {
+        search::update_search_keys(&mut tx, &[id]).await?;
+    }
+
+    check_parents(&mut tx, &[id], &known).await?;
+
+    record_changes(
+        &mut tx,
+        Some(auth.current_user.id),
+        ChangeAction::Edit,
+        &[id],
+        &before,
+    )
+    .await?;
+
+    tx.commit().await?;
+
+    if let Some(old_image_hash) = old_image_hash {
+        images::release(&state.db_pool, state.storage.as_ref(), &[old_image_hash]).await?;
+    }
+
+    Ok(())
+}
+
+#[derive(Clone, Deserialize)]
+pub struct DeleteMemberParams {
+    #[serde(default)]
+    pub policy: DeletePolicy,
+}
+
+/// Move a family member to the trash,
+/// they keep their parents, children and marriages so they can be restored
+///
+/// what happens to their children depends on the policy, see [`DeletePolicy`]
+pub async fn delete_member(
+    auth: AuthExtractor<{ UserRole::Admin as u8 }>,
+    State(state): State<Arc<InnerAppState>>,
+    Path(id): Path<i64>,
+    Query(params): Query<DeleteMemberParams>,
+) -> anyhow::Result<Json<DeleteMemberResponse>, MembersError> {
+    let mut tx = state.db_pool.begin().await?;
+
+    let member = sqlx::query!(
+        r#"
+SELECT gender as "gender: Gender", father_id, mother_id
+FROM members
+WHERE id = $1 AND deleted_at IS NULL
+FOR UPDATE"#,
+        id,
+    )
+    .fetch_optional(&mut *tx)
+    .await?
+    .ok_or(MembersError::MemberNotFound)?;
+
+    let children = sqlx::query_as!(
+        AffectedMember,
+        r#"
+SELECT id, name, last_name
+FROM members
+WHERE (father_id = $1 OR mother_id = $1) AND deleted_at IS NULL
+ORDER BY id"#,
+        id,
+    )
+    .fetch_all(&mut *tx)
+    .await?;
+
+    let ids: Vec<i64> = std::iter::once(id)
+        .chain(children.iter().map(|c| c.id))
+        .collect();
+    let mut before = snapshots(&mut tx, &ids).await?;
+
+    let mut reparented = Vec::new();
+
+    let mut deleted = match params.policy {
+        DeletePolicy::Refuse if !children.is_empty() => {
+            return Err(MembersError::HasChildren(children));
+        }
+        DeletePolicy::Refuse => Vec::new(),
+        DeletePolicy::Reparent => {
+            // the children take the parent on the same side as the deleted member
+            let new_parent = match member.gender {
+                Gender::Male => member.father_id,
+                Gender::Female => member.mother_id,
+            };
+
+            if new_parent.is_none() && !children.is_empty() {
+                return Err(MembersError::NoParentToReparent(children));
+            }
+
+            reparented = sqlx::query_as!(
+                AffectedMember,
+                r#"
+UPDATE members
+SET
+    father_id = CASE WHEN father_id = $1 THEN $2 ELSE father_id END,
+    mother_id = CASE WHEN mother_id = $1 THEN $2 ELSE mother_id END
+WHERE (father_id = $1 OR mother_id = $1) AND deleted_at IS NULL
+RETURNING id, name, last_name"#,
+                id,
+                new_parent,
+            )
+            .fetch_all(&mut *tx)
+            .await?;
+
+            Vec::new()
+        }
+        DeletePolicy::Cascade => {
+            let descendants = sqlx::query_as!(
+                AffectedMember,
+                r#"
+WITH RECURSIVE descendants AS (
+    SELECT m.id FROM members m
+    WHERE (m.father_id = $1 OR m.mother_id = $1) AND m.deleted_at IS NULL
+    UNION
+    SELECT m.id FROM members m
+    INNER JOIN descendants d ON m.father_id = d.id OR m.mother_id = d.id
+    WHERE m.deleted_at IS NULL
+)
+SELECT id as "id!", name, last_name
+FROM members
+WHERE id IN (SELECT id FROM descendants) AND id != $1"#,
+                id,
+            )
+            .fetch_all(&mut *tx)
+            .await?;
+
+            let ids: Vec<i64> = descendants.iter().map(|d| d.id).collect();
+            before.extend(snapshots(&mut tx, &ids).await?);
+
+            sqlx::query!(
+                r#"UPDATE members SET deleted_at = now() WHERE id = ANY($1)"#,
+                &ids,
+            )
+            .execute(&mut *tx)
+            .await?;
+
+            descendants
+        }
+    };
+
+    let member = sqlx::query_as!(
+        AffectedMember,
+        r#"
+UPDATE members SET deleted_at = now() WHERE id = $1
+RETURNING id, name, last_name"#,
+        id,
+    )
+    .fetch_one(&mut *tx)
+    .await?;
+
+    reparented.sort_by_key(|m| m.id);
+    deleted.sort_by_key(|m| m.id);
+    deleted.insert(0, member);
+
+    let deleted_ids: Vec<i64> = deleted.iter().map(|m| m.id).collect();
+    let reparented_ids: Vec<i64> = reparented.iter().map(|m| m.id).collect();
+    record_changes(
+        &mut tx,
+        Some(auth.current_user.id),
+        ChangeAction::Delete,
+        &deleted_ids,
+        &before,
+    )
+    .await?;
+    record_changes(
+        &mut tx,
+        Some(auth.current_user.id),
+        ChangeAction::Edit,
+        &reparented_ids,
+        &before,
+    )
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(Json(DeleteMemberResponse {
+        policy: params.policy,
+        deleted,
+        reparented,
+    }))
+}
+
+pub async fn get_trash_members(
+    db_pool: &PgPool,
+) -> Result<Vec<DeletedMemberResponse>, MembersError> {
+    sqlx::query_as!(
+        DeletedMemberResponse,
+        r#"
+SELECT
+    id, name, last_name, gender as "gender: Gender",
+    father_id, mother_id, deleted_at as "deleted_at!"
+FROM members
+WHERE deleted_at IS NOT NULL
+ORDER BY deleted_at DESC
+        "#,
+    )
+    .fetch_all(db_pool)
+    .await
+    .map_err(Into::into)
+}
+
+/// Get the members in the trash, most recently deleted first
+pub async fn get_trash(
+    _auth: AuthExtractor<{ UserRole::Admin as u8 }>,
+    State(state): State<Arc<InnerAppState>>,
+) -> anyhow::Result<Json<Vec<DeletedMemberResponse>>, MembersError> {
+    let trash = get_trash_members(&state.db_pool).await?;
+
+    Ok(Json(trash))
+}
+
+/// Take a member out of the trash,
+/// descendants that were deleted along with them come back too,
+/// and children that were moved to another parent are moved back
+pub async fn restore_member(
+    auth: AuthExtractor<{ UserRole::Admin as u8 }>,
+    State(state): State<Arc<InnerAppState>>,
+    Path(id): Path<i64>,
+) -> anyhow::Result<(), MembersError> {
+    let mut tx = state.db_pool.begin().await?;
+
+    let ids = sqlx::query_scalar!(
+        r#"
+WITH RECURSIVE restored AS (
+    SELECT id, deleted_at FROM members WHERE id = $1 AND deleted_at IS NOT NULL
+    UNION
+    SELECT m.id, m.deleted_at FROM members m
+    INNER JOIN restored r ON m.father_id = r.id OR m.mother_id = r.id
+    WHERE m.deleted_at = r.deleted_at
+)
+SELECT id as "id!" FROM restored"#,
+        id,
+    )
+    .fetch_all(&mut *tx)
+    .await?;
+
+    if ids.is_empty() {
+        return Err(MembersError::MemberNotFound);
+    }
+
+    // the children the delete moved to another parent, found by the edits recorded
+    // with the delete, unless their parent on that side was changed again since
+    let moved = sqlx::query!(
+        r#"
+WITH deletions AS (
+    SELECT DISTINCT ON (c.member_id)
+        c.member_id, c.changed_at, m.gender = 'male' AS is_father
+    FROM member_changes c
+    INNER JOIN members m ON m.id = c.member_id
+    WHERE c.member_id = ANY($1) AND c.action = 'delete'
+    ORDER BY c.member_id, c.changed_at DESC
+),
+moved AS (
+    SELECT
+        c.member_id AS child_id, d.member_id AS parent_id, d.is_father,
+        (c.after ->> CASE WHEN d.is_father THEN 'father_id' ELSE 'mother_id' END)::INT8
+            AS moved_to
+    FROM deletions d
+    INNER JOIN member_changes c ON c.changed_at = d.changed_at AND c.action = 'edit'
+    WHERE (c.before ->> CASE WHEN d.is_father THEN 'father_id' ELSE 'mother_id' END)::INT8
+        = d.member_id
+)
+SELECT moved.child_id as "child_id!", moved.parent_id as "parent_id!", moved.is_father as "is_father!"
+FROM moved
+INNER JOIN members child ON child.id = moved.child_id
+WHERE child.deleted_at IS NULL
+    AND CASE WHEN moved.is_father THEN child.father_id ELSE child.mother_id END
+        IS NOT DISTINCT FROM moved.moved_to
+        "#,
+        &ids,
+    )
+    .fetch_all(&mut *tx)
+    .await?;
+
+    let children: Vec<i64> = moved.iter().map(|m| m.child_id).collect();
+    let parents: Vec<i64> = moved.iter().map(|m| m.parent_id).collect();
+    let is_father: Vec<bool> = moved.iter().map(|m| m.is_father).collect();
+
+    let before = snapshots(&mut tx, &ids).await?;
+    let children_before = snapshots(&mut tx, &children).await?;
+
+    sqlx::query!(
+        r#"UPDATE members SET deleted_at = NULL WHERE id = ANY($1)"#,
+        &ids,
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    sqlx::query!(
+        r#"
+UPDATE members m
+SET
+    father_id = CASE WHEN p.is_father THEN p.parent_id ELSE m.father_id END,
+    mother_id = CASE WHEN p.is_father THEN m.mother_id ELSE p.parent_id END
+FROM unnest($1::INT8[], $2::INT8[], $3::BOOL[]) AS p(id, parent_id, is_father)
+WHERE m.id = p.id
+        "#,
+        &children,
+        &parents,
+        &is_father,
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    record_changes(
+        &mut tx,
+        Some(auth.current_user.id),
+        ChangeAction::Restore,
+        &ids,
+        &before,
+    )
+    .await?;
+    record_changes(
+        &mut tx,
+        Some(auth.current_user.id),
+        ChangeAction::Edit,
+        &children,
+        &children_before,
+    )
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(())
+}
+
+/// Permanently remove a member that's in the trash
+pub async fn purge_member(
+    auth: AuthExtractor<{ UserRole::Admin as u8 }>,
+    State(state): State<Arc<InnerAppState>>,
+    Path(id): Path<i64>,
+) -> anyhow::Result<(), MembersError> {
+    let mut tx = state.db_pool.begin().await?;
+
+    let before = snapshots(&mut tx, &[id]).await?;
+
+    // the files of the member, their image sizes and media are deleted with them
+    let keys = sqlx::query_scalar!(
+        r#"
+SELECT image_hash as "key!" FROM members WHERE id = $1 AND image_hash IS NOT NULL
+UNION SELECT image_hash FROM member_image_sizes WHERE member_id = $1
+UNION SELECT content_hash FROM member_media WHERE member_id = $1
+UNION SELECT thumbnail_hash FROM member_media WHERE member_id = $1 AND thumbnail_hash IS NOT NULL
+        "#,
+        id,
+    )
+    .fetch_all(&mut *tx)
+    .await?;
+
+    let res = sqlx::query!(
+        r#"
+DELETE FROM members WHERE id = $1 AND deleted_at IS NOT NULL"#,
+        id,
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    if res.rows_affected() < 1 {
+        return Err(MembersError::MemberNotFound);
+    }
+
+    record_changes(
+        &mut tx,
+        Some(auth.current_user.id),
+        ChangeAction::Purge,
+        &[id],
+        &before,
+    )
+    .await?;
+
+    tx.commit().await?;
+
+    images::release(&state.db_pool, state.storage.as_ref(), &keys).await?;
+
+    Ok(())
+}
+
+#[serde_as]
+#[derive(Clone, Deserialize)]
+pub struct HistoryParams {
+    #[serde_as(as = "Option<DisplayFromStr>")]
+    pub page: Option<usize>,
+    #[serde_as(as = "Option<DisplayFromStr>")]
+    pub per_page: Option<usize>,
+}
+
+/// Get the changes made to a member, or to all members if `member_id` is `None`,
+/// most recent first
+pub async fn get_member_changes(
+    db_pool: &PgPool,
+    member_id: Option<i64>,
+    params: &HistoryParams,
+) -> Result<Vec<MemberChangeResponse>, MembersError> {
+    let per_page = params.per_page.unwrap_or(50);
+
+    let recs = sqlx::query_as!(
+        MemberChangeRow,
+        r#"
+SELECT
+    c.id, c.member_id, c.user_id, u.username as "username?",
+    c.action as "action: ChangeAction", c.changed_at, c.before, c.after
+FROM
+    member_changes c
+LEFT JOIN
+    users u ON c.user_id = u.id
+WHERE
+    $1::INT8 IS NULL OR c.member_id = $1
+ORDER BY
+    c.changed_at DESC, c.id DESC
+LIMIT $2 OFFSET $3
+        "#,
+        member_id,
+        per_page as i64,
+        (params.page.unwrap_or(0) * per_page) as i64,
+    )
+    .fetch_all(db_pool)
+    .await?;
+
+    Ok(recs.into_iter().map(Into::into).collect())
+}
+
+/// Get the change history of a member
+pub async fn get_member_history(
+    _auth: AuthExtractor<{ UserRole::Admin as u8 }>,
+    State(state): State<Arc<InnerAppState>>,
+    Path(id): Path<i64>,
+    Query(params): Query<HistoryParams>,
+) -> anyhow::Result<Json<Vec<MemberChangeResponse>>, MembersError> {
+    let changes = get_member_changes(&state.db_pool, Some(id), &params).await?;
+
+    Ok(Json(changes))
+}
+
+/// Get the change history of the whole tree
+pub async fn get_history(
+    _auth: AuthExtractor<{ UserRole::Admin as u8 }>,
+    State(state): State<Arc<InnerAppState>>,
+    Query(params): Query<HistoryParams>,
+) -> anyhow::Result<Json<Vec<MemberChangeResponse>>, MembersError> {
+    let changes = get_member_changes(&state.db_pool, None, &params).await?;
+
+    Ok(Json(changes))
+}
+
+/// Put a member back the way they were right after the given change,
+/// this also brings back purged members
+///
+/// parents that no longer exist are left empty
+pub async fn revert_member(
+    auth: AuthExtractor<{ UserRole::Admin as u8 }>,
+    State(state): State<Arc<InnerAppState>>,
+    Path((id, change_id)): Path<(i64, i64)>,
+) -> anyhow::Result<(), MembersError> {
+    let mut tx = state.db_pool.begin().await?;
+
+    let version = sqlx::query_scalar!(
+        r#"SELECT after FROM member_changes WHERE id = $1 AND member_id = $2"#,
+        change_id,
+        id,
+    )
+    .fetch_optional(&mut *tx)
+    .await?
+    .ok_or(MembersError::ChangeNotFound)?
+    // purges have nothing to go back to
+    .ok_or(MembersError::InvalidValue(String::from("change")))?;
+
+    let version: MemberSnapshot = serde_json::from_value(version).map_err(|e| {
+        log::error!("member change {change_id}: {e}");
+        MembersError::SomethingWentWrong
+    })?;
+
+    let before = snapshots(&mut tx, &[id]).await?;
+    let known = parent_problems(&mut tx, &[id]).await?;
+
+    sqlx::query!(
+        r#"
+INSERT INTO members
+    (id, name, last_name, gender, birthday, death_date, deceased, burial_place,
+    father_id, mother_id, personal_info, deleted_at)
+VALUES
+    ($1, $2, $3, $4, $5, $6, $7, $8,
+    (SELECT id FROM members WHERE id = $9), (SELECT id FROM members WHERE id = $10), $11, $12)
+ON CONFLICT(id)
+DO UPDATE SET
+    name = EXCLUDED.name, last_name = EXCLUDED.last_name, gender = EXCLUDED.gender,
+    birthday = EXCLUDED.birthday, death_date = EXCLUDED.death_date, deceased = EXCLUDED.deceased,
+    burial_place = EXCLUDED.burial_place, father_id = EXCLUDED.father_id,
+    mother_id = EXCLUDED.mother_id, personal_info = EXCLUDED.personal_info,
+    deleted_at = EXCLUDED.deleted_at
+        "#,
+        id,
+        version.name,
+        version.last_name,
+        version.gender as _,
+        version.birthday as _,
+        version.death_date as _,
+        version.deceased,
+        version.burial_place,
+        version.father_id,
+        version.mother_id,
+        version.personal_info,
+        version.deleted_at,
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    search::update_search_keys(&mut tx, &[id]).await?;
+    check_parents(&mut tx, &[id], &known).await?;
+
+    record_changes(
+        &mut tx,
+        Some(auth.current_user.id),
+        ChangeAction::Revert,
+        &[id],
+        &before,
+    )
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(())
+}
+
+async fn get_all_marriages(db_pool: &PgPool) -> Result<Vec<MarriageRowWithSpouses>, MembersError> {
+    sqlx::query_as!(
+        MarriageRowWithSpouses,
+        r#"
+SELECT
+    marriages.id,
+    marriages.husband_id,
+    marriages.wife_id,
+    marriages.married_at,
+    marriages.ended_at,
+    marriages.end_reason as "end_reason: MarriageEndReason",
+    marriages.marriage_order,
+    husband.name AS husband_name,
+    husband.last_name AS husband_last_name,
+    wife.name AS wife_name,
+    wife.last_name AS wife_last_name
+FROM
+    marriages
+INNER JOIN
+    members husband ON marriages.husband_id = husband.id
+INNER JOIN
+    members wife ON marriages.wife_id = wife.id
+WHERE
+    husband.deleted_at IS NULL AND wife.deleted_at IS NULL
+ORDER BY
+    marriages.marriage_order ASC
+        "#,
+    )
+    .fetch_all(db_pool)
+    .await
+    .map_err(Into::into)
+}
+
+/// the marriages that any of the given members is in
+async fn marriages_of(
+    db_pool: &PgPool,
+    ids: &[i64],
+) -> Result<Vec<MarriageRowWithSpouses>, MembersError> {
+    sqlx::query_as!(
+        MarriageRowWithSpouses,
+        r#"
+SELECT
+    marriages.id,
+    marriages.husband_id,
+    marriages.wife_id,
+    marriages.married_at,
+    marriages.ended_at,
+    marriages.end_reason as "end_reason: MarriageEndReason",
+    marriages.marriage_order,
+    husband.name AS husband_name,
+    husband.last_name AS husband_last_name,
+    wife.name AS wife_name,
+    wife.last_name AS wife_last_name
+FROM
+    marriages
+INNER JOIN
+    members husband ON marriages.husband_id = husband.id
+INNER JOIN
+    members wife ON marriages.wife_id = wife.id
+WHERE
+    (marriages.husband_id = ANY($1) OR marriages.wife_id = ANY($1))
+    AND husband.deleted_at IS NULL AND wife.deleted_at IS NULL
+ORDER BY
+    marriages.marriage_order ASC
+        "#,
+        ids,
+    )
+    .fetch_all(db_pool)
+    .await
+    .map_err(Into::into)
+}
+
+/// Makes sure the husband is male, the wife is female, neither is in the trash,
+/// and that the marriage doesn't end before it starts
+async fn validate_marriage(
+    db_pool: &PgPool,
+    marriage: &CreateMarriage,
+) -> Result<(), MembersError> {
+    if let Some((married_at, ended_at)) = marriage.married_at.zip(marriage.ended_at) {
+        if ended_at < married_at {
+            return Err(MembersError::InvalidValue(String::from("ended_at")));
+        }
+    }
+
+    let husband = sqlx::query!(
+        r#"SELECT gender as "gender: Gender" FROM members WHERE id = $1 AND deleted_at IS NULL"#,
+        marriage.husband_id,
+    )
+    .fetch_optional(db_pool)
+    .await?;
+
+    if !husband.is_some_and(|h| h.gender == Gender::Male) {
+        return Err(MembersError::InvalidValue(String::from("husband_id")));
+    }
+
+    let wife = sqlx::query!(
+        r#"SELECT gender as "gender: Gender" FROM members WHERE id = $1 AND deleted_at IS NULL"#,
+        marriage.wife_id,
+    )
+    .fetch_optional(db_pool)
+    .await?;
+
+    if !wife.is_some_and(|w| w.gender == Gender::Female) {
+        return Err(MembersError::InvalidValue(String::from("wife_id")));
+    }
+
+    Ok(())
+}
+
+/// A couple that's married twice is a conflict and not a failure
+fn marriage_error(e: sqlx::Error) -> MembersError {
+    match e.as_database_error() {
+        Some(db_error) if db_error.is_unique_violation() => MembersError::MarriageExists,
+        _ => e.into(),
+    }
+}
+
+/// Get the spouses of a family member
+pub async fn get_member_spouses(
+    State(state): State<Arc<InnerAppState>>,
+    Path(id): Path<i64>,
+) -> anyhow::Result<Json<Vec<SpouseResponse>>, MembersError> {
+    let marriages = sqlx::query_as!(
+        MarriageRowWithSpouses,
+        r#"
+SELECT
+    marriages.id,
+    marriages.husband_id,
+    marriages.wife_id,
+    marriages.married_at,
+    marriages.ended_at,
+    marriages.end_reason as "end_reason: MarriageEndReason",
+    marriages.marriage_order,
+    husband.name AS husband_name,
+    husband.last_name AS husband_last_name,
+    wife.name AS wife_name,
+    wife.last_name AS wife_last_name
+FROM
+    marriages
+INNER JOIN
+    members husband ON marriages.husband_id = husband.id
+INNER JOIN
+    members wife ON marriages.wife_id = wife.id
+WHERE
+    (marriages.husband_id = $1 OR marriages.wife_id = $1)
+    AND husband.deleted_at IS NULL AND wife.deleted_at IS NULL
+        "#,
+        id,
+    )
+    .fetch_all(&state.db_pool)
+    .await?;
+
+    Ok(Json(spouses_of(id, &marriages)))
+}
+
+/// Get how the second member is related by blood to the first one
+pub async fn get_relationship(
+    State(state): State<Arc<InnerAppState>>,
+    Path((id, other_id)): Path<(i64, i64)>,
+) -> anyhow::Result<Json<RelationshipResponse>, MembersError> {
+    let members = sqlx::query_as!(
+        MemberLinkRow,
+        r#"
+WITH RECURSIVE ancestors AS (
+    SELECT id, father_id, mother_id FROM members WHERE id = ANY($1) AND deleted_at IS NULL
+    UNION
+    SELECT m.id, m.father_id, m.mother_id FROM members m
+    INNER JOIN ancestors a ON m.id = a.father_id OR m.id = a.mother_id
+    WHERE m.deleted_at IS NULL
+)
+SELECT id, name, last_name, gender as "gender: Gender", mother_id, father_id, deleted_at
+FROM members
+WHERE id IN (SELECT id FROM ancestors)
+        "#,
+        &[id, other_id],
+    )
+    .fetch_all(&state.db_pool)
+    .await?;
+
+    let members: HashMap<i64, MemberLinkRow> = members.into_iter().map(|m| (m.id, m)).collect();
+
+    if !members.contains_key(&id) || !members.contains_key(&other_id) {
+        return Err(MembersError::MemberNotFound);
+    }
+
+    let relationship = kinship::relate(&members, id, other_id).ok_or(MembersError::NotRelated)?;
+
+    Ok(Json(relationship))
+}
+
+/// Add a marriage between two family members
+pub async fn add_marriage(
+    auth: AuthExtractor<{ UserRole::Admin as u8 }>,
+    State(state): State<Arc<InnerAppState>>,
+    Json(payload): Json<CreateMarriage>,
+) -> anyhow::Result<Json<MarriageResponse>, MembersError> {
+    validate_marriage(&state.db_pool, &payload).await?;
+
+    let mut tx = state.db_pool.begin().await?;
+
+    // marriages added to the same husband at once wait for each other to get their order
+    sqlx::query!(
+        "SELECT id FROM members WHERE id = $1 FOR UPDATE",
+        payload.husband_id,
+    )
+    .fetch_optional(&mut *tx)
+    .await?;
+
+    let spouses = [payload.husband_id, payload.wife_id];
+    let before = snapshots(&mut tx, &spouses).await?;
+
+    let marriage = sqlx::query_as!(
+        MarriageResponse,
+        r#"
+INSERT INTO marriages (husband_id, wife_id, married_at, ended_at, end_reason, marriage_order)
+VALUES (
+    $1, $2, $3, $4, $5,
+    COALESCE($6, (SELECT COALESCE(MAX(marriage_order), 0) + 1 FROM marriages WHERE husband_id = $1))
+)
+RETURNING
+    id, husband_id, wife_id, married_at, ended_at,
+    end_reason as "end_reason: MarriageEndReason",
+    marriage_order
+        "#,
+        payload.husband_id,
+        payload.wife_id,
+        payload.married_at.map(date_to_timestamp),
+        payload.ended_at.map(date_to_timestamp),
+        payload.end_reason as _,
+        payload.marriage_order,
+    )
+    .fetch_one(&mut *tx)
+    .await
+    .map_err(marriage_error)?;
+
+    record_changes(
+        &mut tx,
+        Some(auth.current_user.id),
+        ChangeAction::Edit,
+        &spouses,
+        &before,
+    )
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(Json(marriage))
+}
+
+/// Edit a marriage, all of its fields are replaced
+pub async fn edit_marriage(
+    auth: AuthExtractor<{ UserRole::Admin as u8 }>,
+    State(state): State<Arc<InnerAppState>>,
+    Path(id): Path<i64>,
+    Json(payload): Json<CreateMarriage>,
+) -> anyhow::Result<Json<MarriageResponse>, MembersError> {
+    validate_marriage(&state.db_pool, &payload).await?;
+
+    let mut tx = state.db_pool.begin().await?;
+
+    // the spouses before and after the edit all see it in their history
+    let old = sqlx::query!(
+        "SELECT husband_id, wife_id FROM marriages WHERE id = $1 FOR UPDATE",
+        id,
+    )
+    .fetch_optional(&mut *tx)
+    .await?
+    .ok_or(MembersError::MarriageNotFound)?;
+
+    let spouses = [
+        old.husband_id,
+        old.wife_id,
+        payload.husband_id,
+        payload.wife_id,
+    ];
+    let before = snapshots(&mut tx, &spouses).await?;
+
+    let Some(marriage) = sqlx::query_as!(
+        MarriageResponse,
+        r#"
+UPDATE marriages
+SET
+    husband_id = $2,
+    wife_id = $3,
+    married_at = $4,
+    ended_at = $5,
+    end_reason = $6,
+    marriage_order = COALESCE($7, marriage_order)
+WHERE id = $1
+RETURNING
+    id, husband_id, wife_id, married_at, ended_at,
+    end_reason as "end_reason: MarriageEndReason",
+    marriage_order
+        "#,
+        id,
+        payload.husband_id,
+        payload.wife_id,
+        payload.married_at.map(date_to_timestamp),
+        payload.ended_at.map(date_to_timestamp),
+        payload.end_reason as _,
+        payload.marriage_order,
+    )
+    .fetch_optional(&mut *tx)
+    .await
+    .map_err(marriage_error)?
+    else {
+        return Err(MembersError::MarriageNotFound);
+    };
+
+    record_changes(
+        &mut tx,
+        Some(auth.current_user.id),
+        ChangeAction::Edit,
+        &spouses,
+        &before,
+    )
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(Json(marriage))
+}
+
+/// Remove a marriage
+pub async fn delete_marriage(
+    auth: AuthExtractor<{ UserRole::Admin as u8 }>,
+    State(state): State<Arc<InnerAppState>>,
+    Path(id): Path<i64>,
+) -> anyhow::Result<(), MembersError> {
+    let mut tx = state.db_pool.begin().await?;
+
+    let marriage = sqlx::query!(
+        "SELECT husband_id, wife_id FROM marriages WHERE id = $1 FOR UPDATE",
+        id,
+    )
+    .fetch_optional(&mut *tx)
+    .await?
+    .ok_or(MembersError::MarriageNotFound)?;
+
+    let spouses = [marriage.husband_id, marriage.wife_id];
+    let before = snapshots(&mut tx, &spouses).await?;
+
+    sqlx::query!(
+        r#"
+DELETE FROM marriages WHERE id = $1"#,
+        id,
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    record_changes(
+        &mut tx,
+        Some(auth.current_user.id),
+        ChangeAction::Edit,
+        &spouses,
+        &before,
+    )
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(())
+}
+
+/// Finds the members that aren't connected to the tree drawn from `root`,
+/// or to any of the families when there's no root.
+///
+/// members are connected through their parents, children and spouses,
+/// so families that married into the tree aren't reported
+pub async fn find_unreachable_members(
+    db_pool: &PgPool,
+    root: Option<i64>,
+) -> Result<Vec<UnreachableMemberResponse>, MembersError> {
+    let members = sqlx::query_as!(
+        MemberLinkRow,
+        r#"
+SELECT id, name, last_name, gender as "gender: Gender", mother_id, father_id, deleted_at
+FROM members
+ORDER BY id
+        "#,
+    )
+    .fetch_all(db_pool)
+    .await?;
+
+    // members in the trash aren't part of the tree, but the ones linked to them are reported
+    let (members, trash): (Vec<MemberLinkRow>, Vec<MemberLinkRow>) =
+        members.into_iter().partition(|m| m.deleted_at.is_none());
+    let trash: HashSet<i64> = trash.iter().map(|m| m.id).collect();
+
+    if members.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let marriages = get_all_marriages(db_pool).await?;
+
+    let by_id: HashMap<i64, &MemberLinkRow> = members.iter().map(|m| (m.id, m)).collect();
+
+    let mut links: HashMap<i64, Vec<i64>> = HashMap::new();
+    for member in &members {
+        for parent_id in [member.father_id, member.mother_id].into_iter().flatten() {
+            links.entry(member.id).or_default().push(parent_id);
+            links.entry(parent_id).or_default().push(member.id);
+        }
+    }
+    for marriage in &marriages {
+        links
+            .entry(marriage.husband_id)
+            .or_default()
+            .push(marriage.wife_id);
+        links
+            .entry(marriage.wife_id)
+            .or_default()
+            .push(marriage.husband_id);
+    }
 
-        sqlx::query!(
-            r#"
-UPDATE members
-SET last_name = $1::TEXT
-WHERE id = $2"#,
-            last_name,
-            id,
-        )
-        .execute(&mut *tx)
-        .await?;
+    // without a root every family with no parents is a tree of its own
+    let roots = match root {
+        Some(root_id) => vec![by_id.get(&root_id).ok_or(MembersError::NoRootMember)?.id],
+        None => members
+            .iter()
+            .filter(|m| m.father_id.is_none() && m.mother_id.is_none())
+            .map(|m| m.id)
+            .collect(),
+    };
+
+    let mut reachable = HashSet::new();
+    let mut queue = VecDeque::from(roots);
+    while let Some(id) = queue.pop_front() {
+        if !reachable.insert(id) {
+            continue;
+        }
+
+        if let Some(linked) = links.get(&id) {
+            queue.extend(linked.iter().filter(|l| by_id.contains_key(*l)));
+        }
     }
 
-    if let Some(birthday) = &update_member.birthday {
-        sqlx::query!(
-            r#"
-    UPDATE members
-    SET birthday = $2
-    WHERE id = $1
-            "#,
-            id,
-            birthday,
-        )
-        .execute(&mut *tx)
-        .await?;
+    let unreachable = members
+        .iter()
+        .filter(|m| !reachable.contains(&m.id))
+        .map(|m| {
+            let missing_parent = [m.father_id, m.mother_id]
+                .into_iter()
+                .flatten()
+                .find(|p| !by_id.contains_key(p));
+
+            let reason = if let Some(parent_id) = missing_parent {
+                if trash.contains(&parent_id) {
+                    UnreachableReason::ParentInTrash { parent_id }
+                } else {
+                    UnreachableReason::MissingParent { parent_id }
+                }
+            } else if m.father_id.is_none() && m.mother_id.is_none() {
+                UnreachableReason::NoParents
+            } else {
+                // walk up the line until reaching the member it's hanging from
+                let mut visited = HashSet::from([m.id]);
+                let mut current = m;
+                loop {
+                    let Some(parent) = current
+                        .father_id
+                        .or(current.mother_id)
+                        .and_then(|p| by_id.get(&p))
+                    else {
+                        break UnreachableReason::DisconnectedAncestor {
+                            ancestor_id: current.id,
+                        };
+                    };
+
+                    if !visited.insert(parent.id) {
+                        break UnreachableReason::AncestryCycle;
+                    }
+
+                    current = parent;
+                }
+            };
+
+            UnreachableMemberResponse {
+                id: m.id,
+                name: m.name.clone(),
+                last_name: m.last_name.clone(),
+                gender: m.gender,
+                father_id: m.father_id,
+                mother_id: m.mother_id,
+                reason,
+            }
+        })
+        .collect();
+
+    Ok(unreachable)
+}
+
+/// Get the members that don't show up in the tree, and why
+pub async fn get_unreachable_members(
+    _auth: AuthExtractor<{ UserRole::Admin as u8 }>,
+    State(state): State<Arc<InnerAppState>>,
+    Query(params): Query<MembersParams>,
+) -> anyhow::Result<Json<Vec<UnreachableMemberResponse>>, MembersError> {
+    let unreachable = find_unreachable_members(&state.db_pool, params.root).await?;
+
+    Ok(Json(unreachable))
+}
+
+pub async fn export_members(
+    _auth: AuthExtractor<{ UserRole::Admin as u8 }>,
+    State(state): State<Arc<InnerAppState>>,
+) -> Result<impl IntoResponse, MembersError> {
+    let recs = sqlx::query_as!(
+        MemberRow,
+        r#"
+SELECT
+m.id,
+m.name,
+m.gender as "gender: Gender",
+m.birthday as "birthday: PartialDate",
+m.death_date as "death_date: PartialDate",
+m.deceased,
+m.burial_place,
+m.last_name,
+m.image_hash,
+m.image_type,
+m.personal_info,
+m.father_id,
+m.mother_id,
+nasab.nasab as "nasab",
+NULL::TEXT as "nasab_latin"
+FROM members m
+INNER JOIN member_nasabs($1) nasab ON nasab.id = m.id
+WHERE m.deleted_at IS NULL
+"#,
+        state.nasab.depth as i32,
+    )
+    .fetch_all(&state.db_pool)
+    .await?;
+
+    let mut csv_writer = csv::Writer::from_writer(vec![]);
+
+    for mut rec in recs {
+        let nasab = Nasab::new(rec.nasab.as_deref().unwrap_or(&rec.name), &rec.last_name);
+        rec.nasab = Some(nasab.arabic);
+        rec.nasab_latin = Some(nasab.latin);
+
+        csv_writer.serialize(rec).map_err(|e| {
+            log::error!("{e}");
+            MembersError::SomethingWentWrong
+        })?;
     }
 
-    if let Some(gender) = &update_member.gender {
-        sqlx::query!(
+    let headers = [
+        (axum::http::header::CONTENT_TYPE, "text/csv"),
+        (
+            axum::http::header::CONTENT_DISPOSITION,
+            r#"attachment; filename="exported-members.csv""#,
+        ),
+    ];
+
+    csv_writer.flush().map_err(|e| {
+        log::error!("{e}");
+        MembersError::SomethingWentWrong
+    })?;
+
+    let data = csv_writer.into_inner().map_err(|e| {
+        log::error!("{e}");
+        MembersError::SomethingWentWrong
+    })?;
+
+    Ok((headers, data))
+}
+
+#[serde_as]
+#[derive(Clone, Deserialize)]
+pub struct GedcomExportParams {
+    /// reference each member's photos and documents
+    #[serde_as(as = "Option<DisplayFromStr>")]
+    pub media: Option<bool>,
+}
+
+/// Export members and marriages as a GEDCOM file other genealogy software can read
+pub async fn export_gedcom(
+    _auth: AuthExtractor<{ UserRole::Admin as u8 }>,
+    State(state): State<Arc<InnerAppState>>,
+    Query(params): Query<GedcomExportParams>,
+) -> Result<impl IntoResponse, MembersError> {
+    let members = sqlx::query_as!(
+        MemberSnapshot,
+        r#"
+SELECT
+    id, name, last_name, gender as "gender: Gender",
+    birthday as "birthday: PartialDate", death_date as "death_date: PartialDate",
+    deceased, burial_place, father_id, mother_id, personal_info, deleted_at,
+    image_hash, NULL::JSONB as marriages, NULL::JSONB as media
+FROM members
+WHERE deleted_at IS NULL
+ORDER BY id
+        "#,
+    )
+    .fetch_all(&state.db_pool)
+    .await?;
+
+    let marriages = sqlx::query_as!(
+        MarriageResponse,
+        r#"
+SELECT
+    id, husband_id, wife_id, married_at, ended_at,
+    end_reason as "end_reason: MarriageEndReason",
+    marriage_order
+FROM marriages
+ORDER BY husband_id, marriage_order
+        "#,
+    )
+    .fetch_all(&state.db_pool)
+    .await?;
+
+    let media = if params.media.unwrap_or_default() {
+        sqlx::query_as!(
+            MediaRow,
             r#"
-UPDATE members
-SET gender = $2
-WHERE id = $1
+SELECT
+    mm.id, mm.member_id, mm.kind as "kind: MediaKind", mm.caption,
+    mm.date as "date: PartialDate", mm.position, mm.file_name, mm.content_type,
+    mm.content_hash as "content_hash!",
+    mm.thumbnail_hash IS NOT NULL as "has_thumbnail!",
+    COALESCE(m.image_hash = mm.content_hash, false) as "is_profile!",
+    mm.uploaded_at
+FROM member_media mm
+INNER JOIN members m ON m.id = mm.member_id
+WHERE m.deleted_at IS NULL
+ORDER BY mm.member_id, mm.position, mm.id
             "#,
-            id,
-            gender as _,
         )
-        .execute(&mut *tx)
-        .await?;
+        .fetch_all(&state.db_pool)
+        .await?
+    } else {
+        Vec::new()
+    };
+
+    let nasabs: HashMap<i64, String> = sqlx::query!(
+        r#"
+SELECT m.id, nasab.nasab as "nasab!"
+FROM members m
+INNER JOIN member_nasabs($1) nasab ON nasab.id = m.id
+WHERE m.deleted_at IS NULL
+        "#,
+        state.nasab.depth as i32,
+    )
+    .fetch_all(&state.db_pool)
+    .await?
+    .into_iter()
+    .map(|m| (m.id, m.nasab))
+    .collect();
+
+    let data = gedcom::write(
+        &members,
+        &nasabs,
+        &marriages,
+        &media,
+        Utc::now().date_naive(),
+    );
+
+    let headers = [
+        (
+            axum::http::header::CONTENT_TYPE,
+            "text/vnd.familysearch.gedcom; charset=utf-8",
+        ),
+        (
+            axum::http::header::CONTENT_DISPOSITION,
+            r#"attachment; filename="exported-members.ged""#,
+        ),
+    ];
+
+    Ok((headers, data))
+}
+
+#[serde_as]
+#[derive(Clone, Deserialize)]
+pub struct CsvImportParams {
+    /// only report what the import would do
+    #[serde_as(as = "Option<DisplayFromStr>")]
+    pub dry_run: Option<bool>,
+    /// the `confirmation` of a dry run of the same file,
+    /// needed when the file updates existing members
+    pub confirm: Option<String>,
+    #[serde(default)]
+    pub mode: CsvImportMode,
+}
+
+/// Import members from a CSV file, see [`CsvImportMode`] for how rows are matched to members
+///
+/// nothing is written if any row has an error, they're all reported at once
+pub async fn upload_members_csv(
+    auth: AuthExtractor<{ UserRole::Admin as u8 }>,
+    State(state): State<Arc<InnerAppState>>,
+    Query(params): Query<CsvImportParams>,
+    mut multipart: Multipart,
+) -> Result<Json<CsvImportReport>, MembersError> {
+    let mut file_data = None;
+
+    while let Some(field) = multipart
+        .next_field()
+        .await
+        .map_err(|_e| MembersError::SomethingWentWrong)?
+    {
+        match field.name() {
+            Some("members_csv") => {
+                file_data = Some(field.text().await.map_err(|e| {
+                    log::error!("{e}");
+                    MembersError::SomethingWentWrong
+                })?);
+            }
+            Some(_) => {
+                continue;
+            }
+            None => {
+                return Err(MembersError::BadRequest);
+            }
+        }
     }
 
-    if let Some(mother_id) = &update_member.mother_id {
-        sqlx::query!(
-            r#"
-    UPDATE members
-    SET mother_id = $2
-    WHERE id = $1
-            "#,
-            id,
-            mother_id
-        )
-        .execute(&mut *tx)
-        .await?;
-    } else if remove_mother_id {
-        sqlx::query!(
-            r#"
-    UPDATE members
-    SET mother_id = NULL
-    WHERE id = $1
-            "#,
-            id,
-        )
-        .execute(&mut *tx)
-        .await?;
+    let file_data = file_data.ok_or(MembersError::BadRequest)?;
+
+    let parsed = csv_import::parse(&file_data);
+
+    let mut report = CsvImportReport {
+        dry_run: params.dry_run.unwrap_or_default(),
+        errors: parsed.errors,
+        confirmation: content_key(file_data.as_bytes()),
+        ..Default::default()
+    };
+
+    let mut tx = state.db_pool.begin().await?;
+
+    // the rows are written even on a dry run to check the parents they link,
+    // the transaction is only committed for a confirmed import without errors
+    let (ids, before) = match params.mode {
+        CsvImportMode::Replace => replace_from_csv(&mut tx, &parsed.rows, &mut report).await?,
+        CsvImportMode::Merge => merge_from_csv(&mut tx, &parsed.rows, &mut report).await?,
+    };
+
+    if report.dry_run {
+        tx.rollback().await?;
+        return Ok(Json(report));
+    }
+
+    if !report.errors.is_empty() {
+        return Err(MembersError::InvalidCsv(report.errors));
+    }
+
+    if !report.updated.is_empty() && params.confirm.as_ref() != Some(&report.confirmation) {
+        return Err(MembersError::UnconfirmedImport(report.updated));
+    }
+
+    if params.mode == CsvImportMode::Replace {
+        sqlx::query!(r#"SELECT setval('members_id_seq', (SELECT MAX(id) FROM members));"#)
+            .fetch_optional(&mut *tx)
+            .await?;
+    }
+
+    record_changes(
+        &mut tx,
+        Some(auth.current_user.id),
+        ChangeAction::Import,
+        &ids,
+        &before,
+    )
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(Json(report))
+}
+
+/// Write the rows of a CSV file over the members with the same ids,
+/// returns the ids of the written members and their fields before
+async fn replace_from_csv(
+    conn: &mut PgConnection,
+    csv_rows: &[CsvRow],
+    report: &mut CsvImportReport,
+) -> Result<(Vec<i64>, HashMap<i64, MemberSnapshot>), MembersError> {
+    let ids: Vec<i64> = csv_rows.iter().map(|r| r.member.id).collect();
+    let before = snapshots(conn, &ids).await?;
+    let known = parent_problems(conn, &ids).await?;
+
+    for csv_row in csv_rows {
+        let (row, member) = (csv_row.row, &csv_row.member);
+
+        match before.get(&member.id) {
+            None => report.new.push(CsvImportRow::new(row, member, Vec::new())),
+            Some(existing) => match csv_import::changed_fields(member, existing) {
+                fields if fields.is_empty() => report
+                    .unchanged
+                    .push(CsvImportRow::new(row, member, fields)),
+                fields => report.updated.push(CsvImportRow::new(row, member, fields)),
+            },
+        }
+    }
+
+    let rows: HashMap<i64, usize> = csv_rows.iter().map(|r| (r.member.id, r.row)).collect();
+
+    let parent_ids: Vec<i64> = csv_rows
+        .iter()
+        .flat_map(|r| [r.member.father_id, r.member.mother_id])
+        .flatten()
+        .filter(|id| !rows.contains_key(id))
+        .collect();
+
+    let existing: HashSet<i64> =
+        sqlx::query_scalar!("SELECT id FROM members WHERE id = ANY($1)", &parent_ids,)
+            .fetch_all(&mut *conn)
+            .await?
+            .into_iter()
+            .collect();
+
+    let parse_errors = report.errors.len();
+
+    for csv_row in csv_rows {
+        for (column, parent_id) in [
+            ("father_id", csv_row.member.father_id),
+            ("mother_id", csv_row.member.mother_id),
+        ] {
+            match parent_id {
+                Some(id) if !rows.contains_key(&id) && !existing.contains(&id) => {
+                    report.errors.push(CsvRowError {
+                        row: csv_row.row,
+                        column: Some(column.to_string()),
+                        message: format!("member {id} doesn't exist"),
+                    })
+                }
+                _ => {}
+            }
+        }
+    }
+
+    if report.errors.len() > parse_errors || csv_rows.is_empty() {
+        return Ok((ids, before));
+    }
+
+    let mut query = sqlx::QueryBuilder::new("INSERT INTO members (id, name, last_name, gender, birthday, death_date, deceased, burial_place, mother_id, father_id)");
+
+    query.push_values(csv_rows, |mut b, csv_row| {
+        let members = &csv_row.member;
+
+        b.push_bind(members.id)
+            .push_bind(&members.name)
+            .push_bind(&members.last_name)
+            .push_bind(members.gender)
+            .push_bind(members.birthday)
+            .push_bind(members.death_date)
+            .push_bind(members.deceased)
+            .push_bind(&members.burial_place)
+            .push_bind(members.mother_id)
+            .push_bind(members.father_id);
+    });
+
+    query.push(r#"
+        ON CONFLICT(id)
+        DO UPDATE SET
+        name = EXCLUDED.name, last_name = EXCLUDED.last_name, gender = EXCLUDED.gender,
+        birthday = EXCLUDED.birthday, death_date = EXCLUDED.death_date, deceased = EXCLUDED.deceased,
+        burial_place = EXCLUDED.burial_place, mother_id = EXCLUDED.mother_id, father_id = EXCLUDED.father_id
+    "#);
+
+    query.build().execute(&mut *conn).await?;
+
+    search::update_search_keys(conn, &ids).await?;
+    check_csv_parents(conn, &ids, &known, &rows, report).await?;
+
+    Ok((ids, before))
+}
+
+/// Add the rows of a CSV file as new members, their ids only link them to each other,
+/// a row with an `existing_id` stands for that member and isn't written
+///
+/// returns the ids of the new members and their (empty) fields before
+async fn merge_from_csv(
+    conn: &mut PgConnection,
+    csv_rows: &[CsvRow],
+    report: &mut CsvImportReport,
+) -> Result<(Vec<i64>, HashMap<i64, MemberSnapshot>), MembersError> {
+    let parse_errors = report.errors.len();
+
+    let existing_ids: Vec<i64> = csv_rows.iter().filter_map(|r| r.existing_id).collect();
+    let existing: HashSet<i64> = sqlx::query_scalar!(
+        "SELECT id FROM members WHERE id = ANY($1) AND deleted_at IS NULL",
+        &existing_ids,
+    )
+    .fetch_all(&mut *conn)
+    .await?
+    .into_iter()
+    .collect();
+
+    // the member each id in the file stands for
+    let mut members: HashMap<i64, i64> = HashMap::new();
+
+    for csv_row in csv_rows {
+        let Some(existing_id) = csv_row.existing_id else {
+            continue;
+        };
+
+        if existing.contains(&existing_id) {
+            members.insert(csv_row.member.id, existing_id);
+
+            let mut matched = CsvImportRow::new(csv_row.row, &csv_row.member, Vec::new());
+            matched.member_id = Some(existing_id);
+            report.unchanged.push(matched);
+        } else {
+            report.errors.push(CsvRowError {
+                row: csv_row.row,
+                column: Some(String::from("existing_id")),
+                message: format!("member {existing_id} doesn't exist"),
+            });
+        }
     }
 
-    if let Some(father_id) = &update_member.father_id {
-        sqlx::query!(
-            r#"
-    UPDATE members
-    SET father_id = $2
-    WHERE id = $1
-            "#,
-            id,
-            father_id
-        )
-        .execute(&mut *tx)
-        .await?;
-    } else if remove_father_id {
-        sqlx::query!(
-            r#"
-    UPDATE members
-    SET father_id = NULL
-    WHERE id = $1
-            "#,
-            id,
-        )
-        .execute(&mut *tx)
-        .await?;
+    let file_ids: HashSet<i64> = csv_rows.iter().map(|r| r.member.id).collect();
+    let new_rows: Vec<&CsvRow> = csv_rows
+        .iter()
+        .filter(|r| r.existing_id.is_none())
+        .collect();
+
+    for csv_row in &new_rows {
+        for (column, parent_id) in [
+            ("father_id", csv_row.member.father_id),
+            ("mother_id", csv_row.member.mother_id),
+        ] {
+            match parent_id {
+                Some(id) if !file_ids.contains(&id) => report.errors.push(CsvRowError {
+                    row: csv_row.row,
+                    column: Some(column.to_string()),
+                    message: format!("{id} isn't the id of a row in the file"),
+                }),
+                _ => {}
+            }
+        }
     }
 
-    if let Some(info) = &update_member.info {
-        let info = sqlx::types::JsonValue::deserialize(serde::de::value::MapDeserializer::new(
-            info.clone().into_iter(),
-        ))
-        .map_err(|_e| MembersError::SomethingWentWrong)?;
+    if report.errors.len() > parse_errors || new_rows.is_empty() {
+        return Ok((Vec::new(), HashMap::new()));
+    }
 
-        sqlx::query!(
-            r#"
-    UPDATE members
-    SET personal_info = $2
-    WHERE id = $1
-            "#,
-            id,
-            info,
-        )
-        .execute(&mut *tx)
-        .await?;
-    } else if remove_info {
-        sqlx::query!(
+    let mut ids = Vec::with_capacity(new_rows.len());
+    let mut rows = HashMap::new();
+
+    for csv_row in &new_rows {
+        let member = &csv_row.member;
+
+        let id = sqlx::query_scalar!(
             r#"
-    UPDATE members
-    SET personal_info = NULL
-    WHERE id = $1
+INSERT INTO members (name, gender, birthday, death_date, deceased, burial_place, last_name)
+VALUES ($1, $2, $3, $4, $5, $6, $7)
+RETURNING id
             "#,
-            id,
+            member.name,
+            member.gender as _,
+            member.birthday as _,
+            member.death_date as _,
+            member.deceased,
+            member.burial_place,
+            member.last_name,
         )
-        .execute(&mut *tx)
+        .fetch_one(&mut *conn)
         .await?;
-    }
 
-    if let Some((image, image_type)) = &update_member
-        .image
-        .and_then(|i| update_member.image_type.map(|it| (i, it)))
-    {
-        sqlx::query!(
-            r#"
-UPDATE members
-SET image = $2, image_type = $3
-WHERE id = $1"#,
-            update_member.id,
-            image,
-            image_type,
-        )
-        .execute(&mut *tx)
-        .await?;
-    }
+        members.insert(member.id, id);
+        rows.insert(id, csv_row.row);
+        ids.push(id);
 
-    tx.commit().await?;
+        let mut added = CsvImportRow::new(csv_row.row, member, Vec::new());
+        // the id is thrown away with the rest of a dry run
+        added.member_id = (!report.dry_run).then_some(id);
+        report.new.push(added);
+    }
 
-    Ok(())
-}
+    let parent = |id: Option<i64>| id.and_then(|id| members.get(&id).copied());
+    let fathers: Vec<Option<i64>> = new_rows
+        .iter()
+        .map(|r| parent(r.member.father_id))
+        .collect();
+    let mothers: Vec<Option<i64>> = new_rows
+        .iter()
+        .map(|r| parent(r.member.mother_id))
+        .collect();
 
-/// Remove a family member
-pub async fn delete_member(
-    _auth: AuthExtractor<{ UserRole::Admin as u8 }>,
-    State(state): State<Arc<InnerAppState>>,
-    Path(id): Path<i64>,
-) -> anyhow::Result<(), MembersError> {
     sqlx::query!(
         r#"
-DELETE FROM members WHERE id = $1"#,
-        id,
+UPDATE members m
+SET father_id = p.father_id, mother_id = p.mother_id
+FROM unnest($1::INT8[], $2::INT8[], $3::INT8[]) AS p(id, father_id, mother_id)
+WHERE m.id = p.id
+        "#,
+        &ids,
+        &fathers as &[Option<i64>],
+        &mothers as &[Option<i64>],
     )
-    .execute(&state.db_pool)
+    .execute(&mut *conn)
     .await?;
 
-    Ok(())
-}
-
-pub async fn export_members(
-    _auth: AuthExtractor<{ UserRole::Admin as u8 }>,
-    State(state): State<Arc<InnerAppState>>,
-) -> Result<impl IntoResponse, MembersError> {
-    let recs = sqlx::query_as!(
-        MemberRow,
-        r#"
-SELECT
-m.id,
-m.name,
-m.gender as "gender: Gender",
-m.birthday,
-m.last_name,
-m.image,
-m.image_type,
-m.personal_info,
-m.father_id,
-m.mother_id
-FROM members m
-"#,
-    )
-    .fetch_all(&state.db_pool)
-    .await?;
+    search::update_search_keys(conn, &ids).await?;
+    check_csv_parents(conn, &ids, &[], &rows, report).await?;
 
-    let mut csv_writer = csv::Writer::from_writer(vec![]);
+    Ok((ids, HashMap::new()))
+}
 
-    for rec in recs {
-        csv_writer.serialize(rec).map_err(|e| {
-            log::error!("{e}");
-            MembersError::SomethingWentWrong
-        })?;
+/// Check the parent links of imported members,
+/// the problems are added to the report under the row of the member
+async fn check_csv_parents(
+    conn: &mut PgConnection,
+    ids: &[i64],
+    known: &[InvalidParent],
+    rows: &HashMap<i64, usize>,
+    report: &mut CsvImportReport,
+) -> Result<(), MembersError> {
+    match check_parents(conn, ids, known).await {
+        Ok(()) => Ok(()),
+        Err(MembersError::InvalidParents(problems)) => {
+            report
+                .errors
+                .extend(problems.into_iter().map(|p| CsvRowError {
+                    row: rows.get(&p.member_id).copied().unwrap_or_default(),
+                    column: Some(format!("{}_id", p.side)),
+                    message: p.to_string(),
+                }));
+            Ok(())
+        }
+        Err(e) => Err(e),
     }
-
-    let headers = [
-        (axum::http::header::CONTENT_TYPE, "text/csv"),
-        (
-            axum::http::header::CONTENT_DISPOSITION,
-            &r#"attachment; filename="exported-members.csv""#,
-        ),
-    ];
-
-    csv_writer.flush().map_err(|e| {
-        log::error!("{e}");
-        MembersError::SomethingWentWrong
-    })?;
-
-    let data = csv_writer.into_inner().map_err(|e| {
-        log::error!("{e}");
-        MembersError::SomethingWentWrong
-    })?;
-
-    Ok((headers, data))
 }
 
-pub async fn upload_members_csv(
-    _auth: AuthExtractor<{ UserRole::Admin as u8 }>,
+/// postgres takes at most 65535 bind parameters per query, members are inserted with 9
+const GEDCOM_ROWS_PER_INSERT: usize = 5_000;
+
+/// Import the individuals and families of a GEDCOM file as new members and marriages
+///
+/// the file is imported as a whole or not at all, tags and values that can't be kept
+/// and parent links that can't be right are listed in the response instead of failing the import
+pub async fn import_gedcom(
+    auth: AuthExtractor<{ UserRole::Admin as u8 }>,
     State(state): State<Arc<InnerAppState>>,
     mut multipart: Multipart,
-) -> Result<(), MembersError> {
+) -> Result<Json<GedcomImportResponse>, MembersError> {
+    let mut file_data = None;
+
     while let Some(field) = multipart
         .next_field()
         .await
         .map_err(|_e| MembersError::SomethingWentWrong)?
     {
         match field.name() {
-            Some("members_csv") => {
-                let file_data = field.text().await.map_err(|e| {
+            Some("gedcom") => {
+                let bytes = field.bytes().await.map_err(|e| {
                     log::error!("{e}");
                     MembersError::SomethingWentWrong
                 })?;
 
-                let mut csv_reader = csv::ReaderBuilder::new()
-                    .delimiter(b',')
-                    .from_reader(file_data.as_bytes());
-
-                let members: Vec<MemberRow> = csv_reader
-                    .deserialize::<MemberRow>()
-                    .map(|r| {
-                        r.map_err(|e| {
-                            log::error!("{e}");
-                            MembersError::SomethingWentWrong
-                        })
-                    })
-                    .collect::<Result<Vec<MemberRow>, MembersError>>()?;
+                file_data = Some(
+                    String::from_utf8(bytes.to_vec())
+                        .map_err(|_| MembersError::InvalidValue(String::from("gedcom")))?,
+                );
+            }
+            Some(_) => {
+                continue;
+            }
+            None => {
+                return Err(MembersError::BadRequest);
+            }
+        }
+    }
 
-                let mut tx = state.db_pool.begin().await?;
+    let file_data = file_data.ok_or(MembersError::BadRequest)?;
 
-                let mut query = sqlx::QueryBuilder::new("INSERT INTO members (id, name, last_name, gender, birthday, mother_id, father_id)");
+    let mut report = GedcomImportResponse::default();
+    let gedcom = gedcom::read(&file_data, &mut report);
 
-                query.push_values(members, |mut b, members| {
-                    b.push_bind(members.id)
-                        .push_bind(members.name)
-                        .push_bind(members.last_name)
-                        .push_bind(members.gender)
-                        .push_bind(members.birthday)
-                        .push_bind(members.mother_id)
-                        .push_bind(members.father_id);
-                });
+    let mut tx = state.db_pool.begin().await?;
+
+    // the ids are taken up front so the members can be inserted together
+    let new_ids = sqlx::query_scalar!(
+        r#"
+SELECT nextval(pg_get_serial_sequence('members', 'id')) as "id!"
+FROM generate_series(1, $1::INT8)
+        "#,
+        gedcom.individuals.len() as i64,
+    )
+    .fetch_all(&mut *tx)
+    .await?;
 
-                query.push(r#"
-                    ON CONFLICT(id)
-                    DO UPDATE SET
-                    name = EXCLUDED.name, last_name = EXCLUDED.last_name, gender = EXCLUDED.gender,
-                    birthday = EXCLUDED.birthday, mother_id = EXCLUDED.mother_id, father_id = EXCLUDED.father_id
-                "#);
+    let ids: HashMap<&str, i64> = gedcom
+        .individuals
+        .iter()
+        .map(|individual| individual.xref.as_str())
+        .zip(new_ids)
+        .collect();
 
-                query.build().execute(&mut *tx).await?;
+    for chunk in gedcom.individuals.chunks(GEDCOM_ROWS_PER_INSERT) {
+        let mut query = sqlx::QueryBuilder::new(
+            "INSERT INTO members (id, name, gender, birthday, death_date, deceased, burial_place, last_name, personal_info)",
+        );
 
-                sqlx::query!(r#"SELECT setval('members_id_seq', (SELECT MAX(id) FROM members));"#)
-                    .fetch_optional(&mut *tx)
-                    .await?;
+        let mut rows = Vec::with_capacity(chunk.len());
+        for individual in chunk {
+            let info = (!individual.personal_info.is_empty())
+                .then(|| serde_json::to_value(&individual.personal_info))
+                .transpose()
+                .map_err(anyhow::Error::from)?;
 
-                tx.commit().await?;
-            }
-            Some(_) => {
-                continue;
+            rows.push((individual, info));
+        }
+
+        query.push_values(rows, |mut b, (individual, info)| {
+            b.push_bind(ids[individual.xref.as_str()])
+                .push_bind(&individual.name)
+                .push_bind(individual.gender)
+                .push_bind(individual.birthday)
+                .push_bind(individual.death_date)
+                .push_bind(individual.deceased)
+                .push_bind(&individual.burial_place)
+                .push_bind(&individual.last_name)
+                .push_bind(info);
+        });
+
+        query.build().execute(&mut *tx).await?;
+    }
+
+    let parent = |xref: &Option<String>| xref.as_deref().and_then(|x| ids.get(x).copied());
+
+    let mut children = Vec::new();
+    let mut fathers = Vec::new();
+    let mut mothers = Vec::new();
+
+    for individual in &gedcom.individuals {
+        if individual.father.is_some() || individual.mother.is_some() {
+            children.push(ids[individual.xref.as_str()]);
+            fathers.push(parent(&individual.father));
+            mothers.push(parent(&individual.mother));
+        }
+    }
+
+    sqlx::query!(
+        r#"
+UPDATE members m
+SET father_id = p.father_id, mother_id = p.mother_id
+FROM unnest($1::INT8[], $2::INT8[], $3::INT8[]) AS p(id, father_id, mother_id)
+WHERE m.id = p.id
+        "#,
+        &children,
+        &fathers as &[Option<i64>],
+        &mothers as &[Option<i64>],
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    // the husbands are all new, so their marriages are ordered as the FAM records are
+    let mut orders: HashMap<&str, i32> = HashMap::new();
+    let marriage_orders: Vec<i32> = gedcom
+        .marriages
+        .iter()
+        .map(|marriage| {
+            let order = orders.entry(marriage.husband.as_str()).or_default();
+            *order += 1;
+            *order
+        })
+        .collect();
+
+    for (chunk, chunk_orders) in gedcom
+        .marriages
+        .chunks(GEDCOM_ROWS_PER_INSERT)
+        .zip(marriage_orders.chunks(GEDCOM_ROWS_PER_INSERT))
+    {
+        let mut query = sqlx::QueryBuilder::new(
+            "INSERT INTO marriages (husband_id, wife_id, married_at, ended_at, end_reason, marriage_order)",
+        );
+
+        query.push_values(
+            chunk.iter().zip(chunk_orders),
+            |mut b, (marriage, order)| {
+                b.push_bind(ids[marriage.husband.as_str()])
+                    .push_bind(ids[marriage.wife.as_str()])
+                    .push_bind(marriage.married_at.map(date_to_timestamp))
+                    .push_bind(marriage.ended_at.map(date_to_timestamp))
+                    .push_bind(marriage.end_reason)
+                    .push_bind(order);
+            },
+        );
+
+        query.build().execute(&mut *tx).await?;
+    }
+
+    let lines: HashMap<i64, usize> = gedcom
+        .individuals
+        .iter()
+        .map(|individual| (ids[individual.xref.as_str()], individual.line))
+        .collect();
+    let ids: Vec<i64> = ids.into_values().collect();
+
+    // links that can't be right, like a father that's a woman or a parent born
+    // after their child, are left out and reported instead of failing the whole file
+    for problem in parent_problems(&mut tx, &ids).await? {
+        match problem.side {
+            ParentSide::Father => {
+                sqlx::query!(
+                    "UPDATE members SET father_id = NULL WHERE id = $1",
+                    problem.member_id,
+                )
+                .execute(&mut *tx)
+                .await?;
             }
-            None => {
-                return Err(MembersError::BadRequest);
+            ParentSide::Mother => {
+                sqlx::query!(
+                    "UPDATE members SET mother_id = NULL WHERE id = $1",
+                    problem.member_id,
+                )
+                .execute(&mut *tx)
+                .await?;
             }
         }
+
+        report.problems.push(GedcomProblem::new(
+            lines.get(&problem.member_id).copied().unwrap_or_default(),
+            &format!("{problem}, the link was left out"),
+        ));
     }
 
-    Ok(())
+    search::update_search_keys(&mut tx, &ids).await?;
+    check_parents(&mut tx, &ids, &[]).await?;
+
+    record_changes(
+        &mut tx,
+        Some(auth.current_user.id),
+        ChangeAction::Import,
+        &ids,
+        &HashMap::new(),
+    )
+    .await?;
+
+    tx.commit().await?;
+
+    report.members_added = ids.len();
+    report.marriages_added = gedcom.marriages.len();
+
+    Ok(Json(report))
 }
 
 /// Request adding a family member
@@ -939,17 +3667,54 @@ pub async fn request_add_member(
                 let Ok(birthday) = field.text().await else {
                     return Err(MembersError::InvalidValue(String::from("birthday")));
                 };
-                let birthday = NaiveDate::parse_from_str(&birthday, "%Y-%m-%d")
-                    .map_err(|e| {
-                        log::error!("birthday error: {e}");
-                        MembersError::InvalidValue(String::from("birthday"))
-                    })?
-                    .and_time(
-                        NaiveTime::from_hms_opt(0, 0, 1).expect("00:00:01 should be a valid time"),
-                    )
-                    .and_utc();
+
+                if birthday.is_empty() {
+                    continue;
+                }
+
+                let birthday = birthday.parse::<PartialDate>().map_err(|e| {
+                    log::error!("birthday error: {e}");
+                    MembersError::InvalidValue(String::from("birthday"))
+                })?;
                 new_member_builder.birthday(birthday);
             }
+            Some("death_date") => {
+                let Ok(death_date) = field.text().await else {
+                    return Err(MembersError::InvalidValue(String::from("death_date")));
+                };
+
+                if death_date.is_empty() {
+                    continue;
+                }
+
+                let death_date = death_date.parse::<PartialDate>().map_err(|e| {
+                    log::error!("death_date error: {e}");
+                    MembersError::InvalidValue(String::from("death_date"))
+                })?;
+                new_member_builder.death_date(death_date);
+            }
+            Some("deceased") => {
+                let Ok(deceased) = field.text().await else {
+                    return Err(MembersError::InvalidValue(String::from("deceased")));
+                };
+
+                new_member_builder.deceased(
+                    deceased
+                        .parse()
+                        .map_err(|_e| MembersError::InvalidValue(String::from("deceased")))?,
+                );
+            }
+            Some("burial_place") => {
+                let Ok(burial_place) = field.text().await else {
+                    return Err(MembersError::InvalidValue(String::from("burial_place")));
+                };
+
+                if burial_place.is_empty() {
+                    continue;
+                }
+
+                new_member_builder.burial_place(burial_place);
+            }
             Some("father_id") => {
                 let Ok(father_id) = field.text().await else {
                     return Err(MembersError::InvalidValue(String::from("father_id")));
@@ -984,7 +3749,7 @@ pub async fn request_add_member(
                 if let Some(image_content_type) = field.content_type() {
                     let image_content_type = image_content_type.to_string();
                     match image_content_type.as_str() {
-                        "image/png" | "image/jpg" | "image/jpeg" => {
+                        content_type if images::ACCEPTED_TYPES.contains(&content_type) => {
                             let Ok(image) = field.bytes().await else {
                                 return Err(MembersError::InvalidValue(String::from("image")));
                             };
@@ -993,8 +3758,10 @@ pub async fn request_add_member(
                                 continue;
                             }
 
-                            new_member_builder.image(image.to_vec());
-                            new_member_builder.image_type(image_content_type.to_string());
+                            let (image, image_type) = images::normalize(image.to_vec()).await?;
+
+                            new_member_builder.image(image);
+                            new_member_builder.image_type(image_type.to_string());
                         }
                         mime_type => {
                             log::debug!("{mime_type}");
@@ -1039,26 +3806,38 @@ pub async fn request_add_member(
         .ok()
     });
 
+    let mut tx = state.db_pool.begin().await?;
+
+    let image_hash = match &new_member.image {
+        Some(image) => Some(images::put(&mut tx, state.storage.as_ref(), image).await?),
+        None => None,
+    };
+
     sqlx::query!(
         r#"
-            INSERT INTO member_add_requests (id, name, gender, birthday, last_name, father_id, mother_id, image, image_type, personal_info, submitted_at)
-            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11)
+            INSERT INTO member_add_requests (id, name, gender, birthday, death_date, deceased, burial_place, last_name, father_id, mother_id, image_hash, image_type, personal_info, submitted_at)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14)
         "#,
         uuid::Uuid::new_v4(),
         new_member.name,
         new_member.gender as _,
-        new_member.birthday,
+        new_member.birthday as _,
+        new_member.death_date as _,
+        new_member.deceased,
+        new_member.burial_place,
         new_member.last_name,
         new_member.father_id,
         new_member.mother_id,
-        new_member.image,
+        image_hash,
         new_member.image_type,
         info,
         Utc::now().naive_utc(),
     )
-    .execute(&state.db_pool)
+    .execute(&mut *tx)
     .await?;
 
+    tx.commit().await?;
+
     Ok(())
 }
 
@@ -1078,21 +3857,24 @@ pub async fn get_requested_members_flat(
             m.id,
             m.name,
             m.gender as "gender: Gender",
-            m.birthday,
+            m.birthday as "birthday: PartialDate",
+            m.death_date as "death_date: PartialDate",
+            m.deceased,
+            m.burial_place,
             m.last_name,
-            m.image,
+            m.image_hash,
             m.image_type,
             m.personal_info,
             m.status as "status: RequestStatus",
             mother.id as mother_id,
             mother.name AS mother_name,
             mother.gender AS "mother_gender: Gender",
-            mother.birthday AS mother_birthday,
+            mother.birthday AS "mother_birthday: PartialDate",
             mother.last_name AS mother_last_name,
             father.id as father_id,
             father.name AS father_name,
             father.gender AS "father_gender: Gender",
-            father.birthday AS father_birthday,
+            father.birthday AS "father_birthday: PartialDate",
             father.last_name AS father_last_name
         FROM
             member_add_requests m
@@ -1161,21 +3943,24 @@ pub async fn get_requested_members_flat(
             m.id,
             m.name,
             m.gender as "gender: Gender",
-            m.birthday,
+            m.birthday as "birthday: PartialDate",
+            m.death_date as "death_date: PartialDate",
+            m.deceased,
+            m.burial_place,
             m.last_name,
-            m.image,
+            m.image_hash,
             m.image_type,
             m.personal_info,
             m.status as "status: RequestStatus",
             mother.id as mother_id,
             mother.name AS mother_name,
             mother.gender AS "mother_gender: Gender",
-            mother.birthday AS mother_birthday,
+            mother.birthday AS "mother_birthday: PartialDate",
             mother.last_name AS mother_last_name,
             father.id as father_id,
             father.name AS father_name,
             father.gender AS "father_gender: Gender",
-            father.birthday AS father_birthday,
+            father.birthday AS "father_birthday: PartialDate",
             father.last_name AS father_last_name
         FROM
             member_add_requests m
@@ -1206,7 +3991,12 @@ pub async fn get_requested_members_flat(
             id: m.id,
             name: m.name,
             gender: m.gender,
-            birthday: m.birthday,
+            birthday: m.birthday.map(|d| d.to_gregorian()),
+            birthday_hijri: m.birthday.and_then(|d| d.to_hijri()),
+            death_date: m.death_date.map(|d| d.to_gregorian()),
+            death_date_hijri: m.death_date.and_then(|d| d.to_hijri()),
+            deceased: m.deceased,
+            burial_place: m.burial_place,
             last_name: m.last_name,
             father_id: m.father_id,
             mother_id: m.mother_id,
@@ -1218,7 +4008,7 @@ pub async fn get_requested_members_flat(
                         .collect::<IndexMap<String, String>>()
                 })
             }),
-            image: m.image,
+            image_hash: m.image_hash,
             image_type: m.image_type,
             status: m.status,
         })
@@ -1229,7 +4019,7 @@ pub async fn get_requested_members_flat(
 
 /// Approve a member add request
 pub async fn approve_member_request(
-    _auth: AuthExtractor<{ UserRole::Admin as u8 }>,
+    auth: AuthExtractor<{ UserRole::Admin as u8 }>,
     State(state): State<Arc<InnerAppState>>,
     Path(id): Path<Uuid>,
 ) -> anyhow::Result<(), MembersError> {
@@ -1243,7 +4033,9 @@ SET status = $1
 WHERE id = $2 AND status = $3
 RETURNING
     id, name, gender as "gender: Gender",
-    birthday, father_id, image, last_name,
+    birthday as "birthday: PartialDate", death_date as "death_date: PartialDate",
+    deceased, burial_place,
+    father_id, image_hash, last_name,
     image_type, mother_id, personal_info,
     status as "status: RequestStatus";
 "#,
@@ -1254,22 +4046,38 @@ RETURNING
     .fetch_one(&mut *tx)
     .await?;
 
-    sqlx::query!(
+    let member_id = sqlx::query_scalar!(
         r#"
-            INSERT INTO members (name, gender, birthday, last_name, father_id, mother_id, image, image_type, personal_info)
-            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)
+            INSERT INTO members (name, gender, birthday, death_date, deceased, burial_place, last_name, father_id, mother_id, image_hash, image_type, personal_info)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12)
+            RETURNING id
         "#,
         member.name,
         member.gender as _,
-        member.birthday,
+        member.birthday as _,
+        member.death_date as _,
+        member.deceased,
+        member.burial_place,
         member.last_name,
         member.father_id,
         member.mother_id,
-        member.image,
+        member.image_hash,
         member.image_type,
         member.personal_info,
     )
-    .execute(&mut *tx)
+    .fetch_one(&mut *tx)
+    .await?;
+
+    search::update_search_keys(&mut tx, &[member_id]).await?;
+    check_parents(&mut tx, &[member_id], &[]).await?;
+
+    record_changes(
+        &mut tx,
+        Some(auth.current_user.id),
+        ChangeAction::Approve,
+        &[member_id],
+        &HashMap::new(),
+    )
     .await?;
 
     tx.commit().await?;
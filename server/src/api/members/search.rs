@@ -0,0 +1,194 @@
+use sqlx::{PgConnection, PgPool};
+
+use super::transliteration::to_latin;
+
+/// The words that are written apart from or joined to the name after them,
+/// e.g. Al Shammari and Alshammari or Abdul Rahman and Abdulrahman
+const JOINED: [&str; 9] = [
+    "al", "el", "abd", "abdul", "abdel", "abdal", "abu", "abo", "aba",
+];
+
+/// A name in Latin letters reduced to how it sounds, the same for the common spellings
+/// of the name and for the name in Arabic, e.g. محمد, Muhammad, Mohammed and Mohamad
+/// are all "mhmd"
+///
+/// short vowels aren't written in Arabic and are spelled differently by everyone,
+/// so only a word's first vowel is kept, as "a", and a long i or a diphthong, which
+/// Arabic does write, as "y", e.g. حسين, Husayn and Hussein are "hsyn" but حسن is "hsn"
+pub fn search_key(text: &str) -> String {
+    let latin = to_latin(text).to_lowercase();
+    let mut words: Vec<String> = Vec::new();
+    let mut joined = false;
+
+    for word in latin
+        .split(|c: char| c.is_whitespace() || c == '-')
+        .filter(|w| !w.is_empty())
+    {
+        match words.last_mut() {
+            Some(last) if joined => last.push_str(word),
+            _ => words.push(word.to_string()),
+        }
+        joined = JOINED.contains(&word);
+    }
+
+    words
+        .iter()
+        .map(|w| word_key(w))
+        .filter(|w| !w.is_empty())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn word_key(word: &str) -> String {
+    let word = word.replace("ph", "f").replace("ck", "k").replace('q', "k");
+
+    // doubled letters are one letter with a shadda, which isn't always written
+    let mut letters: Vec<char> = word.chars().filter(char::is_ascii_alphabetic).collect();
+    letters.dedup();
+
+    // y is a vowel after another letter unless a vowel follows it, e.g. Husayn but not Yusuf
+    let vowels: Vec<bool> = (0..letters.len())
+        .map(|i| match letters[i] {
+            'a' | 'e' | 'i' | 'o' | 'u' => true,
+            'y' => i > 0 && !matches!(letters.get(i + 1), Some('a' | 'e' | 'i' | 'o' | 'u')),
+            _ => false,
+        })
+        .collect();
+
+    let mut key = String::with_capacity(letters.len());
+    let mut i = 0;
+
+    while i < letters.len() {
+        if vowels[i] {
+            let start = i;
+            while vowels.get(i).copied().unwrap_or(false) {
+                i += 1;
+            }
+
+            if start == 0 {
+                key.push('a');
+            }
+            if i - start > 1 && matches!(letters[i - 1], 'e' | 'i' | 'y') {
+                key.push('y');
+            }
+            continue;
+        }
+
+        // an h after a vowel that doesn't start a syllable isn't heard, e.g. Sarah and Sara
+        let silent = letters[i] == 'h'
+            && i > 0
+            && vowels[i - 1]
+            && !vowels.get(i + 1).copied().unwrap_or(false);
+
+        if !silent {
+            key.push(letters[i]);
+        }
+        i += 1;
+    }
+
+    key
+}
+
+/// Make the search keys of the given members from their names,
+/// wherever names are written since the database can't make them itself
+pub async fn update_search_keys(conn: &mut PgConnection, ids: &[i64]) -> Result<(), sqlx::Error> {
+    let names = sqlx::query_as!(
+        MemberName,
+        "SELECT id, name, last_name FROM members WHERE id = ANY($1)",
+        ids,
+    )
+    .fetch_all(&mut *conn)
+    .await?;
+
+    save_search_keys(conn, &names).await
+}
+
+/// Make the search keys of the members that don't have one yet,
+/// the ones from before the keys were kept or were made differently
+pub async fn fill_search_keys(db_pool: &PgPool) -> Result<(), sqlx::Error> {
+    let mut conn = db_pool.acquire().await?;
+
+    let names = sqlx::query_as!(
+        MemberName,
+        "SELECT id, name, last_name FROM members WHERE search_key IS NULL",
+    )
+    .fetch_all(&mut *conn)
+    .await?;
+
+    save_search_keys(&mut conn, &names).await
+}
+
+struct MemberName {
+    id: i64,
+    name: String,
+    last_name: String,
+}
+
+async fn save_search_keys(
+    conn: &mut PgConnection,
+    names: &[MemberName],
+) -> Result<(), sqlx::Error> {
+    if names.is_empty() {
+        return Ok(());
+    }
+
+    let ids: Vec<i64> = names.iter().map(|m| m.id).collect();
+    let keys: Vec<String> = names
+        .iter()
+        .map(|m| search_key(&format!("{} {}", m.name, m.last_name)))
+        .collect();
+
+    sqlx::query!(
+        r#"
+UPDATE members m SET search_key = k.key
+FROM unnest($1::BIGINT[], $2::TEXT[]) AS k(id, key)
+WHERE m.id = k.id
+        "#,
+        &ids,
+        &keys,
+    )
+    .execute(conn)
+    .await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn same_name_in_both_scripts() {
+        assert_eq!(search_key("الشمري"), search_key("Alshammari"));
+        assert_eq!(search_key("الشمري"), search_key("Al-Shammari"));
+        assert_eq!(search_key("الشمري"), search_key("Al Shamri"));
+        assert_eq!(search_key("حسن"), search_key("Hasan"));
+        assert_eq!(search_key("حسن"), search_key("Hassan"));
+        assert_eq!(search_key("حسين"), search_key("Husayn"));
+        assert_eq!(search_key("حسين"), search_key("Hussein"));
+        assert_eq!(search_key("حسين"), search_key("Husain"));
+        assert_eq!(search_key("محمد الشمري"), search_key("Mohammed Alshammari"));
+        assert_eq!(search_key("عبدالرحمن"), search_key("Abdul Rahman"));
+        assert_eq!(search_key("عبدالرحمن"), search_key("Abd al-Rahman"));
+        assert_eq!(search_key("يوسف"), search_key("Yousef"));
+        assert_eq!(search_key("فيصل"), search_key("Faysal"));
+        assert_eq!(search_key("نايف"), search_key("Naif"));
+    }
+
+    #[test]
+    fn different_names() {
+        assert_ne!(search_key("حسن"), search_key("حسين"));
+        assert_ne!(search_key("Hasan"), search_key("Hussein"));
+        assert_ne!(search_key("محمد"), search_key("محمود"));
+    }
+
+    #[test]
+    fn keys() {
+        assert_eq!(search_key("Muhammad"), "mhmd");
+        assert_eq!(search_key("Mohamad"), "mhmd");
+        assert_eq!(search_key("حسين"), "hsyn");
+        assert_eq!(search_key("Alshammari"), "alshmr");
+        // the name after Abu or Al is part of the same word
+        assert_eq!(search_key("Ali Al Shammari"), "al alshmr");
+    }
+}
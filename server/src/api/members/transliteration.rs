@@ -0,0 +1,300 @@
+/// Names that are spelled a settled way in Latin letters, which the letters alone don't give
+/// since short vowels aren't written, compared without diacritics or hamzas
+const KNOWN_NAMES: &[(&str, &str)] = &[
+    ("بن", "bin"),
+    ("بنت", "bint"),
+    ("ابن", "ibn"),
+    ("أبو", "Abu"),
+    ("أم", "Umm"),
+    ("آل", "Al"),
+    ("محمد", "Muhammad"),
+    ("أحمد", "Ahmad"),
+    ("محمود", "Mahmud"),
+    ("مصطفى", "Mustafa"),
+    ("علي", "Ali"),
+    ("عمر", "Umar"),
+    ("عثمان", "Uthman"),
+    ("حسن", "Hasan"),
+    ("حسين", "Husayn"),
+    ("إبراهيم", "Ibrahim"),
+    ("إسماعيل", "Ismail"),
+    ("إسحاق", "Ishaq"),
+    ("إلياس", "Ilyas"),
+    ("يوسف", "Yusuf"),
+    ("يعقوب", "Yaqub"),
+    ("موسى", "Musa"),
+    ("عيسى", "Isa"),
+    ("يحيى", "Yahya"),
+    ("سليمان", "Sulayman"),
+    ("سلطان", "Sultan"),
+    ("فيصل", "Faisal"),
+    ("سعد", "Saad"),
+    ("سعود", "Saud"),
+    ("حمد", "Hamad"),
+    ("تركي", "Turki"),
+    ("مشعل", "Mishal"),
+    ("نايف", "Nayif"),
+    ("أسامة", "Usamah"),
+    ("أنس", "Anas"),
+    ("زياد", "Ziyad"),
+    ("إله", "Ilah"),
+    ("أمل", "Amal"),
+    ("عبدالله", "Abdullah"),
+    ("فاطمة", "Fatimah"),
+    ("عائشة", "Aishah"),
+    ("خديجة", "Khadijah"),
+    ("مريم", "Maryam"),
+    ("زينب", "Zaynab"),
+    ("نورة", "Nurah"),
+    ("سارة", "Sarah"),
+    ("منيرة", "Munirah"),
+    ("لطيفة", "Latifah"),
+    ("حصة", "Hissah"),
+    ("أسماء", "Asma"),
+    ("آمنة", "Aminah"),
+    ("رقية", "Ruqayyah"),
+    ("شيخة", "Shaykhah"),
+];
+
+/// Write Arabic text in Latin letters, e.g. محمد بن عبدالعزيز الخالدي
+/// as Muhammad bin Abdulaziz Al-Khalidi
+///
+/// words that aren't in Arabic are kept as they are
+pub fn to_latin(text: &str) -> String {
+    let words: Vec<String> = text.split_whitespace().map(plain).collect();
+    let mut latin = Vec::with_capacity(words.len());
+    let mut i = 0;
+
+    while i < words.len() {
+        // عبد is only a name with the word after it, e.g. عبد الرحمن
+        if words[i] == "عبد" && i + 1 < words.len() {
+            latin.push(abd(&words[i + 1]));
+            i += 2;
+        } else {
+            latin.push(word(&words[i]));
+            i += 1;
+        }
+    }
+
+    latin.join(" ")
+}
+
+fn is_arabic(c: char) -> bool {
+    ('\u{0621}'..='\u{064A}').contains(&c)
+}
+
+/// the word without diacritics or tatweel
+fn plain(word: &str) -> String {
+    word.chars()
+        .filter(|c| !('\u{064B}'..='\u{0652}').contains(c) && !matches!(c, '\u{0670}' | 'ـ'))
+        .collect()
+}
+
+/// the word as it's looked up in [`KNOWN_NAMES`]
+fn key(word: &str) -> String {
+    plain(word)
+        .chars()
+        .map(|c| match c {
+            'أ' | 'إ' | 'آ' | 'ٱ' => 'ا',
+            'ى' => 'ي',
+            'ة' => 'ه',
+            c => c,
+        })
+        .collect()
+}
+
+fn known(word: &str) -> Option<&'static str> {
+    let word = key(word);
+
+    KNOWN_NAMES
+        .iter()
+        .find(|(arabic, _)| key(arabic) == word)
+        .map(|(_, latin)| *latin)
+}
+
+fn capitalize(word: &str) -> String {
+    let mut chars = word.chars();
+
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+fn word(word: &str) -> String {
+    if !word.chars().any(is_arabic) {
+        return word.to_string();
+    }
+
+    if let Some(latin) = known(word) {
+        return latin.to_string();
+    }
+
+    if let Some(rest) = word.strip_prefix("عبد").filter(|rest| !rest.is_empty()) {
+        return abd(rest);
+    }
+
+    if let Some(rest) = word
+        .strip_prefix("ال")
+        .filter(|rest| rest.chars().count() > 1)
+    {
+        return format!("Al-{}", capitalize(&self::word(rest)));
+    }
+
+    capitalize(&letters(word))
+}
+
+/// عبد with the name after it, e.g. عبدالرحمن is Abdulrahman
+fn abd(name: &str) -> String {
+    if key(name) == "الله" {
+        return String::from("Abdullah");
+    }
+
+    let name = name.strip_prefix("ال").unwrap_or(name);
+
+    format!("Abdul{}", word(name).to_lowercase())
+}
+
+enum Letter {
+    Consonant(&'static str),
+    Vowel(&'static str),
+}
+
+/// The vowel letters, which can also be consonants depending on what's around them
+fn is_vowel(c: char) -> bool {
+    matches!(c, 'ا' | 'و' | 'ي' | 'ى' | 'ة' | 'آ')
+}
+
+/// Spell a word letter by letter, guessing the short vowels that aren't written
+fn letters(word: &str) -> String {
+    let chars: Vec<char> = word.chars().collect();
+    let mut letters: Vec<Letter> = Vec::with_capacity(chars.len());
+
+    for (i, c) in chars.iter().enumerate() {
+        let after_consonant = matches!(letters.last(), Some(Letter::Consonant(_)));
+        let before_consonant = chars.get(i + 1).is_none_or(|next| !is_vowel(*next));
+
+        let letter = match c {
+            'ا' | 'أ' | 'آ' if i == 0 => Letter::Vowel("a"),
+            'إ' if i == 0 => Letter::Vowel("i"),
+            'ع' if i == 0 => Letter::Vowel("a"),
+            'ا' | 'آ' | 'ى' => Letter::Vowel("a"),
+            'ة' if i + 1 == chars.len() => Letter::Vowel("ah"),
+            'ة' => Letter::Consonant("t"),
+            'و' if i > 0 && after_consonant && before_consonant => Letter::Vowel("u"),
+            'و' => Letter::Consonant("w"),
+            'ي' if i > 0 && after_consonant && before_consonant => Letter::Vowel("i"),
+            'ي' => Letter::Consonant("y"),
+            'ء' | 'أ' | 'إ' | 'ؤ' | 'ئ' | 'ع' => Letter::Consonant("'"),
+            'ب' => Letter::Consonant("b"),
+            'ت' | 'ط' => Letter::Consonant("t"),
+            'ث' => Letter::Consonant("th"),
+            'ج' => Letter::Consonant("j"),
+            'ح' | 'ه' => Letter::Consonant("h"),
+            'خ' => Letter::Consonant("kh"),
+            'د' | 'ض' => Letter::Consonant("d"),
+            'ذ' => Letter::Consonant("dh"),
+            'ر' => Letter::Consonant("r"),
+            'ز' | 'ظ' => Letter::Consonant("z"),
+            'س' | 'ص' => Letter::Consonant("s"),
+            'ش' => Letter::Consonant("sh"),
+            'غ' => Letter::Consonant("gh"),
+            'ف' => Letter::Consonant("f"),
+            'ق' => Letter::Consonant("q"),
+            'ك' => Letter::Consonant("k"),
+            'ل' => Letter::Consonant("l"),
+            'م' => Letter::Consonant("m"),
+            'ن' => Letter::Consonant("n"),
+            _ => continue,
+        };
+
+        // an initial ع or hamza already gives the vowel of a long alif after it
+        if let (Letter::Vowel(vowel), Some(Letter::Vowel(last))) = (&letter, letters.last()) {
+            if vowel == last {
+                continue;
+            }
+        }
+
+        letters.push(letter);
+    }
+
+    let consonant = |i: usize| matches!(letters.get(i), Some(Letter::Consonant(_)));
+
+    // a long alif followed by two consonants at the end of the word is the فاعل pattern,
+    // which takes an i between them, e.g. خالد, ناصر, and فاطمة or خالدي after them
+    let participle = letters
+        .iter()
+        .rposition(|l| matches!(l, Letter::Vowel("a")))
+        .filter(|&a| {
+            a > 0
+                && consonant(a + 1)
+                && consonant(a + 2)
+                && (a + 3 == letters.len() || (a + 4 == letters.len() && !consonant(a + 3)))
+        })
+        .map(|a| a + 1);
+
+    let mut latin = String::new();
+    let mut after_vowel = false;
+
+    for (i, letter) in letters.iter().enumerate() {
+        match letter {
+            Letter::Vowel(sound) => {
+                latin.push_str(sound);
+                after_vowel = true;
+            }
+            Letter::Consonant(sound) => {
+                latin.push_str(sound);
+
+                if participle == Some(i) {
+                    latin.push('i');
+                    after_vowel = true;
+                } else if !after_vowel && consonant(i + 1) {
+                    // a syllable starts with a single consonant and a vowel
+                    latin.push('a');
+                    after_vowel = true;
+                } else {
+                    after_vowel = false;
+                }
+            }
+        }
+    }
+
+    latin
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn known_names() {
+        assert_eq!(to_latin("محمد بن علي"), "Muhammad bin Ali");
+        assert_eq!(to_latin("فاطمة بنت حسين"), "Fatimah bint Husayn");
+        // with diacritics and hamzas written differently
+        assert_eq!(to_latin("مُحَمَّد"), "Muhammad");
+        assert_eq!(to_latin("ابراهيم"), "Ibrahim");
+    }
+
+    #[test]
+    fn letters() {
+        assert_eq!(
+            to_latin("محمد بن عبدالعزيز الخالدي"),
+            "Muhammad bin Abdulaziz Al-Khalidi"
+        );
+        assert_eq!(to_latin("الشمري"), "Al-Shamri");
+        assert_eq!(to_latin("خالد"), "Khalid");
+        assert_eq!(to_latin("ناصر"), "Nasir");
+    }
+
+    #[test]
+    fn abd() {
+        assert_eq!(to_latin("عبدالله"), "Abdullah");
+        assert_eq!(to_latin("عبد الله"), "Abdullah");
+        assert_eq!(to_latin("عبد الرحمن"), "Abdulrahman");
+    }
+
+    #[test]
+    fn latin_is_kept() {
+        assert_eq!(to_latin("Smith محمد"), "Smith Muhammad");
+    }
+}
@@ -0,0 +1,334 @@
+use std::collections::{HashMap, HashSet};
+
+use chrono::Months;
+use sqlx::PgConnection;
+
+use crate::Gender;
+
+use super::{
+    dates::PartialDate,
+    models::{InvalidParent, ParentProblem, ParentSide},
+    MembersError,
+};
+
+/// the youngest a parent can be when their child is born
+const MIN_PARENT_AGE: u32 = 12;
+
+const MAX_MOTHER_AGE: u32 = 60;
+
+const MAX_FATHER_AGE: u32 = 90;
+
+/// how long after the father's death a child can still be born
+const MAX_POSTHUMOUS_MONTHS: u32 = 12;
+
+/// The fields of a member that matter for their parent links
+struct Lineage {
+    id: i64,
+    gender: Gender,
+    birthday: Option<PartialDate>,
+    death_date: Option<PartialDate>,
+    father_id: Option<i64>,
+    mother_id: Option<i64>,
+    /// in the trash
+    deleted: bool,
+}
+
+impl Lineage {
+    fn parents(&self) -> impl Iterator<Item = (ParentSide, i64)> {
+        [
+            self.father_id.map(|id| (ParentSide::Father, id)),
+            self.mother_id.map(|id| (ParentSide::Mother, id)),
+        ]
+        .into_iter()
+        .flatten()
+    }
+}
+
+/// Check the parent links of the given members as they are now,
+/// problems that are in `known` are let through so bad links that were already
+/// there don't block changes to the rest of the member
+///
+/// meant to run inside the transaction that changed them, after the change,
+/// so a failed check rolls it back
+pub async fn check_parents(
+    conn: &mut PgConnection,
+    ids: &[i64],
+    known: &[InvalidParent],
+) -> Result<(), MembersError> {
+    let problems: Vec<InvalidParent> = parent_problems(conn, ids)
+        .await?
+        .into_iter()
+        .filter(|p| !known.contains(p))
+        .collect();
+
+    if problems.is_empty() {
+        Ok(())
+    } else {
+        Err(MembersError::InvalidParents(problems))
+    }
+}
+
+/// Find the problems with the parent links of the given members,
+/// both to their parents and from their children
+pub async fn parent_problems(
+    conn: &mut PgConnection,
+    ids: &[i64],
+) -> Result<Vec<InvalidParent>, sqlx::Error> {
+    let lineages = sqlx::query_as!(
+        Lineage,
+        r#"
+WITH RECURSIVE ancestors AS (
+    SELECT id, father_id, mother_id FROM members WHERE id = ANY($1)
+    UNION
+    SELECT m.id, m.father_id, m.mother_id FROM members m
+    INNER JOIN ancestors a ON m.id = a.father_id OR m.id = a.mother_id
+)
+SELECT
+    id, gender as "gender: Gender",
+    birthday as "birthday: PartialDate", death_date as "death_date: PartialDate",
+    father_id, mother_id, deleted_at IS NOT NULL as "deleted!"
+FROM members
+WHERE id IN (SELECT id FROM ancestors) OR father_id = ANY($1) OR mother_id = ANY($1)
+        "#,
+        ids,
+    )
+    .fetch_all(conn)
+    .await?;
+
+    let lineages: HashMap<i64, Lineage> = lineages.into_iter().map(|m| (m.id, m)).collect();
+    let ids: HashSet<i64> = ids.iter().copied().collect();
+
+    Ok(find_problems(&lineages, &ids))
+}
+
+/// the problems with the links to or from the members in `ids`,
+/// `lineages` has them, their ancestors and their children
+fn find_problems(lineages: &HashMap<i64, Lineage>, ids: &HashSet<i64>) -> Vec<InvalidParent> {
+    let mut problems = Vec::new();
+
+    for member in lineages.values() {
+        for (side, parent_id) in member.parents() {
+            // only links to or from the checked members, the rest of the tree is left alone
+            if !ids.contains(&member.id) && !ids.contains(&parent_id) {
+                continue;
+            }
+
+            let problem = if parent_id == member.id {
+                Some(ParentProblem::OwnParent)
+            } else if ids.contains(&member.id) && is_ancestor(lineages, member.id, parent_id) {
+                Some(ParentProblem::AncestryCycle)
+            } else {
+                lineages
+                    .get(&parent_id)
+                    .and_then(|parent| check_pair(member, parent, side))
+            };
+
+            if let Some(problem) = problem {
+                problems.push(InvalidParent {
+                    member_id: member.id,
+                    parent_id,
+                    side,
+                    problem,
+                });
+            }
+        }
+    }
+
+    problems.sort_by_key(|p| (p.member_id, p.parent_id));
+
+    problems
+}
+
+/// whether `member_id` can be reached by following parents up from `start`
+fn is_ancestor(lineages: &HashMap<i64, Lineage>, member_id: i64, start: i64) -> bool {
+    let mut visited = HashSet::new();
+    let mut stack = vec![start];
+
+    while let Some(id) = stack.pop() {
+        if id == member_id {
+            return true;
+        }
+
+        if !visited.insert(id) {
+            continue;
+        }
+
+        if let Some(lineage) = lineages.get(&id) {
+            stack.extend(lineage.parents().map(|(_, id)| id));
+        }
+    }
+
+    false
+}
+
+/// Check a single parent link, dates only count when they make the link impossible
+fn check_pair(child: &Lineage, parent: &Lineage, side: ParentSide) -> Option<ParentProblem> {
+    // the tree leaves out trashed members, and their children with them
+    if parent.deleted && !child.deleted {
+        return Some(ParentProblem::ParentDeleted);
+    }
+
+    if parent.gender != side.gender() {
+        return Some(ParentProblem::WrongGender);
+    }
+
+    let years = |years: u32| Months::new(years * 12);
+
+    let child_earliest = child.birthday.and_then(|b| b.earliest());
+    let child_latest = child.birthday.and_then(|b| b.latest());
+    let parent_earliest = parent.birthday.and_then(|b| b.earliest());
+    let parent_latest = parent.birthday.and_then(|b| b.latest());
+    let parent_died = parent.death_date.and_then(|d| d.latest());
+
+    if let (Some(child), Some(parent)) = (child_latest, parent_earliest) {
+        if parent
+            .checked_add_months(years(MIN_PARENT_AGE))
+            .is_some_and(|youngest| child < youngest)
+        {
+            return Some(ParentProblem::ParentTooYoung);
+        }
+    }
+
+    let max_age = match side {
+        ParentSide::Father => MAX_FATHER_AGE,
+        ParentSide::Mother => MAX_MOTHER_AGE,
+    };
+
+    if let (Some(child), Some(parent)) = (child_earliest, parent_latest) {
+        if parent
+            .checked_add_months(years(max_age))
+            .is_some_and(|oldest| child > oldest)
+        {
+            return Some(ParentProblem::ParentTooOld);
+        }
+    }
+
+    let grace = match side {
+        ParentSide::Father => Months::new(MAX_POSTHUMOUS_MONTHS),
+        ParentSide::Mother => Months::new(0),
+    };
+
+    if let (Some(child), Some(died)) = (child_earliest, parent_died) {
+        if died
+            .checked_add_months(grace)
+            .is_some_and(|last| child > last)
+        {
+            return Some(ParentProblem::ParentDiedBeforeBirth);
+        }
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lineages(links: &[(i64, Option<i64>)]) -> HashMap<i64, Lineage> {
+        links
+            .iter()
+            .map(|&(id, father_id)| {
+                let member = Lineage {
+                    id,
+                    father_id,
+                    ..lineage(Gender::Male, None, false)
+                };
+                (id, member)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn own_parent() {
+        let lineages = lineages(&[(1, Some(1))]);
+
+        let problems = find_problems(&lineages, &HashSet::from([1]));
+        assert_eq!(problems.len(), 1);
+        assert_eq!(problems[0].problem, ParentProblem::OwnParent);
+    }
+
+    #[test]
+    fn ancestry_cycle() {
+        // 3 is the grandchild of 1, and is made 1's father
+        let lineages = lineages(&[(1, Some(3)), (2, Some(1)), (3, Some(2))]);
+
+        assert!(is_ancestor(&lineages, 1, 3));
+
+        let problems = find_problems(&lineages, &HashSet::from([1]));
+        assert_eq!(problems.len(), 1);
+        assert_eq!((problems[0].member_id, problems[0].parent_id), (1, 3));
+        assert_eq!(problems[0].problem, ParentProblem::AncestryCycle);
+    }
+
+    #[test]
+    fn not_a_cycle() {
+        let lineages = lineages(&[(1, None), (2, Some(1)), (3, Some(2))]);
+
+        assert!(!is_ancestor(&lineages, 3, 1));
+        assert!(find_problems(&lineages, &HashSet::from([1, 2, 3])).is_empty());
+    }
+
+    fn lineage(gender: Gender, birthday: Option<&str>, deleted: bool) -> Lineage {
+        Lineage {
+            id: 0,
+            gender,
+            birthday: birthday.map(|b| b.parse().unwrap()),
+            death_date: None,
+            father_id: None,
+            mother_id: None,
+            deleted,
+        }
+    }
+
+    #[test]
+    fn trashed_parents() {
+        let child = lineage(Gender::Male, None, false);
+        let father = lineage(Gender::Male, None, true);
+
+        assert_eq!(
+            check_pair(&child, &father, ParentSide::Father),
+            Some(ParentProblem::ParentDeleted)
+        );
+
+        // both in the trash, e.g. a branch that was deleted together
+        let child = lineage(Gender::Male, None, true);
+        assert_eq!(check_pair(&child, &father, ParentSide::Father), None);
+    }
+
+    #[test]
+    fn parent_gender_and_age() {
+        let child = lineage(Gender::Female, Some("1950"), false);
+        let mother = lineage(Gender::Female, Some("1930"), false);
+
+        assert_eq!(check_pair(&child, &mother, ParentSide::Mother), None);
+        assert_eq!(
+            check_pair(&child, &mother, ParentSide::Father),
+            Some(ParentProblem::WrongGender)
+        );
+        assert_eq!(
+            check_pair(
+                &child,
+                &lineage(Gender::Female, Some("1945"), false),
+                ParentSide::Mother
+            ),
+            Some(ParentProblem::ParentTooYoung)
+        );
+        assert_eq!(
+            check_pair(
+                &child,
+                &lineage(Gender::Female, Some("1880"), false),
+                ParentSide::Mother
+            ),
+            Some(ParentProblem::ParentTooOld)
+        );
+        // a circa date could still be right
+        assert_eq!(
+            check_pair(
+                &child,
+                &lineage(Gender::Female, Some("circa 1945"), false),
+                ParentSide::Mother
+            ),
+            None
+        );
+    }
+}
@@ -40,7 +40,7 @@ pub enum UsersError {
 
 impl IntoResponse for UsersError {
     fn into_response(self) -> axum::response::Response {
-        log::error!("{:#?}", self);
+        log::error!("{self:#?}");
 
         match self {
             UsersError::UserNotFound => (
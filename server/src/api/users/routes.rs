@@ -47,7 +47,7 @@ WHERE sessions.id = $1
         .fetch_optional(&state.db_pool)
         .await?
         {
-            log::error!("user already logged in with session: {:?}", session);
+            log::error!("user already logged in with session: {session:?}");
             return Err(UsersError::AlreadyLoggedIn);
         }
     }
@@ -74,6 +74,11 @@ WHERE users.email = $1
         return Err(UsersError::UserNotFound);
     };
 
+    // users restored from a backup without password hashes have none
+    if user.password.is_empty() {
+        return Err(UsersError::InvalidCredentials);
+    }
+
     let parsed_password = PasswordHash::new(&user.password)?;
 
     if argon2
@@ -36,7 +36,7 @@ pub enum AuthError {
 
 impl IntoResponse for AuthError {
     fn into_response(self) -> axum::response::Response {
-        log::error!("{:#?}", self);
+        log::error!("{self:#?}");
 
         match self {
             AuthError::SomethingWentWrong => (StatusCode::INTERNAL_SERVER_ERROR).into_response(),
@@ -1,5 +1,6 @@
 use std::{path::PathBuf, sync::Arc};
 
+use api::members::nasab::NasabConfig;
 use axum::{
     extract::FromRef,
     http::StatusCode,
@@ -7,11 +8,13 @@ use axum::{
 };
 use serde::{Deserialize, Serialize};
 use sqlx::PgPool;
+use storage::{Storage, StorageConfig};
 use tower_cookies::Key;
 
 pub mod api;
 pub mod auth;
 pub mod pages;
+pub mod storage;
 
 #[derive(Serialize, Deserialize, Debug, Default)]
 pub struct ErrorResponse {
@@ -39,6 +42,12 @@ pub enum ConfigError {
 #[derive(Serialize, Deserialize)]
 pub struct Config {
     pub cookie_secret: String,
+    /// where uploaded images and documents are kept
+    #[serde(default)]
+    pub storage: StorageConfig,
+    /// how the full names of members are put together
+    #[serde(default)]
+    pub nasab: NasabConfig,
 }
 
 impl Config {
@@ -55,7 +64,7 @@ impl Config {
     }
 }
 
-#[derive(Debug, Clone, Copy, sqlx::Type, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, sqlx::Type, Serialize, Deserialize, PartialEq)]
 #[sqlx(type_name = "gender")]
 #[sqlx(rename_all = "snake_case")]
 #[serde(rename_all = "snake_case")]
@@ -125,6 +134,8 @@ pub struct Pagination {
 pub struct InnerAppState {
     pub db_pool: PgPool,
     pub cookies_secret: Key,
+    pub storage: Box<dyn Storage>,
+    pub nasab: NasabConfig,
 }
 
 #[derive(Clone, FromRef)]
@@ -3,16 +3,30 @@ use std::sync::Arc;
 use axum::{
     extract::DefaultBodyLimit,
     http::{HeaderValue, Method},
-    routing::{get, post, put},
+    routing::{delete, get, post, put},
     Router,
 };
 use rand::Rng;
 use server::{
     api::{
-        members::routes::{
-            add_member, approve_member_request, delete_member, disapprove_member_request,
-            edit_member, export_members, get_members, get_members_flat, request_add_member,
-            upload_members_csv,
+        backup::{
+            archive,
+            routes::{get_backup, restore_backup},
+        },
+        members::{
+            dates, images,
+            routes::{
+                add_marriage, add_member, add_member_media, approve_member_request,
+                delete_marriage, delete_member, delete_member_media, disapprove_member_request,
+                edit_marriage, edit_member, edit_member_media, export_gedcom, export_members,
+                get_history, get_member, get_member_ancestors, get_member_descendants,
+                get_member_history, get_member_image, get_member_media, get_member_media_file,
+                get_member_siblings, get_member_spouses, get_members, get_members_flat,
+                get_relationship, get_root_members, get_trash, get_unreachable_members,
+                import_gedcom, purge_member, request_add_member, restore_member, revert_member,
+                set_profile_media, upload_members_csv,
+            },
+            search,
         },
         sessions::refresh_session,
         users::routes::{login, logout, me},
@@ -23,9 +37,12 @@ use server::{
 
 use server::api::users::routes::create_user;
 
-use clap::Parser;
+use clap::{Parser, Subcommand};
 use sqlx::PgPool;
-use std::net::{Ipv4Addr, SocketAddrV4};
+use std::{
+    net::{Ipv4Addr, SocketAddrV4},
+    path::PathBuf,
+};
 use tower_cookies::{CookieManagerLayer, Key};
 use tower_http::{cors::CorsLayer, limit::RequestBodyLimitLayer, services::ServeDir};
 
@@ -35,6 +52,24 @@ struct Cli {
     /// Address to start server on
     #[arg(short, long)]
     address: Option<SocketAddrV4>,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Move the images that are still kept in the database to the configured storage
+    MoveImages,
+    /// Write a backup of the members, their images, add requests and users to a file
+    Backup {
+        path: PathBuf,
+        /// keep the password hashes of the users
+        #[arg(long)]
+        passwords: bool,
+    },
+    /// Replace everything in the database with a backup made by `backup`
+    Restore { path: PathBuf },
 }
 
 #[tokio::main]
@@ -58,6 +93,14 @@ async fn main() {
         panic!("Failed to migrate DB");
     }
 
+    if let Err(e) = dates::convert_stored_hijri_dates(&pool).await {
+        log::error!("Failed to convert the hijri dates: {e}");
+    }
+
+    if let Err(e) = search::fill_search_keys(&pool).await {
+        log::error!("Failed to make the search keys: {e}");
+    }
+
     let config = match Config::load_config() {
         Ok(config) => config,
         Err(err) => match &err {
@@ -71,6 +114,8 @@ async fn main() {
 
                 let config = Config {
                     cookie_secret: secret,
+                    storage: Default::default(),
+                    nasab: Default::default(),
                 };
 
                 let config_str =
@@ -82,15 +127,88 @@ async fn main() {
                 config
             }
             _ => {
-                panic!("{:#?}", err);
+                panic!("{err:#?}");
             }
         },
     };
 
+    let storage = config.storage.build();
+
+    match cli.command {
+        Some(Command::MoveImages) => {
+            match images::move_to_storage(&pool, storage.as_ref()).await {
+                Ok(moved) => log::info!("moved {moved} images to the storage"),
+                Err(e) => panic!("Failed to move images: {e:#?}"),
+            }
+
+            return;
+        }
+        Some(Command::Backup { path, passwords }) => {
+            let backup = match archive::create(&pool, passwords).await {
+                Ok(backup) => backup,
+                Err(e) => panic!("Failed to make a backup: {e:#?}"),
+            };
+
+            let file = tokio::fs::File::create(&path)
+                .await
+                .expect("creating the backup file");
+            let files = match archive::write(
+                &backup,
+                storage.as_ref(),
+                &mut tokio::io::BufWriter::new(file),
+            )
+            .await
+            {
+                Ok(files) => files,
+                Err(e) => panic!("Failed to write the backup: {e:#?}"),
+            };
+
+            log::info!(
+                "wrote {} members and {} files to {}",
+                backup.members.len(),
+                files,
+                path.display()
+            );
+
+            return;
+        }
+        Some(Command::Restore { path }) => {
+            let data = std::fs::read(&path).expect("reading the backup file");
+
+            let restored = match archive::parse(&data) {
+                Ok(backup) => archive::restore(&pool, storage.as_ref(), &backup, None).await,
+                Err(e) => Err(e),
+            };
+
+            match restored {
+                Ok(summary) => log::info!(
+                    "restored {} members and {} users from the backup made at {}",
+                    summary.members,
+                    summary.users,
+                    summary.created_at
+                ),
+                Err(e) => panic!("Failed to restore the backup: {e:#?}"),
+            }
+
+            return;
+        }
+        None => {}
+    }
+
+    match images::count_in_database(&pool).await {
+        Ok(0) => {}
+        Ok(count) => log::warn!(
+            "{count} images are still in the database and won't be served, run `server move-images`"
+        ),
+        Err(e) => log::error!("Failed to count images in the database: {e}"),
+    }
+
     let app_state = AppState {
         inner: Arc::new(InnerAppState {
             db_pool: pool,
             cookies_secret: Key::from(config.cookie_secret.as_bytes()),
+            storage,
+            nasab: config.nasab,
         }),
     };
 
@@ -100,11 +218,57 @@ async fn main() {
         .route("/register", get(register_page))
         .route("/add", get(add_request_page))
         .route("/api/members", get(get_members).post(add_member))
-        .route("/api/members/:id", put(edit_member).delete(delete_member))
+        .route(
+            "/api/members/:id",
+            get(get_member).put(edit_member).delete(delete_member),
+        )
+        .route("/api/members/:id/ancestors", get(get_member_ancestors))
+        .route("/api/members/:id/descendants", get(get_member_descendants))
+        .route("/api/members/:id/siblings", get(get_member_siblings))
+        .route("/api/members/:id/image", get(get_member_image))
+        .route(
+            "/api/members/:id/media",
+            get(get_member_media).post(add_member_media),
+        )
+        .route(
+            "/api/members/:id/media/:media_id",
+            get(get_member_media_file)
+                .put(edit_member_media)
+                .delete(delete_member_media),
+        )
+        .route(
+            "/api/members/:id/media/:media_id/profile",
+            post(set_profile_media),
+        )
+        .route("/api/members/:id/spouses", get(get_member_spouses))
+        .route(
+            "/api/members/:id/relationship/:other_id",
+            get(get_relationship),
+        )
+        .route("/api/members/:id/history", get(get_member_history))
+        .route(
+            "/api/members/:id/history/:change_id/revert",
+            post(revert_member),
+        )
+        .route("/api/members/history", get(get_history))
         .route("/api/members/flat", get(get_members_flat))
+        .route("/api/members/roots", get(get_root_members))
+        .route("/api/members/unreachable", get(get_unreachable_members))
+        .route("/api/members/trash", get(get_trash))
+        .route("/api/members/trash/:id", delete(purge_member))
+        .route("/api/members/trash/:id/restore", post(restore_member))
+        .route("/api/members/marriages", post(add_marriage))
+        .route(
+            "/api/members/marriages/:id",
+            put(edit_marriage).delete(delete_marriage),
+        )
         .route("/api/members/export", get(export_members))
+        .route("/api/members/export/gedcom", get(export_gedcom))
         .route("/api/members/import", post(upload_members_csv))
+        .route("/api/members/import/gedcom", post(import_gedcom))
         .route("/api/members/add-request", post(request_add_member))
+        .route("/api/backup", get(get_backup))
+        .route("/api/backup/restore", post(restore_backup))
         .route("/api/members/approve/:id", put(approve_member_request))
         .route(
             "/api/members/disapprove/:id",
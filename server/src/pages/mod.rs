@@ -1,4 +1,3 @@
-use crate::api::members::models::RequestStatus;
 use std::sync::Arc;
 
 use askama::Template;
@@ -12,8 +11,14 @@ use serde::Deserialize;
 use crate::{
     api::{
         members::{
-            models::{MemberResponseBrief, RequestedMemberResponseBrief},
-            routes::{get_members_flat, get_requested_members_flat, FlatMembersParams},
+            models::{
+                DeletedMemberResponse, MemberChangeResponse, MemberResponseBrief,
+                UnreachableMemberResponse,
+            },
+            routes::{
+                find_unreachable_members, get_member_changes, get_members_flat, get_trash_members,
+                FlatMembersParams, HistoryParams,
+            },
             MembersError,
         },
         users::models::UserRole,
@@ -23,15 +28,9 @@ use crate::{
 };
 
 mod filters {
-    use base64::Engine;
-
     pub fn deref_i64(s: &i64) -> ::askama::Result<i64> {
         Ok(*s)
     }
-
-    pub fn bytes_to_base64(bytes: &[u8]) -> ::askama::Result<String> {
-        Ok(base64::prelude::BASE64_STANDARD.encode(bytes))
-    }
 }
 
 #[derive(thiserror::Error, Debug)]
@@ -51,7 +50,7 @@ pub enum PagesError {
 
 impl IntoResponse for PagesError {
     fn into_response(self) -> axum::response::Response {
-        log::error!("{:#?}", self);
+        log::error!("{self:#?}");
 
         match self {
             PagesError::Auth(e) => e.into_response(),
@@ -75,20 +74,17 @@ pub struct SomethingWentWrongTemplate;
 pub struct AdminTemplate {
     name: String,
     members: Vec<MemberResponseBrief>,
-    add_requests: Vec<RequestedMemberResponseBrief>,
-    members_query: Option<String>,
-    requests_query: Option<String>,
+    unreachable: Vec<UnreachableMemberResponse>,
+    trash: Vec<DeletedMemberResponse>,
+    history: Vec<MemberChangeResponse>,
 }
 
 serde_with::with_prefix!(prefix_members "members_");
-serde_with::with_prefix!(prefix_requests "requests_");
 
 #[derive(Deserialize)]
 pub struct AdminParams {
     #[serde(flatten, with = "prefix_members")]
     members_params: FlatMembersParams,
-    #[serde(flatten, with = "prefix_requests")]
-    requests_params: FlatMembersParams,
 }
 
 pub async fn admin_page(
@@ -98,22 +94,29 @@ pub async fn admin_page(
 ) -> Result<impl IntoResponse, PagesError> {
     match auth {
         Ok(auth) => {
-            let members_query = params.0.members_params.query.clone();
             let Json(members) =
                 match get_members_flat(state.clone(), Query(params.0.members_params)).await {
                     Ok(members) => members,
                     Err(MembersError::NoMembers) => Vec::new().into(),
                     Err(e) => return Err(e.into()),
                 };
-            let requests_query = params.0.requests_params.query.clone();
-            let Json(add_requests) =
-                get_requested_members_flat(state, Query(params.0.requests_params)).await?;
+            let unreachable = find_unreachable_members(&state.db_pool, None).await?;
+            let trash = get_trash_members(&state.db_pool).await?;
+            let history = get_member_changes(
+                &state.db_pool,
+                None,
+                &HistoryParams {
+                    page: None,
+                    per_page: Some(20),
+                },
+            )
+            .await?;
             Ok(AdminTemplate {
                 name: auth.current_user.username,
                 members,
-                add_requests,
-                members_query,
-                requests_query,
+                unreachable,
+                trash,
+                history,
             }
             .into_response())
         }
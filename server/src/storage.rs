@@ -0,0 +1,127 @@
+use std::path::{Path, PathBuf};
+
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+#[derive(thiserror::Error, Debug)]
+pub enum StorageError {
+    #[error("file {0} not found")]
+    NotFound(String),
+
+    #[error("invalid file key {0}")]
+    InvalidKey(String),
+
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+}
+
+/// Where images and documents are kept
+///
+/// files are addressed by the sha256 of their content, so storing the same file twice
+/// keeps one copy and a key always points to the same content
+#[async_trait]
+pub trait Storage: Send + Sync {
+    /// Store a file and return its key
+    async fn put(&self, content: &[u8]) -> Result<String, StorageError>;
+
+    async fn get(&self, key: &str) -> Result<Vec<u8>, StorageError>;
+
+    /// Remove a file, removing a missing file is not an error
+    async fn delete(&self, key: &str) -> Result<(), StorageError>;
+}
+
+/// The key of a file, the same as `encode(sha256(content), 'hex')` in postgres
+pub fn content_key(content: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(content))
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "backend", rename_all = "snake_case")]
+pub enum StorageConfig {
+    /// Keep files in a directory on the server
+    Local { path: PathBuf },
+}
+
+impl Default for StorageConfig {
+    fn default() -> Self {
+        StorageConfig::Local {
+            path: PathBuf::from("media"),
+        }
+    }
+}
+
+impl StorageConfig {
+    pub fn build(&self) -> Box<dyn Storage> {
+        match self {
+            StorageConfig::Local { path } => Box::new(LocalStorage::new(path)),
+        }
+    }
+}
+
+/// Files in a local directory, spread over sub directories named after
+/// the first two characters of their key so no directory gets too big
+pub struct LocalStorage {
+    root: PathBuf,
+}
+
+impl LocalStorage {
+    pub fn new(root: impl AsRef<Path>) -> Self {
+        Self {
+            root: root.as_ref().to_path_buf(),
+        }
+    }
+
+    fn path(&self, key: &str) -> Result<PathBuf, StorageError> {
+        // keys are only ever sha256 hashes, anything else could point outside the root
+        if key.len() != 64 || !key.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return Err(StorageError::InvalidKey(key.to_string()));
+        }
+
+        Ok(self.root.join(&key[..2]).join(key))
+    }
+}
+
+#[async_trait]
+impl Storage for LocalStorage {
+    async fn put(&self, content: &[u8]) -> Result<String, StorageError> {
+        let key = content_key(content);
+        let path = self.path(&key)?;
+
+        if tokio::fs::try_exists(&path).await? {
+            return Ok(key);
+        }
+
+        if let Some(dir) = path.parent() {
+            tokio::fs::create_dir_all(dir).await?;
+        }
+
+        // written next to the file and renamed so a half written file is never read
+        let temp_path = path.with_extension(format!("{}.tmp", uuid::Uuid::new_v4()));
+        tokio::fs::write(&temp_path, content).await?;
+
+        if let Err(e) = tokio::fs::rename(&temp_path, &path).await {
+            let _ = tokio::fs::remove_file(&temp_path).await;
+            return Err(e.into());
+        }
+
+        Ok(key)
+    }
+
+    async fn get(&self, key: &str) -> Result<Vec<u8>, StorageError> {
+        match tokio::fs::read(self.path(key)?).await {
+            Ok(content) => Ok(content),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                Err(StorageError::NotFound(key.to_string()))
+            }
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    async fn delete(&self, key: &str) -> Result<(), StorageError> {
+        match tokio::fs::remove_file(self.path(key)?).await {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        }
+    }
+}
@@ -2,13 +2,19 @@ use std::sync::mpsc::{self, Receiver, Sender};
 
 use eframe::egui;
 
-use crate::{Message, load_family_data, setup_fonts, tree::TreeUi};
+use crate::{
+    Message, RootMember, load_family_data, load_roots, setup_fonts,
+    tree::{Calendar, TreeUi, draw::shape_text, gallery},
+};
 
 pub struct App {
     tree: TreeUi,
     message_receiver: Receiver<Message>,
     message_sender: Sender<Message>,
     backend_address: String,
+    roots: Vec<RootMember>,
+    /// the root the tree is drawn from, `None` lets the server pick it
+    selected_root: Option<i32>,
 }
 
 impl App {
@@ -23,18 +29,23 @@ impl App {
         let (sender, receiver) = mpsc::channel();
 
         #[cfg(not(target_arch = "wasm32"))]
-        let address = "http://localhost:3001";
+        let address = String::from("http://localhost:3001");
 
+        // the page is served by the backend, image urls still need the full address
         #[cfg(target_arch = "wasm32")]
-        let address = "";
+        let address = cc.integration_info.web_info.location.origin.clone();
 
-        load_family_data(address, sender.clone(), &cc.egui_ctx);
+        load_family_data(&address, None, sender.clone(), &cc.egui_ctx);
+        load_roots(&address, sender.clone(), &cc.egui_ctx);
+        gallery::set_backend_address(&cc.egui_ctx, &address);
 
         Self {
             tree: TreeUi::new(None),
             message_sender: sender.clone(),
             message_receiver: receiver,
-            backend_address: address.to_string(),
+            backend_address: address,
+            roots: Vec::new(),
+            selected_root: None,
         }
     }
 }
@@ -63,26 +74,90 @@ impl eframe::App for App {
                 let reload = ui.button("⟳").on_hover_text("Refresh tree");
 
                 if reload.clicked() {
-                    load_family_data(&self.backend_address, self.message_sender.clone(), ctx);
+                    load_family_data(
+                        &self.backend_address,
+                        self.selected_root,
+                        self.message_sender.clone(),
+                        ctx,
+                    );
+                    load_roots(&self.backend_address, self.message_sender.clone(), ctx);
                     self.tree.request_recenter();
                 }
 
+                if self.roots.len() > 1 {
+                    let selected_text = self
+                        .roots
+                        .iter()
+                        .find(|r| Some(r.id) == self.selected_root.or(self.tree.root_id()))
+                        .map(RootMember::label)
+                        .unwrap_or_default();
+
+                    let previous_root = self.selected_root;
+
+                    egui::ComboBox::from_id_salt("root_member")
+                        .selected_text(shape_text(&selected_text))
+                        .show_ui(ui, |ui| {
+                            for root in &self.roots {
+                                ui.selectable_value(
+                                    &mut self.selected_root,
+                                    Some(root.id),
+                                    shape_text(&root.label()),
+                                );
+                            }
+                        })
+                        .response
+                        .on_hover_text("Family root");
+
+                    if previous_root != self.selected_root {
+                        load_family_data(
+                            &self.backend_address,
+                            self.selected_root,
+                            self.message_sender.clone(),
+                            ctx,
+                        );
+                        self.tree.request_recenter();
+                    }
+                }
+
+                let mut calendar = Calendar::preferred(ctx);
+
+                egui::ComboBox::from_id_salt("calendar")
+                    .selected_text(shape_text(calendar.label()))
+                    .show_ui(ui, |ui| {
+                        for option in [Calendar::Gregorian, Calendar::Hijri] {
+                            ui.selectable_value(&mut calendar, option, shape_text(option.label()));
+                        }
+                    })
+                    .response
+                    .on_hover_text("Calendar");
+
+                Calendar::set_preferred(ctx, calendar);
+
                 let is_debug = cfg!(debug_assertions);
 
                 if is_debug {
                     let label = ui.label("backend address:");
-                    egui::TextEdit::singleline(&mut self.backend_address)
+                    let response = egui::TextEdit::singleline(&mut self.backend_address)
                         .hint_text("http://localhost:3001")
                         .show(ui)
                         .response
                         .labelled_by(label.id);
+
+                    if response.changed() {
+                        gallery::set_backend_address(ctx, &self.backend_address);
+                    }
                 }
             });
         });
 
         egui::CentralPanel::default().show(ctx, |ui| {
             if ui.input(|i| i.key_pressed(egui::Key::F5)) {
-                load_family_data(&self.backend_address, self.message_sender.clone(), ctx);
+                load_family_data(
+                    &self.backend_address,
+                    self.selected_root,
+                    self.message_sender.clone(),
+                    ctx,
+                );
             }
             self.tree.draw(ui);
         });
@@ -91,11 +166,16 @@ impl eframe::App for App {
             log::debug!("got {message:?}");
             match message {
                 Message::LoadedFamilyData(root_node) => {
-                    self.tree.set_root(Some(root_node));
+                    // the galleries may have changed since the tree was loaded
+                    gallery::clear(ctx);
+                    self.tree.set_root(Some(*root_node));
                     log::debug!("set the root");
                     self.tree.layout();
                     log::debug!("laid out the tree");
                 }
+                Message::LoadedRoots(roots) => {
+                    self.roots = roots;
+                }
             }
         }
     }
@@ -18,9 +18,28 @@ pub enum Gender {
     Female,
 }
 
+/// A member with no parents that a family tree can be drawn from
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RootMember {
+    pub id: i32,
+    name: String,
+    last_name: String,
+    descendants_count: i64,
+}
+
+impl RootMember {
+    fn label(&self) -> String {
+        format!(
+            "{} {} ({})",
+            self.name, self.last_name, self.descendants_count
+        )
+    }
+}
+
 #[derive(Debug)]
 enum Message {
-    LoadedFamilyData(Node),
+    LoadedFamilyData(Box<Node>),
+    LoadedRoots(Vec<RootMember>),
 }
 
 const FONT: &[u8] = include_bytes!("../fonts/arial.ttf");
@@ -48,9 +67,19 @@ fn setup_fonts(ctx: &egui::Context) {
     ctx.set_fonts(fonts);
 }
 
-fn load_family_data(address: &str, sender: Sender<Message>, ctx: &egui::Context) {
+fn load_family_data(
+    address: &str,
+    root: Option<i32>,
+    sender: Sender<Message>,
+    ctx: &egui::Context,
+) {
     let ctx = ctx.clone();
-    let request = ehttp::Request::get(format!("{address}/api/members"));
+    let url = match root {
+        Some(root) => format!("{address}/api/members?root={root}"),
+        None => format!("{address}/api/members"),
+    };
+    let address = address.to_string();
+    let request = ehttp::Request::get(url);
     ehttp::fetch(request, move |res| match res {
         Ok(res) => {
             if !res.ok {
@@ -59,8 +88,9 @@ fn load_family_data(address: &str, sender: Sender<Message>, ctx: &egui::Context)
             }
 
             match res.json::<Node>() {
-                Ok(node) => {
-                    let _ = sender.send(Message::LoadedFamilyData(node));
+                Ok(mut node) => {
+                    node.resolve_image_urls(&address);
+                    let _ = sender.send(Message::LoadedFamilyData(Box::new(node)));
                     log::info!("Received family data successfully");
                     ctx.request_repaint();
                 }
@@ -74,3 +104,29 @@ fn load_family_data(address: &str, sender: Sender<Message>, ctx: &egui::Context)
         }
     });
 }
+
+fn load_roots(address: &str, sender: Sender<Message>, ctx: &egui::Context) {
+    let ctx = ctx.clone();
+    let request = ehttp::Request::get(format!("{address}/api/members/roots"));
+    ehttp::fetch(request, move |res| match res {
+        Ok(res) => {
+            if !res.ok {
+                log::error!("{res:?}");
+                return;
+            }
+
+            match res.json::<Vec<RootMember>>() {
+                Ok(roots) => {
+                    let _ = sender.send(Message::LoadedRoots(roots));
+                    ctx.request_repaint();
+                }
+                Err(e) => {
+                    log::error!("failed to fetch family roots: {e}");
+                }
+            }
+        }
+        Err(e) => {
+            log::error!("failed to fetch family roots: {e}");
+        }
+    });
+}
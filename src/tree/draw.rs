@@ -9,12 +9,13 @@ use egui::{
 };
 use unicode_bidi::BidiInfo;
 
+use crate::Gender;
 use crate::zoom::Zoom;
 
 #[cfg(feature = "debug-ui")]
 use egui::StrokeKind;
 
-use super::{DEFAULT_IMAGE, NODE_RADIUS, Node, SimpleNode, TreeUi, layout::LayoutTree};
+use super::{Calendar, MarriageEndReason, NODE_RADIUS, Node, TreeUi, gallery, layout::LayoutTree};
 
 const MAX_SCALE: f32 = 5.0;
 const MIN_SCALE: f32 = 0.2;
@@ -87,7 +88,6 @@ impl TreeUi {
                 &mut self.offset,
                 self.scale,
                 &mut self.layout_tree,
-                vec![],
                 background_clicked,
             );
         }
@@ -101,7 +101,6 @@ impl Node {
         offset: &mut Vec2,
         scale: f32,
         layout_tree: &mut LayoutTree,
-        mut lineage: Vec<SimpleNode>,
         background_clicked: bool,
     ) {
         let stroke = ui.visuals().widgets.noninteractive.fg_stroke;
@@ -162,6 +161,21 @@ impl Node {
                 ..Default::default()
             },
         );
+        let calendar = Calendar::preferred(ui.ctx());
+        let lifespan = self.lifespan(calendar);
+        let birthday = self.birthday(calendar).cloned();
+        let death_date = self.death_date(calendar).cloned();
+        if let Some(lifespan) = &lifespan {
+            job.append(
+                &format!("\n{}", shape_text(lifespan)),
+                0.0,
+                TextFormat {
+                    font_id: FontId::new(16.0 * scale, FontFamily::Monospace),
+                    color: ui.visuals().weak_text_color(),
+                    ..Default::default()
+                },
+            );
+        }
         let galley = painter.layout_job(job);
 
         #[cfg(feature = "debug-ui")]
@@ -230,6 +244,8 @@ impl Node {
             self.window_is_open = false;
         }
 
+        let image = self.medium_image();
+
         egui::Window::new(self.id.to_string())
             .id(egui::Id::new(self.id))
             .max_width(180.)
@@ -245,15 +261,6 @@ impl Node {
             .current_pos(window_pos)
             .show(ui.ctx(), |ui| {
                 ui.with_layout(egui::Layout::top_down(Align::RIGHT), |ui| {
-                    let image = self
-                        .image
-                        .as_ref()
-                        .map(|i| egui::ImageSource::Bytes {
-                            uri: format!("{}-{}", self.id, self.name).into(),
-                            bytes: egui::load::Bytes::from(i.clone()),
-                        })
-                        .unwrap_or(DEFAULT_IMAGE);
-
                     egui::Image::new(image)
                         .maintain_aspect_ratio(true)
                         .show_loading_spinner(true)
@@ -272,16 +279,54 @@ impl Node {
 
                     ui.label(self.id.to_string());
 
-                    let lineage = lineage
-                        .iter()
-                        .rev()
-                        .map(|l| format!("{} ", l.name.clone()))
-                        .take(2)
-                        .collect::<String>();
-                    ui.heading(shape_text(&format!(
-                        "{} {}{}",
-                        self.name, lineage, self.last_name
-                    )));
+                    match &self.nasab {
+                        Some(nasab) => {
+                            ui.heading(shape_text(&nasab.arabic));
+                            ui.label(&nasab.latin);
+                        }
+                        None => {
+                            ui.heading(shape_text(&format!("{} {}", self.name, self.last_name)));
+                        }
+                    }
+
+                    if let Some(lifespan) = &lifespan {
+                        ui.label(shape_text(lifespan));
+                    }
+
+                    if let Some(birthday) = &birthday {
+                        ui.label(shape_text(&format!("تاريخ الميلاد: {birthday}")));
+                    }
+
+                    if let Some(death_date) = &death_date {
+                        ui.label(shape_text(&format!("تاريخ الوفاة: {death_date}")));
+                    }
+
+                    if let Some(burial_place) = &self.burial_place {
+                        ui.label(shape_text(&format!("مكان الدفن: {burial_place}")));
+                    }
+
+                    if !self.spouses.is_empty() {
+                        ui.add_space(10.);
+                        ui.label(shape_text(match self.gender {
+                            Gender::Male => "الزوجات:",
+                            Gender::Female => "الأزواج:",
+                        }));
+
+                        for spouse in &self.spouses {
+                            let status = match spouse.end_reason {
+                                Some(MarriageEndReason::Divorce) => " (انفصال)",
+                                Some(MarriageEndReason::Death) => match spouse.gender {
+                                    Gender::Male => " (متوفى)",
+                                    Gender::Female => " (متوفاة)",
+                                },
+                                None => "",
+                            };
+                            ui.label(shape_text(&format!(
+                                "{} {}{status}",
+                                spouse.name, spouse.last_name
+                            )));
+                        }
+                    }
 
                     if let Some(personal_info) = self.personal_info.as_ref() {
                         if !personal_info.is_empty() {
@@ -298,21 +343,14 @@ impl Node {
                             }
                         }
                     }
+
+                    gallery::show(ui, self.id);
                 });
             });
 
-        lineage.push(self.clone().into());
-
         if !self.collapsed {
             for child in self.children.iter_mut() {
-                child.draw(
-                    ui,
-                    offset,
-                    scale,
-                    layout_tree,
-                    lineage.clone(),
-                    background_clicked,
-                );
+                child.draw(ui, offset, scale, layout_tree, background_clicked);
             }
         }
 
@@ -371,20 +409,14 @@ impl Node {
             StrokeKind::Middle,
         );
 
-        let image = self
-            .image
-            .as_ref()
-            .map(|i| egui::ImageSource::Bytes {
-                uri: format!("{}-{}", self.id, self.name).into(),
-                bytes: egui::load::Bytes::from(i.clone()),
-            })
-            .unwrap_or(DEFAULT_IMAGE);
-
-        egui::Image::new(image)
-            .corner_radius(CornerRadius::same(NODE_RADIUS) * scale)
-            .maintain_aspect_ratio(true)
-            .show_loading_spinner(true)
-            .paint_at(ui, image_rect);
+        // images are only requested once their node is on screen
+        if ui.is_rect_visible(image_rect) {
+            egui::Image::new(self.thumbnail())
+                .corner_radius(CornerRadius::same(NODE_RADIUS) * scale)
+                .maintain_aspect_ratio(true)
+                .show_loading_spinner(true)
+                .paint_at(ui, image_rect);
+        }
 
         if response.hovered() {
             let painter = ui.painter();
@@ -407,7 +439,7 @@ impl Node {
     }
 }
 
-fn shape_text(input: &str) -> String {
+pub(crate) fn shape_text(input: &str) -> String {
     let mut output = String::new();
     if input.is_empty() {
         return output;
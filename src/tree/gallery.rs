@@ -0,0 +1,171 @@
+use eframe::egui;
+
+use egui::{Sense, Widget};
+use serde::Deserialize;
+
+use super::{Calendar, PartialDate, draw::shape_text};
+
+const THUMBNAIL_WIDTH: f32 = 160.;
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum MediaKind {
+    Photo,
+    Document,
+}
+
+/// A photo or a scanned document of a member, as sent by the server
+#[derive(Debug, Clone, Deserialize)]
+struct Media {
+    kind: MediaKind,
+    caption: Option<String>,
+    date: Option<PartialDate>,
+    date_hijri: Option<PartialDate>,
+    file_name: Option<String>,
+    url: String,
+    thumbnail_url: Option<String>,
+    is_profile: bool,
+}
+
+impl Media {
+    fn date(&self, calendar: Calendar) -> Option<&PartialDate> {
+        match calendar {
+            Calendar::Gregorian => self.date.as_ref(),
+            Calendar::Hijri => self.date_hijri.as_ref(),
+        }
+    }
+}
+
+/// The gallery of a member, kept in the context's temp data so it's only fetched
+/// the first time the info window is opened
+#[derive(Debug, Clone)]
+enum Gallery {
+    Loading,
+    Loaded(Vec<Media>),
+    Failed,
+}
+
+fn gallery_id(member_id: i32) -> egui::Id {
+    egui::Id::new(("member_gallery", member_id))
+}
+
+fn address_id() -> egui::Id {
+    egui::Id::new("backend_address")
+}
+
+/// the address galleries are fetched from, the urls of the media are relative to it
+pub fn set_backend_address(ctx: &egui::Context, address: &str) {
+    ctx.data_mut(|d| d.insert_temp(address_id(), address.trim_end_matches('/').to_string()));
+}
+
+/// forget the loaded galleries so they're fetched again
+pub fn clear(ctx: &egui::Context) {
+    ctx.data_mut(|d| d.remove_by_type::<Gallery>());
+}
+
+fn load_gallery(ctx: &egui::Context, member_id: i32) {
+    let address = ctx
+        .data(|d| d.get_temp::<String>(address_id()))
+        .unwrap_or_default();
+
+    ctx.data_mut(|d| d.insert_temp(gallery_id(member_id), Gallery::Loading));
+
+    let ctx = ctx.clone();
+    let request = ehttp::Request::get(format!("{address}/api/members/{member_id}/media"));
+    ehttp::fetch(request, move |res| {
+        let gallery = match res {
+            Ok(res) if res.ok => match res.json::<Vec<Media>>() {
+                Ok(mut media) => {
+                    for m in &mut media {
+                        m.url.insert_str(0, &address);
+                        if let Some(url) = &mut m.thumbnail_url {
+                            url.insert_str(0, &address);
+                        }
+                    }
+                    Gallery::Loaded(media)
+                }
+                Err(e) => {
+                    log::error!("failed to fetch member gallery: {e}");
+                    Gallery::Failed
+                }
+            },
+            Ok(res) => {
+                log::error!("{res:?}");
+                Gallery::Failed
+            }
+            Err(e) => {
+                log::error!("failed to fetch member gallery: {e}");
+                Gallery::Failed
+            }
+        };
+
+        ctx.data_mut(|d| d.insert_temp(gallery_id(member_id), gallery));
+        ctx.request_repaint();
+    });
+}
+
+/// Show the photos and documents of a member in their info window,
+/// clicking a photo or a document opens the original file
+pub fn show(ui: &mut egui::Ui, member_id: i32) {
+    let Some(gallery) = ui.data(|d| d.get_temp::<Gallery>(gallery_id(member_id))) else {
+        load_gallery(ui.ctx(), member_id);
+        return;
+    };
+
+    let media = match gallery {
+        Gallery::Loading => {
+            ui.spinner();
+            return;
+        }
+        Gallery::Failed => return,
+        Gallery::Loaded(media) => media,
+    };
+
+    if media.is_empty() {
+        return;
+    }
+
+    let calendar = Calendar::preferred(ui.ctx());
+
+    ui.add_space(10.);
+    ui.label(shape_text("الصور والمستندات:"));
+
+    for m in &media {
+        ui.add_space(5.);
+
+        match (&m.thumbnail_url, m.kind) {
+            (Some(thumbnail_url), _) => {
+                let response = egui::Image::new(thumbnail_url.clone())
+                    .max_width(THUMBNAIL_WIDTH)
+                    .maintain_aspect_ratio(true)
+                    .show_loading_spinner(true)
+                    .sense(Sense::click())
+                    .ui(ui);
+
+                if response.clicked() {
+                    ui.ctx().open_url(egui::OpenUrl::new_tab(&m.url));
+                }
+            }
+            (None, kind) => {
+                let name = match (&m.file_name, kind) {
+                    (Some(file_name), _) => file_name.clone(),
+                    (None, MediaKind::Document) => String::from("مستند"),
+                    (None, MediaKind::Photo) => String::from("صورة"),
+                };
+                ui.hyperlink_to(shape_text(&format!("📄 {name}")), &m.url);
+            }
+        }
+
+        if m.is_profile {
+            ui.label(shape_text("الصورة الشخصية"));
+        }
+
+        if let Some(caption) = &m.caption {
+            ui.label(shape_text(caption));
+        }
+
+        if let Some(date) = m.date(calendar) {
+            ui.label(shape_text(&date.to_string()));
+        }
+    }
+}
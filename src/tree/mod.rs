@@ -11,6 +11,7 @@ use serde::{Deserialize, Serialize};
 use crate::Gender;
 
 pub mod draw;
+pub mod gallery;
 pub mod layout;
 
 const DEFAULT_IMAGE: egui::ImageSource<'static> = include_image!("../../assets/avatar.png");
@@ -44,6 +45,10 @@ impl TreeUi {
         self.layout_tree.set_root(self.root.clone());
     }
 
+    pub fn root_id(&self) -> Option<i32> {
+        self.root.as_ref().map(|r| r.id)
+    }
+
     pub fn layout(&mut self) {
         self.layout_tree.layout();
     }
@@ -84,13 +89,28 @@ pub struct Node {
     pub id: i32,
     name: String,
     gender: Gender,
-    birthday: Option<DateTime<Utc>>,
+    birthday: Option<PartialDate>,
+    #[serde(default)]
+    birthday_hijri: Option<PartialDate>,
+    #[serde(default)]
+    death_date: Option<PartialDate>,
+    #[serde(default)]
+    death_date_hijri: Option<PartialDate>,
+    #[serde(default)]
+    deceased: bool,
+    #[serde(default)]
+    burial_place: Option<String>,
     last_name: String,
     father_id: Option<i32>,
     mother_id: Option<i32>,
     pub personal_info: Option<IndexMap<String, String>>,
+    #[serde(default)]
+    spouses: Vec<Spouse>,
     pub children: Vec<Node>,
-    image: Option<Vec<u8>>,
+    #[serde(default)]
+    images: Option<ImageUrls>,
+    #[serde(default)]
+    nasab: Option<Nasab>,
 
     /// used for displaying or hiding the member info window
     #[serde(skip)]
@@ -100,41 +120,197 @@ pub struct Node {
     collapsed: bool,
 }
 
-impl Node {
-    // pub fn add_child(&mut self, child: Node) {
-    //     self.children.push(child);
-    // }
+/// The calendar dates are shown in
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum Calendar {
+    #[default]
+    Gregorian,
+    Hijri,
+}
+
+impl Calendar {
+    fn id() -> egui::Id {
+        egui::Id::new("preferred_calendar")
+    }
+
+    pub fn preferred(ctx: &egui::Context) -> Self {
+        ctx.data(|d| d.get_temp(Self::id())).unwrap_or_default()
+    }
+
+    pub fn set_preferred(ctx: &egui::Context, calendar: Self) {
+        ctx.data_mut(|d| d.insert_temp(Self::id(), calendar));
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Calendar::Gregorian => "ميلادي",
+            Calendar::Hijri => "هجري",
+        }
+    }
+}
+
+/// A date that may only be partially known, as sent by the server,
+/// e.g. "1932-05-02", "1932-05", "1932", "circa 1932", "before 1932" or "after 1932",
+/// hijri dates end with "AH"
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct PartialDate(String);
+
+impl PartialDate {
+    fn is_hijri(&self) -> bool {
+        self.0.ends_with(" AH")
+    }
+
+    /// splits the qualifier from the date, the qualifier is returned in arabic
+    fn split(&self) -> (Option<&'static str>, &str) {
+        [("circa ", "حوالي"), ("before ", "قبل"), ("after ", "بعد")]
+            .into_iter()
+            .find_map(|(prefix, arabic)| {
+                self.0.strip_prefix(prefix).map(|date| (Some(arabic), date))
+            })
+            .map(|(qualifier, date)| (qualifier, date.trim_end_matches(" AH")))
+            .unwrap_or((None, self.0.trim_end_matches(" AH")))
+    }
+
+    /// the year with a short marker for approximate dates, e.g. "~1932"
+    pub fn year(&self) -> String {
+        let marker = match self.0.split_once(' ') {
+            Some(("circa", _)) => "~",
+            Some(("before", _)) => "<",
+            Some(("after", _)) => ">",
+            _ => "",
+        };
+        let (_, date) = self.split();
+        let year = date
+            .split('-')
+            .next()
+            .unwrap_or(date)
+            .trim_start_matches('0');
+
+        format!("{marker}{year}")
+    }
+}
+
+impl std::fmt::Display for PartialDate {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.split() {
+            (Some(qualifier), date) => write!(f, "{qualifier} {date}")?,
+            (None, date) => write!(f, "{date}")?,
+        }
+
+        if self.is_hijri() {
+            write!(f, " هـ")?;
+        }
+
+        Ok(())
+    }
 }
 
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MarriageEndReason {
+    Divorce,
+    Death,
+}
+
+/// The full name of a member with their fathers' names, as the backend puts it together
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Nasab {
+    arabic: String,
+    latin: String,
+}
+
+/// The sizes a member's image is served in,
+/// relative to the backend until [`Node::resolve_image_urls`] is called
 #[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct SimpleNode {
+pub struct ImageUrls {
+    /// a small square for the node
+    thumbnail: String,
+    /// for the info window
+    medium: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Spouse {
     pub id: i32,
     name: String,
-    gender: Gender,
-    birthday: Option<DateTime<Utc>>,
     last_name: String,
+    gender: Gender,
+    married_at: Option<DateTime<Utc>>,
+    ended_at: Option<DateTime<Utc>>,
+    end_reason: Option<MarriageEndReason>,
 }
 
-impl From<Node> for SimpleNode {
-    fn from(value: Node) -> Self {
-        Self {
-            id: value.id,
-            name: value.name,
-            gender: value.gender,
-            birthday: value.birthday,
-            last_name: value.last_name,
+impl Node {
+    pub fn birthday(&self, calendar: Calendar) -> Option<&PartialDate> {
+        match calendar {
+            Calendar::Gregorian => self.birthday.as_ref(),
+            Calendar::Hijri => self.birthday_hijri.as_ref(),
         }
     }
-}
 
-impl From<&Node> for SimpleNode {
-    fn from(value: &Node) -> Self {
-        Self {
-            id: value.id,
-            name: value.name.clone(),
-            gender: value.gender,
-            birthday: value.birthday,
-            last_name: value.last_name.clone(),
+    pub fn death_date(&self, calendar: Calendar) -> Option<&PartialDate> {
+        match calendar {
+            Calendar::Gregorian => self.death_date.as_ref(),
+            Calendar::Hijri => self.death_date_hijri.as_ref(),
         }
     }
+
+    /// the years the member lived, e.g. "1932–2001",
+    /// only shown for deceased members
+    pub fn lifespan(&self, calendar: Calendar) -> Option<String> {
+        if !self.deceased {
+            return None;
+        }
+
+        let year = |date: Option<&PartialDate>| {
+            date.map(PartialDate::year)
+                .unwrap_or_else(|| String::from("?"))
+        };
+
+        let lifespan = format!(
+            "{}–{}",
+            year(self.birthday(calendar)),
+            year(self.death_date(calendar))
+        );
+
+        Some(match calendar {
+            Calendar::Gregorian => lifespan,
+            Calendar::Hijri => format!("{lifespan} هـ"),
+        })
+    }
+
+    /// prefix the image urls of the member and their descendants with the backend address,
+    /// the image loaders only load absolute urls
+    pub fn resolve_image_urls(&mut self, address: &str) {
+        if let Some(images) = &mut self.images {
+            for url in [&mut images.thumbnail, &mut images.medium] {
+                url.insert_str(0, address.trim_end_matches('/'));
+            }
+        }
+
+        for child in &mut self.children {
+            child.resolve_image_urls(address);
+        }
+    }
+
+    /// the member's image for the node, loaded from the backend the first time it's drawn
+    fn thumbnail(&self) -> egui::ImageSource<'static> {
+        self.images
+            .as_ref()
+            .map(|images| egui::ImageSource::Uri(images.thumbnail.clone().into()))
+            .unwrap_or(DEFAULT_IMAGE)
+    }
+
+    /// the member's image for the info window
+    fn medium_image(&self) -> egui::ImageSource<'static> {
+        self.images
+            .as_ref()
+            .map(|images| egui::ImageSource::Uri(images.medium.clone().into()))
+            .unwrap_or(DEFAULT_IMAGE)
+    }
+
+    // pub fn add_child(&mut self, child: Node) {
+    //     self.children.push(child);
+    // }
 }